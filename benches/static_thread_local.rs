@@ -0,0 +1,72 @@
+//! Compares `StaticThreadLocal` accessors with a call through a `fn` pointer,
+//! which is what every access used to cost.
+//!
+//! Run with `cargo bench`. `get` and `set` should be a single `mov` through
+//! `gs` and come out well ahead of the `fn` pointer versions.
+#![feature(asm, test)]
+
+extern crate test;
+
+use test::{black_box, Bencher};
+
+wintls::static_thread_local! {
+	static COUNTER: u64 = 0;
+}
+
+std::thread_local! {
+	static STD_COUNTER: core::cell::Cell<u64> = const { core::cell::Cell::new(0) };
+}
+
+fn get_counter() -> u64 {
+	COUNTER.get()
+}
+fn set_counter(value: u64) {
+	COUNTER.set(value)
+}
+
+#[bench]
+fn get(b: &mut Bencher) {
+	b.iter(|| {
+		for _ in 0..1000 {
+			black_box(COUNTER.get());
+		}
+	});
+}
+
+#[bench]
+fn set(b: &mut Bencher) {
+	b.iter(|| {
+		for i in 0..1000 {
+			COUNTER.set(black_box(i));
+		}
+	});
+}
+
+#[bench]
+fn get_through_fn_pointer(b: &mut Bencher) {
+	let get: fn() -> u64 = black_box(get_counter);
+	b.iter(|| {
+		for _ in 0..1000 {
+			black_box(get());
+		}
+	});
+}
+
+#[bench]
+fn set_through_fn_pointer(b: &mut Bencher) {
+	let set: fn(u64) = black_box(set_counter);
+	b.iter(|| {
+		for i in 0..1000 {
+			set(black_box(i));
+		}
+	});
+}
+
+#[bench]
+fn std_get(b: &mut Bencher) {
+	b.iter(|| {
+		for _ in 0..1000 {
+			black_box(STD_COUNTER.with(|c| c.get()));
+		}
+	});
+}
@@ -0,0 +1,40 @@
+//! Detects whether the compiler supports the unstable features this crate uses
+//! so that a helpful error can be given if it doesn't. Also passes on the
+//! inline destructor capacity.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+	println!("cargo:rerun-if-changed=build.rs");
+	println!("cargo:rerun-if-env-changed=RUSTC_BOOTSTRAP");
+	println!("cargo:rustc-check-cfg=cfg(wintls_not_nightly)");
+	if !is_nightly() {
+		println!("cargo:rustc-cfg=wintls_not_nightly");
+	}
+
+	// The number of destructors that can be stored inline.
+	println!("cargo:rerun-if-env-changed=WINTLS_INLINE_DTORS");
+	let capacity = match env::var("WINTLS_INLINE_DTORS") {
+		Ok(capacity) => capacity
+			.parse::<usize>()
+			.expect("WINTLS_INLINE_DTORS must be a number"),
+		Err(_) => 32,
+	};
+	println!("cargo:rustc-env=WINTLS_INLINE_DTORS={capacity}");
+}
+
+fn is_nightly() -> bool {
+	if env::var_os("RUSTC_BOOTSTRAP").is_some() {
+		return true;
+	}
+	let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+	match Command::new(rustc).arg("--version").output() {
+		Ok(output) => {
+			let version = String::from_utf8_lossy(&output.stdout);
+			version.contains("nightly") || version.contains("-dev")
+		}
+		// If in doubt, let the compiler decide.
+		Err(_) => true,
+	}
+}
@@ -16,6 +16,7 @@ pub fn get_the_value() -> u32 {
 
 // Use a module handle to get the right thread-local.
 // This works because statics themselves aren't inlined.
+// `wintls::module_local!` does the same thing.
 static MODULE_STATIC_DATA: (&u32, fn() -> u32) = {
 	unsafe { (&wintls::raw::_tls_index, || wintls::raw::static_key!(TEST)) }
 };
@@ -24,3 +25,14 @@ pub fn get_module_value() -> u32 {
 	let (&module, key) = MODULE_STATIC_DATA;
 	unsafe { wintls::raw::get_static_from_module(module, key()) }
 }
+
+// The handle only calls functions in this DLL, so it's safe to use from the EXE.
+wintls::dll_safe_thread_local! {
+	pub static SAFE: u32 = 0xfeedface;
+}
+
+// The handle records this DLL's TLS index, so it's also safe to use from the EXE,
+// even though the lookup is inlined.
+wintls::module_local! {
+	pub static MODULE: u32 = 0xfeedface;
+}
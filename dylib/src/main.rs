@@ -7,4 +7,22 @@ fn main() {
 	println!("{:x}", libfoo::inline_the_value());
 	// Get the module handle using the module's index
 	println!("{:x}", libfoo::get_module_value());
+	// Always get the actual value
+	println!("{:x}", libfoo::SAFE.get());
+	assert_eq!(libfoo::SAFE.get(), 0xfeedface);
+	libfoo::SAFE.set(5);
+	assert_eq!(libfoo::SAFE.get(), 5);
+	std::thread::spawn(|| assert_eq!(libfoo::SAFE.get(), 0xfeedface))
+		.join()
+		.unwrap();
+
+	// Always get the actual value, with the lookup inlined
+	println!("{:x}", libfoo::MODULE.get());
+	assert_eq!(libfoo::MODULE.get(), 0xfeedface);
+	libfoo::MODULE.set(5);
+	assert_eq!(libfoo::MODULE.get(), 5);
+	assert_eq!(libfoo::MODULE.with(|value| *value), 5);
+	std::thread::spawn(|| assert_eq!(libfoo::MODULE.get(), 0xfeedface))
+		.join()
+		.unwrap();
 }
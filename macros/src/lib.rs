@@ -0,0 +1,424 @@
+//! Attribute macros for `wintls`.
+//!
+//! These are re-exported by `wintls` when its `macros` feature is enabled and
+//! should be used from there.
+//!
+//! This doesn't depend on `syn` or `quote`. The items it accepts are simple
+//! enough to parse by hand.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Span, TokenStream, TokenTree};
+
+/// Declares a static thread local.
+///
+/// This is an alternative to [`static_thread_local`] for a single `static`
+/// item. It generates the same thread local and [`StaticThreadLocal`] handle
+/// but errors point at the item rather than the macro.
+///
+/// [`static_thread_local`]: https://chrisdenton.github.io/wintls/wintls/macro.static_thread_local.html
+/// [`StaticThreadLocal`]: https://chrisdenton.github.io/wintls/wintls/struct.StaticThreadLocal.html
+///
+/// # Options
+///
+/// * `zeroed`: the initializer is omitted and the value starts out as all zero
+///   bytes. Note the zeros are still stored in the image.
+/// * `align = N`: aligns each thread's copy to at least `N` bytes. `N` can't be
+///   greater than the alignment the heap guarantees (16 bytes on x86_64 and 8
+///   on x86).
+/// * `section = "..."`: the same as `#[tls_section = "..."]` with
+///   [`static_thread_local`].
+/// * `crate = "..."`: the path to `wintls`, if the dependency has been
+///   renamed. This defaults to `::wintls`. The expansion only uses the path
+///   once, to invoke [`static_thread_local`], which uses `$crate` for the rest.
+///
+/// As with [`static_thread_local`], an `#[export_name = "..."]` attribute is
+/// applied to the thread local itself rather than the handle.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// #[wintls::thread_local]
+/// static COUNT: u32 = 0;
+///
+/// /// A zeroed buffer.
+/// #[wintls::thread_local(zeroed, align = 8, section = "B")]
+/// pub static BUFFER: [u8; 256];
+///
+/// # fn main() {
+/// COUNT.set(COUNT.get() + 1);
+/// assert_eq!(BUFFER.get_at(0), 0);
+/// # }
+/// ```
+///
+/// Types that need dropping are rejected. The error points at the type.
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// #[wintls::thread_local]
+/// static NAME: String = String::new();
+/// # fn main() {}
+/// ```
+#[proc_macro_attribute]
+pub fn thread_local(args: TokenStream, item: TokenStream) -> TokenStream {
+	match parse_options(args).and_then(|options| expand(options, item)) {
+		Ok(tokens) => tokens,
+		Err(error) => error.into_compile_error(),
+	}
+}
+
+struct Error {
+	span: Span,
+	message: String,
+}
+impl Error {
+	fn new(span: Span, message: impl Into<String>) -> Self {
+		Self {
+			span,
+			message: message.into(),
+		}
+	}
+
+	fn into_compile_error(self) -> TokenStream {
+		let message = TokenTree::Literal(Literal::string(&self.message)).into();
+		respan(
+			quote(
+				"::core::compile_error!(__message);",
+				&[("__message", &message)],
+			),
+			self.span,
+		)
+	}
+}
+
+#[derive(Default)]
+struct Options {
+	zeroed: bool,
+	align: Option<Literal>,
+	section: Option<Literal>,
+	krate: Option<Literal>,
+}
+
+fn parse_options(args: TokenStream) -> Result<Options, Error> {
+	let mut options = Options::default();
+	let mut tokens = args.into_iter().peekable();
+	while let Some(token) = tokens.next() {
+		let name = match token {
+			TokenTree::Ident(name) => name,
+			token => return Err(Error::new(token.span(), "expected an option")),
+		};
+		let option = name.to_string();
+		match option.as_str() {
+			"zeroed" if !options.zeroed => options.zeroed = true,
+			"align" | "section" | "crate" => {
+				let value = match (tokens.next(), tokens.next()) {
+					(Some(TokenTree::Punct(eq)), Some(TokenTree::Literal(value)))
+						if eq.as_char() == '=' =>
+					{
+						value
+					}
+					_ => {
+						return Err(Error::new(
+							name.span(),
+							format!("expected `{} = ...`", option),
+						))
+					}
+				};
+				let slot = match option.as_str() {
+					"align" => &mut options.align,
+					"section" => &mut options.section,
+					_ => &mut options.krate,
+				};
+				if slot.is_some() {
+					return Err(Error::new(
+						name.span(),
+						format!("duplicate option `{}`", option),
+					));
+				}
+				*slot = Some(value);
+			}
+			"zeroed" => return Err(Error::new(name.span(), "duplicate option `zeroed`")),
+			_ => {
+				return Err(Error::new(
+					name.span(),
+					format!(
+						"unknown option `{}`, expected `zeroed`, `align`, `section` or `crate`",
+						option
+					),
+				))
+			}
+		}
+		match tokens.next() {
+			Some(TokenTree::Punct(comma)) if comma.as_char() == ',' => {}
+			None => break,
+			Some(token) => return Err(Error::new(token.span(), "expected `,`")),
+		}
+	}
+	if let Some(align) = &options.align {
+		let digits = align.to_string();
+		let digits = digits.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+		match digits.parse::<u64>() {
+			Ok(n) if n.is_power_of_two() => {}
+			_ => {
+				return Err(Error::new(
+					align.span(),
+					"the alignment must be a power of two",
+				))
+			}
+		}
+	}
+	Ok(options)
+}
+
+/// A `static` item.
+struct Item {
+	attrs: TokenStream,
+	/// Attributes for the underlying `.tls$` static.
+	storage_attrs: TokenStream,
+	vis: TokenStream,
+	name: Ident,
+	ty: TokenStream,
+	ty_span: Span,
+	value: Option<TokenStream>,
+}
+
+fn parse_item(item: TokenStream) -> Result<Item, Error> {
+	let tokens: Vec<TokenTree> = item.into_iter().collect();
+	let mut i = 0;
+	let is_punct = |token: Option<&TokenTree>, c: char| matches!(token, Some(TokenTree::Punct(p)) if p.as_char() == c);
+	let is_ident = |token: Option<&TokenTree>, s: &str| matches!(token, Some(TokenTree::Ident(ident)) if ident.to_string() == s);
+	let span_at = |i: usize| {
+		tokens
+			.get(i)
+			.or_else(|| tokens.last())
+			.map_or_else(Span::call_site, TokenTree::span)
+	};
+
+	let mut attrs = TokenStream::new();
+	let mut storage_attrs = TokenStream::new();
+	while is_punct(tokens.get(i), '#') {
+		match tokens.get(i + 1) {
+			Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Bracket => {
+				let is_export = is_ident(group.stream().into_iter().next().as_ref(), "export_name");
+				let attr = tokens[i..i + 2].iter().cloned();
+				if is_export {
+					storage_attrs.extend(attr);
+				} else {
+					attrs.extend(attr);
+				}
+				i += 2;
+			}
+			_ => return Err(Error::new(span_at(i), "expected an attribute")),
+		}
+	}
+
+	let mut vis = TokenStream::new();
+	if is_ident(tokens.get(i), "pub") {
+		vis.extend(Some(tokens[i].clone()));
+		i += 1;
+		if let Some(TokenTree::Group(group)) = tokens.get(i) {
+			if group.delimiter() == Delimiter::Parenthesis {
+				vis.extend(Some(tokens[i].clone()));
+				i += 1;
+			}
+		}
+	}
+
+	if !is_ident(tokens.get(i), "static") {
+		return Err(Error::new(span_at(i), "expected a `static` item"));
+	}
+	i += 1;
+	if is_ident(tokens.get(i), "mut") {
+		return Err(Error::new(
+			span_at(i),
+			"thread locals can't be `static mut`, use the handle's methods to modify them",
+		));
+	}
+	let name = match tokens.get(i) {
+		Some(TokenTree::Ident(name)) => name.clone(),
+		_ => return Err(Error::new(span_at(i), "expected a name")),
+	};
+	i += 1;
+	if !is_punct(tokens.get(i), ':') {
+		return Err(Error::new(span_at(i), "expected a type"));
+	}
+	i += 1;
+
+	// The type ends at the first `=` or `;` that's not inside angle brackets.
+	let ty_start = i;
+	let mut depth = 0_usize;
+	while let Some(token) = tokens.get(i) {
+		if let TokenTree::Punct(p) = token {
+			let after_minus = i > 0 && is_punct(tokens.get(i - 1), '-');
+			match p.as_char() {
+				'<' => depth += 1,
+				'>' if !after_minus => depth = depth.saturating_sub(1),
+				'=' | ';' if depth == 0 => break,
+				_ => {}
+			}
+		}
+		i += 1;
+	}
+	if i == ty_start {
+		return Err(Error::new(span_at(i), "expected a type"));
+	}
+	let ty: TokenStream = tokens[ty_start..i].iter().cloned().collect();
+	let ty_span = tokens[ty_start].span();
+
+	let value = if is_punct(tokens.get(i), '=') {
+		let start = i + 1;
+		i = tokens.len() - 1;
+		Some(tokens[start..i].iter().cloned().collect())
+	} else {
+		None
+	};
+	if !is_punct(tokens.get(i), ';') || i + 1 != tokens.len() {
+		return Err(Error::new(span_at(i), "expected `;`"));
+	}
+
+	Ok(Item {
+		attrs,
+		storage_attrs,
+		vis,
+		name,
+		ty,
+		ty_span,
+		value,
+	})
+}
+
+fn expand(options: Options, item: TokenStream) -> Result<TokenStream, Error> {
+	let item = parse_item(item)?;
+	let value = match (item.value, options.zeroed) {
+		(Some(value), false) => value,
+		(None, true) => quote(
+			"unsafe {
+				::core::mem::transmute::<[u8; ::core::mem::size_of::<__ty>()], __ty>(
+					[0; ::core::mem::size_of::<__ty>()]
+				)
+			}",
+			&[("__ty", &item.ty)],
+		),
+		(None, false) => return Err(Error::new(item.name.span(), "expected an initializer")),
+		(Some(value), true) => {
+			let span = value
+				.into_iter()
+				.next()
+				.map_or(item.name.span(), |t| t.span());
+			return Err(Error::new(
+				span,
+				"a `zeroed` thread local can't have an initializer",
+			));
+		}
+	};
+	let repr = match options.align {
+		Some(align) => quote(
+			"C, align(__align)",
+			&[("__align", &TokenTree::from(align).into())],
+		),
+		None => quote("C", &[]),
+	};
+	let mut storage = item.storage_attrs;
+	if let Some(section) = options.section {
+		storage.extend(quote(
+			"#[tls_section = __section]",
+			&[("__section", &TokenTree::from(section).into())],
+		));
+	}
+	let name = TokenTree::from(item.name.clone()).into();
+	// The same message as `static_thread_local!{ @assert_no_drop }`. It's built
+	// here rather than using that arm so the assertion can have the type's span.
+	let message = TokenTree::Literal(Literal::string(&format!(
+		"thread local `{}` has type `{}`, which needs to be dropped. Static thread locals are \
+		 never dropped. Use a type that doesn't need dropping, or an `unsafe_local!` with \
+		 `wintls::dtor::register_dtor`",
+		item.name, item.ty
+	)))
+	.into();
+	// The assertion is given the type's span so that's where the error points.
+	let no_drop = respan(
+		quote(
+			"::core::assert!(!::core::mem::needs_drop::<__ty>(), __message);",
+			&[("__ty", &item.ty), ("__message", &message)],
+		),
+		item.ty_span,
+	);
+
+	let krate = match &options.krate {
+		Some(path) => {
+			let path_str = path.to_string();
+			path_str
+				.strip_prefix('"')
+				.and_then(|path| path.strip_suffix('"'))
+				.and_then(|path| path.parse::<TokenStream>().ok())
+				.filter(|path| !path.is_empty())
+				.ok_or_else(|| Error::new(path.span(), "expected a path to `wintls`"))?
+		}
+		None => quote("::wintls", &[]),
+	};
+	// Everything else is generated by `static_thread_local!`, so the paths can
+	// use `$crate`.
+	Ok(quote(
+		"
+		__crate::static_thread_local!{
+			@attribute [__storage] repr(__repr) [__no_drop]
+			__attrs
+			__vis static __name: __ty = __value;
+		}
+		",
+		&[
+			("__crate", &krate),
+			("__attrs", &item.attrs),
+			("__vis", &item.vis),
+			("__name", &name),
+			("__ty", &item.ty),
+			("__value", &value),
+			("__repr", &repr),
+			("__storage", &storage),
+			("__no_drop", &no_drop),
+		],
+	))
+}
+
+/// Parses `template`, replacing each identifier in `vars` with its tokens.
+fn quote(template: &str, vars: &[(&str, &TokenStream)]) -> TokenStream {
+	substitute(template.parse().unwrap(), vars)
+}
+
+fn substitute(tokens: TokenStream, vars: &[(&str, &TokenStream)]) -> TokenStream {
+	tokens
+		.into_iter()
+		.map(|token| match token {
+			TokenTree::Ident(ident) => {
+				let name = ident.to_string();
+				match vars.iter().find(|(var, _)| *var == name) {
+					Some((_, value)) => (*value).clone(),
+					None => TokenTree::Ident(ident).into(),
+				}
+			}
+			TokenTree::Group(group) => {
+				let mut new = Group::new(group.delimiter(), substitute(group.stream(), vars));
+				new.set_span(group.span());
+				TokenTree::Group(new).into()
+			}
+			token => token.into(),
+		})
+		.collect()
+}
+
+/// Gives every token the same span.
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+	tokens
+		.into_iter()
+		.map(|token| match token {
+			TokenTree::Group(group) => {
+				let mut new = Group::new(group.delimiter(), respan(group.stream(), span));
+				new.set_span(span);
+				TokenTree::Group(new)
+			}
+			mut token => {
+				token.set_span(span);
+				token
+			}
+		})
+		.collect()
+}
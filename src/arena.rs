@@ -0,0 +1,207 @@
+//! Per-thread bump allocation.
+
+use crate::{AccessError, HeapLocal};
+use core::alloc::Layout;
+use core::cell::{Cell, UnsafeCell};
+use core::ptr::NonNull;
+use std::alloc;
+
+// The size of the first chunk. Each new chunk is twice the size of the last.
+const FIRST_CHUNK: usize = 4096;
+const CHUNK_ALIGN: usize = 16;
+
+/// Declares [`LocalArena`]s.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::local_arena!{
+///     static NODES;
+/// }
+///
+/// fn main() {
+///     NODES.with(|arena| {
+///         let a = arena.alloc(1u32);
+///         let b = arena.alloc([2u64; 4]);
+///         assert_eq!(*a + b[0] as u32, 3);
+///
+///         // Resetting needs the references to be gone.
+///         arena.reset();
+///     });
+/// }
+/// ```
+#[macro_export]
+macro_rules! local_arena {
+	($($(#[$attr:meta])* $vis:vis static $name:ident;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::LocalArena = {
+			// Shadows the handle so that errors use its name.
+			$crate::heap_local!{
+				static $name: $crate::Arena = $crate::Arena::new;
+			}
+			$crate::LocalArena::new(&$name)
+		};
+	)+};
+}
+
+/// A handle to a per-thread [`Arena`], declared with
+/// [`local_arena`](crate::local_arena).
+///
+/// A thread's arena is created the first time it's used and all its memory is
+/// freed when the thread exits.
+pub struct LocalArena {
+	arena: &'static HeapLocal<Arena>,
+}
+impl LocalArena {
+	#[doc(hidden)]
+	pub const fn new(arena: &'static HeapLocal<Arena>) -> Self {
+		Self { arena }
+	}
+
+	/// Calls `f` with the current thread's arena.
+	///
+	/// Allocations made in one call are kept until the arena is reset, but
+	/// references to them can't outlive the call.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called from inside `f`, or after the thread local
+	/// destructors have run.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&mut Arena) -> R) -> R {
+		self.arena.with_mut(f)
+	}
+
+	/// Calls `f` with the current thread's arena. Returns an error if it has
+	/// been freed.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called from inside `f`.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&mut Arena) -> R) -> Result<R, AccessError> {
+		self.arena.try_with_mut(f)
+	}
+}
+
+struct Chunk {
+	ptr: NonNull<u8>,
+	layout: Layout,
+}
+
+/// A bump allocator.
+///
+/// Memory is handed out from a list of chunks. Only [`Copy`] values can be
+/// allocated, as nothing in the arena is ever dropped.
+pub struct Arena {
+	// The free part of the current chunk.
+	next: Cell<usize>,
+	end: Cell<usize>,
+	// The current chunk is the last one, which is also the largest.
+	chunks: UnsafeCell<Vec<Chunk>>,
+}
+// The chunks are owned by the arena, and references into them borrow it.
+unsafe impl Send for Arena {}
+impl Arena {
+	/// Creates an empty arena. No memory is allocated until the first
+	/// allocation.
+	pub const fn new() -> Self {
+		Self {
+			next: Cell::new(0),
+			end: Cell::new(0),
+			chunks: UnsafeCell::new(Vec::new()),
+		}
+	}
+
+	/// Moves `value` into the arena.
+	#[inline]
+	pub fn alloc<T: Copy>(&self, value: T) -> &T {
+		let ptr = self.alloc_layout(Layout::new::<T>()).cast::<T>();
+		unsafe {
+			ptr.as_ptr().write(value);
+			&*ptr.as_ptr()
+		}
+	}
+
+	/// Allocates memory for `layout`, which stays valid until the arena is
+	/// reset or dropped.
+	#[inline]
+	pub fn alloc_layout(&self, layout: Layout) -> NonNull<u8> {
+		if layout.size() == 0 {
+			// Any aligned pointer will do.
+			return unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+		}
+		let next = self.next.get();
+		let start = (next + (layout.align() - 1)) & !(layout.align() - 1);
+		match start.checked_add(layout.size()) {
+			Some(end) if next != 0 && end <= self.end.get() => {
+				self.next.set(end);
+				unsafe { NonNull::new_unchecked(start as *mut u8) }
+			}
+			_ => self.alloc_chunk(layout),
+		}
+	}
+
+	#[cold]
+	#[inline(never)]
+	fn alloc_chunk(&self, layout: Layout) -> NonNull<u8> {
+		// `alloc` doesn't call any user code so nothing else can be using the
+		// list.
+		let chunks = unsafe { &mut *self.chunks.get() };
+		let size = chunks
+			.last()
+			.map_or(FIRST_CHUNK, |chunk| chunk.layout.size() * 2)
+			.max(layout.size());
+		let chunk_layout = Layout::from_size_align(size, layout.align().max(CHUNK_ALIGN))
+			.expect("arena allocation is too large");
+		let ptr = unsafe { alloc::alloc(chunk_layout) };
+		let ptr = match NonNull::new(ptr) {
+			Some(ptr) => ptr,
+			None => alloc::handle_alloc_error(chunk_layout),
+		};
+		chunks.push(Chunk {
+			ptr,
+			layout: chunk_layout,
+		});
+		let start = ptr.as_ptr() as usize;
+		self.next.set(start + layout.size());
+		self.end.set(start + size);
+		ptr
+	}
+
+	/// Frees everything in the arena, except for the largest chunk which is
+	/// reused.
+	pub fn reset(&mut self) {
+		let chunks = self.chunks.get_mut();
+		if let Some(largest) = chunks.pop() {
+			for chunk in chunks.drain(..) {
+				unsafe { alloc::dealloc(chunk.ptr.as_ptr(), chunk.layout) };
+			}
+			let start = largest.ptr.as_ptr() as usize;
+			self.next.set(start);
+			self.end.set(start + largest.layout.size());
+			chunks.push(largest);
+		}
+	}
+
+	/// Returns the total size of the arena's chunks.
+	pub fn capacity(&self) -> usize {
+		let chunks = unsafe { &*self.chunks.get() };
+		chunks.iter().map(|chunk| chunk.layout.size()).sum()
+	}
+}
+impl Default for Arena {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Drop for Arena {
+	fn drop(&mut self) {
+		for chunk in self.chunks.get_mut().drain(..) {
+			unsafe { alloc::dealloc(chunk.ptr.as_ptr(), chunk.layout) };
+		}
+	}
+}
@@ -0,0 +1,98 @@
+//! Helpers for thread locals that store arrays.
+
+use crate::{borrow, StaticKey, StaticThreadLocal};
+
+impl<E: Copy, const N: usize, K: StaticKey<Value = [E; N]>> StaticThreadLocal<[E; N], K> {
+	/// Returns a pointer to the element at index `i`, panicking if it's out of
+	/// bounds.
+	#[inline(always)]
+	#[track_caller]
+	fn element_ptr(&self, i: usize) -> *mut E {
+		assert!(i < N, "index out of bounds: the len is {} but the index is {}", N, i);
+		unsafe { K::ptr().cast::<E>().add(i) }
+	}
+
+	/// Returns the element at index `i`.
+	///
+	/// Only the one element is copied, not the whole array.
+	///
+	/// # Panics
+	///
+	/// Panics if `i` is out of bounds.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static SLOTS: [u32; 16] = [0; 16];
+	/// # }
+	/// # fn main() {
+	/// let slot = SLOTS.get_at(3);
+	/// # }
+	/// ```
+	#[inline(always)]
+	#[track_caller]
+	pub fn get_at(&self, i: usize) -> E {
+		let ptr = self.element_ptr(i);
+		borrow::check_read(K::borrow());
+		K::validate(K::ptr());
+		unsafe { *ptr }
+	}
+
+	/// Sets the element at index `i`.
+	///
+	/// # Panics
+	///
+	/// Panics if `i` is out of bounds.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static SLOTS: [u32; 16] = [0; 16];
+	/// # }
+	/// # fn main() {
+	/// SLOTS.set_at(3, 5);
+	/// # }
+	/// ```
+	#[inline(always)]
+	#[track_caller]
+	pub fn set_at(&self, i: usize, value: E) {
+		let ptr = self.element_ptr(i);
+		borrow::check_write(K::borrow());
+		unsafe { *ptr = value }
+	}
+
+	/// Calls `f` with a mutable slice of the array.
+	///
+	/// This allows sorting, searching or filling the array in place. The slice
+	/// cannot escape the closure. The same reentrancy rules apply as for
+	/// [`with_mut`](StaticThreadLocal::with_mut).
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static SLOTS: [u32; 16] = [0; 16];
+	/// # }
+	/// # fn main() {
+	/// SLOTS.with_slice(|slots| slots.fill(5));
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn with_slice<R>(&self, f: impl FnOnce(&mut [E]) -> R) -> R {
+		let _guard = borrow::BorrowGuard::exclusive(K::borrow());
+		let ptr = K::ptr();
+		K::validate(ptr);
+		unsafe { f(core::slice::from_raw_parts_mut(ptr.cast::<E>(), N)) }
+	}
+}
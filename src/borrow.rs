@@ -0,0 +1,106 @@
+//! Debug-only borrow tracking for static thread locals.
+//!
+//! Each local declared in a crate with debug assertions enabled gets an extra
+//! `isize` thread local that counts the live references handed out by `with`
+//! (positive) or marks the one handed out by `with_mut` (negative). Without
+//! debug assertions the flag pointer is null and every check is skipped.
+//!
+//! With the `debug-borrows` feature, `unsafe_local!` handles are tracked the
+//! same way and the panics include the local's name.
+
+/// Resets the borrow flag when the closure returns or panics.
+pub(crate) struct BorrowGuard(*mut isize);
+impl BorrowGuard {
+	#[inline(always)]
+	pub(crate) fn shared(flag: *mut isize) -> Self {
+		Self::shared_named(flag, None)
+	}
+
+	#[inline(always)]
+	pub(crate) fn shared_named(flag: *mut isize, name: Option<&'static str>) -> Self {
+		unsafe {
+			if !flag.is_null() {
+				if *flag < 0 {
+					already_borrowed(name, true);
+				}
+				*flag += 1;
+			}
+		}
+		Self(flag)
+	}
+
+	#[inline(always)]
+	pub(crate) fn exclusive(flag: *mut isize) -> Self {
+		Self::exclusive_named(flag, None)
+	}
+
+	#[inline(always)]
+	pub(crate) fn exclusive_named(flag: *mut isize, name: Option<&'static str>) -> Self {
+		unsafe {
+			if !flag.is_null() {
+				if *flag != 0 {
+					already_borrowed(name, false);
+				}
+				*flag = -1;
+			}
+		}
+		Self(flag)
+	}
+}
+impl Drop for BorrowGuard {
+	#[inline(always)]
+	fn drop(&mut self) {
+		unsafe {
+			if !self.0.is_null() {
+				if *self.0 < 0 {
+					*self.0 = 0;
+				} else {
+					*self.0 -= 1;
+				}
+			}
+		}
+	}
+}
+
+/// Panics if the value can't currently be read.
+#[inline(always)]
+pub(crate) fn check_read(flag: *mut isize) {
+	check_read_named(flag, None)
+}
+
+#[inline(always)]
+pub(crate) fn check_read_named(flag: *mut isize, name: Option<&'static str>) {
+	unsafe {
+		if !flag.is_null() && *flag < 0 {
+			already_borrowed(name, true);
+		}
+	}
+}
+
+/// Panics if the value can't currently be written.
+#[inline(always)]
+pub(crate) fn check_write(flag: *mut isize) {
+	check_write_named(flag, None)
+}
+
+#[inline(always)]
+pub(crate) fn check_write_named(flag: *mut isize, name: Option<&'static str>) {
+	unsafe {
+		if !flag.is_null() && *flag != 0 {
+			already_borrowed(name, false);
+		}
+	}
+}
+
+#[cold]
+fn already_borrowed(name: Option<&'static str>, mutably: bool) -> ! {
+	let how = if mutably {
+		"mutably borrowed"
+	} else {
+		"borrowed"
+	};
+	match name {
+		Some(name) => panic!("thread local `{name}` is already {how}"),
+		None => panic!("thread local is already {how}"),
+	}
+}
@@ -0,0 +1,179 @@
+//! A thread local with the methods of `Cell`.
+
+/// Declares thread locals with a [`CellLocal`] handle.
+///
+/// This is like a `std` thread local holding a [`Cell`](core::cell::Cell),
+/// but without the `Cell`. Each thread already has its own copy so it can be
+/// modified directly. As with [`static_thread_local`](crate::static_thread_local),
+/// types that need dropping are rejected.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::cell_local!{
+///     static COUNT: u32 = 0;
+/// }
+///
+/// fn main() {
+///     COUNT.set(1);
+///     COUNT.update(|n| n + 1);
+///     assert_eq!(COUNT.get(), 2);
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::cell_local!{
+///     static NAME: String = String::new();
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! cell_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::CellLocal<$ty> = {
+			$crate::static_thread_local!{ @assert_no_drop $name: $ty }
+			$crate::init_static!(
+				static $name: $ty = $value;
+			);
+			unsafe { $crate::CellLocal::new(|| $crate::static_ptr!($name)) }
+		};
+	)+};
+}
+
+/// A handle to a thread local declared with [`cell_local`](crate::cell_local).
+///
+/// Every method looks up the current thread's value once. No references to
+/// the value are handed out, so there's no need to track borrows.
+pub struct CellLocal<T: 'static> {
+	get: fn() -> *mut T,
+}
+// The handle only holds a function that finds the current thread's copy, and
+// values are only ever copied in and out of that copy. `T` must be `Send`
+// because each thread starts with a copy of the initial value.
+unsafe impl<T: Send> Sync for CellLocal<T> {}
+unsafe impl<T: Send> Send for CellLocal<T> {}
+impl<T> CellLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local with the type `T`, which doesn't need dropping.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> *mut T) -> Self {
+		Self { get }
+	}
+
+	/// Returns a copy of the value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::cell_local!{
+	///     static X: u32 = 1;
+	/// }
+	/// # fn main() {
+	/// assert_eq!(X.get(), 1);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn get(&self) -> T
+	where
+		T: Copy,
+	{
+		unsafe { *(self.get)() }
+	}
+
+	/// Sets the value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::cell_local!{
+	///     static X: u32 = 1;
+	/// }
+	/// # fn main() {
+	/// X.set(2);
+	/// assert_eq!(X.get(), 2);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn set(&self, value: T) {
+		unsafe { (self.get)().write(value) }
+	}
+
+	/// Sets the value, returning the old value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::cell_local!{
+	///     static X: [u8; 2] = [1, 2];
+	/// }
+	/// # fn main() {
+	/// assert_eq!(X.replace([3, 4]), [1, 2]);
+	/// assert_eq!(X.get(), [3, 4]);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn replace(&self, value: T) -> T {
+		unsafe { (self.get)().replace(value) }
+	}
+
+	/// Takes the value, leaving `Default::default()` in its place.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::cell_local!{
+	///     static X: Option<u32> = Some(1);
+	/// }
+	/// # fn main() {
+	/// assert_eq!(X.take(), Some(1));
+	/// assert_eq!(X.get(), None);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn take(&self) -> T
+	where
+		T: Default,
+	{
+		self.replace(T::default())
+	}
+
+	/// Replaces the value with the result of calling `f` on it. Returns the
+	/// new value.
+	///
+	/// As with [`StaticThreadLocal::update`](crate::StaticThreadLocal::update),
+	/// the value is only looked up once, before `f` is called.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::cell_local!{
+	///     static X: u32 = 1;
+	/// }
+	/// # fn main() {
+	/// assert_eq!(X.update(|x| x * 10), 10);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn update(&self, f: impl FnOnce(T) -> T) -> T
+	where
+		T: Copy,
+	{
+		let ptr = (self.get)();
+		unsafe {
+			let value = f(*ptr);
+			*ptr = value;
+			value
+		}
+	}
+}
@@ -0,0 +1,119 @@
+//! A counter with a part for each thread.
+
+use crate::RegisteredLocal;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Declares [`ThreadLocalCounter`]s.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::thread_local_counter!{
+///     static REQUESTS;
+/// }
+///
+/// fn main() {
+///     REQUESTS.increment();
+///     REQUESTS.add(2);
+///     assert_eq!(REQUESTS.sum(), 3);
+/// }
+/// ```
+#[macro_export]
+macro_rules! thread_local_counter {
+	($($(#[$attr:meta])* $vis:vis static $name:ident;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::ThreadLocalCounter = {
+			static EXITED: ::core::sync::atomic::AtomicU64 = ::core::sync::atomic::AtomicU64::new(0);
+			$crate::registered_local!{
+				static COUNTS: $crate::CounterSlot = $crate::CounterSlot::new(&EXITED);
+			}
+			$crate::ThreadLocalCounter::new(&COUNTS, &EXITED)
+		};
+	)+};
+}
+
+/// A thread's part of a [`ThreadLocalCounter`].
+#[doc(hidden)]
+pub struct CounterSlot {
+	count: AtomicU64,
+	exited: &'static AtomicU64,
+}
+impl CounterSlot {
+	pub const fn new(exited: &'static AtomicU64) -> Self {
+		Self {
+			count: AtomicU64::new(0),
+			exited,
+		}
+	}
+}
+impl Drop for CounterSlot {
+	fn drop(&mut self) {
+		add_to(self.exited, *self.count.get_mut());
+	}
+}
+
+/// A counter that's cheap to add to from many threads at once.
+///
+/// Each thread adds to its own part of the counter, so there's no contention.
+/// Only the thread's part is read and written, which doesn't need atomic
+/// read-modify-write instructions. [`sum`](Self::sum) adds up the parts of
+/// every thread, including threads that have exited.
+///
+/// The counter saturates at `u64::MAX`.
+///
+/// While other threads are adding to the counter, `sum` may miss some of the
+/// additions made at about the same time, including those of a thread that's
+/// exiting. It never counts an addition twice.
+pub struct ThreadLocalCounter {
+	counts: &'static RegisteredLocal<CounterSlot>,
+	exited: &'static AtomicU64,
+}
+impl ThreadLocalCounter {
+	#[doc(hidden)]
+	pub const fn new(
+		counts: &'static RegisteredLocal<CounterSlot>,
+		exited: &'static AtomicU64,
+	) -> Self {
+		Self { counts, exited }
+	}
+
+	/// Adds one to the counter.
+	#[inline]
+	pub fn increment(&self) {
+		self.add(1)
+	}
+
+	/// Adds `n` to the counter.
+	#[inline]
+	pub fn add(&self, n: u64) {
+		let added = self.counts.try_with(|slot| {
+			// Only this thread writes to its count.
+			let count = slot.count.load(Ordering::Relaxed);
+			slot.count.store(count.saturating_add(n), Ordering::Relaxed);
+		});
+		// The thread's part has already been added to the total.
+		if added.is_err() {
+			add_to(self.exited, n);
+		}
+	}
+
+	/// Returns the total of every thread's additions.
+	pub fn sum(&self) -> u64 {
+		// A thread is removed from the registry before its part is added to
+		// `exited`. So reading `exited` first means a thread can't be counted
+		// in both.
+		let mut sum = self.exited.load(Ordering::Acquire);
+		self.counts.for_each(|slot| {
+			sum = sum.saturating_add(slot.count.load(Ordering::Relaxed));
+		});
+		sum
+	}
+}
+
+fn add_to(total: &AtomicU64, n: u64) {
+	let _ = total.fetch_update(Ordering::Release, Ordering::Relaxed, |total| {
+		Some(total.saturating_add(n))
+	});
+}
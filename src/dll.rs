@@ -0,0 +1,94 @@
+//! Thread locals that can be safely used from other modules.
+
+/// Declares thread locals that are always accessed from the module (i.e. the
+/// EXE or DLL) that declared them.
+///
+/// Each module has its own thread locals. Normally the code that finds a
+/// thread local is inlined into the caller, which is fine so long as the
+/// caller is in the same module. But a Rust `dylib` can have its functions
+/// inlined into another module. The inlined code then looks up the thread
+/// local using the other module's TLS index and reads whatever happens to be
+/// there. For example the `dylib` example prints `feedface` when the value is
+/// read from the DLL, but a nonsense value such as `0` when the same read is
+/// inlined into the EXE.
+///
+/// This macro avoids the problem by only accessing the thread local through
+/// functions that are never inlined. The [`DllSafeThreadLocal`] handle only
+/// calls those functions so it can be given to code in other modules. This
+/// does mean every access is a function call.
+///
+/// The types must be [`Copy`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::dll_safe_thread_local!{
+///     pub static COUNTER: u32 = 0;
+/// }
+///
+/// fn main() {
+///     COUNTER.set(COUNTER.get() + 1);
+///     assert_eq!(COUNTER.get(), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! dll_safe_thread_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::DllSafeThreadLocal<$ty> = {
+			$crate::static_thread_local!{
+				static LOCAL: $ty = $value;
+			}
+
+			#[inline(never)]
+			fn get() -> $ty {
+				LOCAL.get()
+			}
+
+			#[inline(never)]
+			fn set(value: $ty) {
+				LOCAL.set(value)
+			}
+
+			unsafe { $crate::DllSafeThreadLocal::new(get, set) }
+		};
+	)+};
+}
+
+/// A handle to a thread local declared with
+/// [`dll_safe_thread_local`](crate::dll_safe_thread_local).
+pub struct DllSafeThreadLocal<T: 'static> {
+	get: fn() -> T,
+	set: fn(T),
+}
+impl<T: Copy> DllSafeThreadLocal<T> {
+	/// # Safety
+	///
+	/// `get` and `set` must access the same thread local and never be inlined.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> T, set: fn(T)) -> Self {
+		Self { get, set }
+	}
+
+	/// Returns the value of the thread local.
+	#[inline]
+	pub fn get(&self) -> T {
+		(self.get)()
+	}
+
+	/// Sets the value of the thread local.
+	#[inline]
+	pub fn set(&self, value: T) {
+		(self.set)(value)
+	}
+
+	/// Sets the value of the thread local, returning the previous value.
+	#[inline]
+	pub fn replace(&self, value: T) -> T {
+		let old = self.get();
+		self.set(value);
+		old
+	}
+}
@@ -2,7 +2,9 @@
 //!
 //! This is super simple. It just allows adding to a thread-local list of
 //! destructors to run. Once the thread exits, the list is drained and all
-//! destructors are run, from last to first.
+//! destructors are run, from last to first. A thread can use [`set_order`] to
+//! run them from first to last instead. Destructors can be given a priority
+//! with [`register_dtor_with_priority`] to run them earlier or later.
 //!
 //! # Example
 //!
@@ -14,10 +16,86 @@
 //!
 //! # Registering Destructors in Destructors
 //!
-//! Destructors can be registered while a destructor is being run. It is
-//! currently up to the users of this library to prevent an infinite loop in
-//! this situation (e.g. by only allowing a thread local to be initialized and
-//! destroyed once, or by checking [`state`]).
+//! Destructors can be registered while a destructor is being run. They're run
+//! after the destructors with the same priority that haven't run yet, but
+//! before any with a lower priority.
+//!
+//! To stop a destructor that keeps registering itself from looping forever,
+//! the destructors are run in at most 8 passes, which can be changed with
+//! [`set_max_passes`]. The destructors registered before they're run are the
+//! first pass, those registered by the first pass are the second, and so on.
+//! Destructors registered by the last pass are discarded without being run,
+//! and their data is leaked. In debug builds this causes a panic once the
+//! other destructors have run (see [Panics](#panics)). Thread locals can avoid
+//! this by only being initialized and destroyed once, or by checking
+//! [`state`].
+//!
+//! Once the thread's destructors have all run, the state is
+//! [`DtorState::Done`] and no more destructors can be registered. Code that
+//! may run after that, such as another TLS callback, can use
+//! [`try_register_dtor`].
+//!
+//! # Using Thread Locals in Destructors
+//!
+//! Destructors run from last registered to first, so a destructor may use a
+//! thread local that has already been dropped. The thread locals in this crate
+//! that drop their values, such as [`Local`](crate::Local) and
+//! [`HeapLocal`](crate::HeapLocal), remember that they've been dropped. After
+//! that, `with` panics and `try_with` returns an
+//! [`AccessError`](crate::AccessError).
+//!
+//! # Panics
+//!
+//! With the `std` feature, a destructor that panics doesn't stop the others
+//! from being run. [`drop_locals`] resumes the first panic once they've all
+//! run. When the thread exits the panics are reported and otherwise ignored,
+//! because they can't unwind out of the thread. Without the `std` feature, a
+//! panic stops the rest of the destructors from being run, and aborts the
+//! process if the thread is exiting.
+//!
+//! Panics can't unwind out of the `extern "C"` functions given to
+//! [`register_dtor_with`], so they always abort.
+//!
+//! # Registering Without Allocating
+//!
+//! [`register_dtor_node`] links a [`DtorNode`] into a list that's separate
+//! from the other destructors, so it never allocates. This is for code that
+//! can't allocate, such as a global allocator. The nodes are run first, from
+//! last registered to first, ignoring priorities and [`set_order`]. Nodes
+//! registered while they're running are run in the next pass, and nodes
+//! registered by the other destructors are run after those destructors. Both
+//! kinds of pass count towards [`set_max_passes`].
+//!
+//! # Storage
+//!
+//! With the `std` feature the destructors are kept in a `Vec`. Without it, or
+//! with the `inline-dtors` feature, they're kept in an array in thread local
+//! storage so that registering a destructor never allocates. The array holds
+//! [`INLINE_CAPACITY`] destructors, 32 unless the `WINTLS_INLINE_DTORS`
+//! environment variable is set when this crate is built. Once it's full,
+//! [`try_register_dtor`] returns an error and the other functions panic.
+//!
+//! # Exiting the Process
+//!
+//! When `main` returns, the CRT calls `exit`, which runs the `atexit` handlers
+//! and then `ExitProcess`. With the `std` feature, this crate registers an
+//! `atexit` handler before `main` is called, which runs the exiting thread's
+//! destructors. So they run after any `atexit` handlers registered by `main`,
+//! but before the CRT and the other threads are torn down. `std::process::exit`
+//! and `ExitProcess` skip the `atexit` handlers, so the destructors are run by
+//! the TLS callback for `DLL_PROCESS_DETACH` instead, after the other threads
+//! have been terminated without running theirs. Either way, each destructor is
+//! only run once.
+//!
+//! Without `std` there's no `atexit` handler, as there may be no CRT, so the
+//! destructors are always run by the TLS callback. The same goes for a copy of
+//! this crate in a DLL. A DLL's `atexit` handlers are run when the DLL is
+//! unloaded, or after the other threads have been terminated if it's still
+//! loaded when the process exits, so there would be no benefit.
+//!
+//! Only the exiting thread's destructors are run. This is usually the main
+//! thread. With the `std` feature, [`enable_process_exit_drain`] also runs
+//! those of the other threads, with some serious caveats.
 //!
 //! # Limitations
 //!
@@ -28,11 +106,278 @@
 //!
 //! Ideally the drop code would be delayed until the thread exits but if the
 //! DLL has already been unloaded then there's no code left to run.
+//!
+//! The reverse happens when a DLL registers destructors with another module's
+//! copy of this crate, e.g. by calling a function in the EXE that calls
+//! [`register_dtor_with`]. With the `std` feature, each destructor remembers
+//! which module its function is in. Once that module has been unloaded, its
+//! destructors are skipped without being run and their data is leaked. This
+//! doesn't cover closures registered with [`register_dtor_boxed`] or nodes
+//! registered with [`register_dtor_node`], as their code is only run through
+//! this crate. A DLL can instead pin itself with [`register_dtor_pinned`], so
+//! that its destructors are always run.
+
+use core::cmp::Reverse;
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+struct Dtor {
+	priority: i8,
+	// Destructors registered while another is running are in the batch after
+	// it.
+	batch: u32,
+	// Ids count up in LIFO order and down in FIFO order, so the next
+	// destructor to run always has the largest.
+	id: u64,
+	data: *mut u8,
+	// `None` once the destructor has been cancelled.
+	f: Option<Call>,
+	// Frees `data` if the destructor is cancelled.
+	free: Option<unsafe fn(*mut u8)>,
+	// The module that `f` is in, if it's another module that can be unloaded.
+	module: Option<&'static unload::Module>,
+	key: Option<usize>,
+}
+// Each thread only runs its own destructors.
+unsafe impl Send for Dtor {}
+#[derive(Clone, Copy)]
+enum Call {
+	// `data` is a `fn()`.
+	Fn,
+	// Panics can only be caught if they unwind out of the destructor, which
+	// they can't do from an `extern "C"` function.
+	Rust(unsafe fn(*mut u8)),
+	C(unsafe extern "C" fn(*mut u8)),
+}
+impl Call {
+	#[inline]
+	unsafe fn call(self, data: *mut u8) {
+		match self {
+			Call::Fn => core::mem::transmute::<*mut u8, fn()>(data)(),
+			Call::Rust(f) => f(data),
+			Call::C(f) => f(data),
+		}
+	}
+
+	/// Returns the address of the code that's run.
+	fn address(self, data: *mut u8) -> usize {
+		match self {
+			Call::Fn => data as usize,
+			Call::Rust(f) => f as usize,
+			Call::C(f) => f as usize,
+		}
+	}
+}
+
+impl Dtor {
+	// The list is sorted by this, so the next destructor to run is the last.
+	fn order(&self) -> (i8, Reverse<u32>, u64) {
+		(self.priority, Reverse(self.batch), self.id)
+	}
+
+	// Returns the function to call, unless the destructor has been cancelled
+	// or its module has been unloaded.
+	fn runnable(&self) -> Option<Call> {
+		match self.module {
+			Some(module) if module.is_unloaded() => None,
+			_ => self.f,
+		}
+	}
+}
 
 crate::unsafe_local!(
-	static DESTRUCTORS: Vec<fn()> = Vec::new();
+	static DESTRUCTORS: list::List = list::List::new();
 );
 
+/// The number of destructors each thread can have when they're stored inline.
+///
+/// See [Storage](self#storage).
+pub const INLINE_CAPACITY: usize = parse_capacity(env!("WINTLS_INLINE_DTORS"));
+
+const fn parse_capacity(capacity: &str) -> usize {
+	// The build script has checked that it's a number.
+	let digits = capacity.as_bytes();
+	let mut value = 0;
+	let mut i = 0;
+	while i < digits.len() {
+		value = value * 10 + (digits[i] - b'0') as usize;
+		i += 1;
+	}
+	value
+}
+
+#[cfg(all(feature = "std", not(feature = "inline-dtors")))]
+mod list {
+	use super::Dtor;
+	use core::ops::{Deref, DerefMut};
+
+	pub(super) struct List(Vec<Dtor>);
+	impl List {
+		pub(super) const fn new() -> Self {
+			Self(Vec::new())
+		}
+
+		pub(super) fn pop(&mut self) -> Option<Dtor> {
+			self.0.pop()
+		}
+
+		pub(super) fn try_insert(&mut self, index: usize, dtor: Dtor) -> Result<(), Dtor> {
+			self.0.insert(index, dtor);
+			Ok(())
+		}
+
+		pub(super) fn remove(&mut self, index: usize) -> Dtor {
+			self.0.remove(index)
+		}
+	}
+	impl Deref for List {
+		type Target = [Dtor];
+		fn deref(&self) -> &[Dtor] {
+			&self.0
+		}
+	}
+	impl DerefMut for List {
+		fn deref_mut(&mut self) -> &mut [Dtor] {
+			&mut self.0
+		}
+	}
+}
+
+#[cfg(any(not(feature = "std"), feature = "inline-dtors"))]
+mod list {
+	use super::{Dtor, INLINE_CAPACITY};
+	use core::mem::MaybeUninit;
+	use core::ops::{Deref, DerefMut};
+	use core::ptr;
+
+	pub(super) struct List {
+		len: usize,
+		items: MaybeUninit<[Dtor; INLINE_CAPACITY]>,
+	}
+	impl List {
+		pub(super) const fn new() -> Self {
+			Self {
+				len: 0,
+				items: MaybeUninit::uninit(),
+			}
+		}
+
+		fn as_mut_ptr(&mut self) -> *mut Dtor {
+			self.items.as_mut_ptr().cast()
+		}
+
+		pub(super) fn pop(&mut self) -> Option<Dtor> {
+			if self.len == 0 {
+				return None;
+			}
+			self.len -= 1;
+			Some(unsafe { self.as_mut_ptr().add(self.len).read() })
+		}
+
+		pub(super) fn try_insert(&mut self, index: usize, dtor: Dtor) -> Result<(), Dtor> {
+			assert!(index <= self.len);
+			if self.len == INLINE_CAPACITY {
+				return Err(dtor);
+			}
+			unsafe {
+				let slot = self.as_mut_ptr().add(index);
+				ptr::copy(slot, slot.add(1), self.len - index);
+				slot.write(dtor);
+			}
+			self.len += 1;
+			Ok(())
+		}
+
+		pub(super) fn remove(&mut self, index: usize) -> Dtor {
+			assert!(index < self.len);
+			self.len -= 1;
+			unsafe {
+				let slot = self.as_mut_ptr().add(index);
+				let dtor = slot.read();
+				ptr::copy(slot.add(1), slot, self.len - index);
+				dtor
+			}
+		}
+	}
+	impl Deref for List {
+		type Target = [Dtor];
+		fn deref(&self) -> &[Dtor] {
+			unsafe { core::slice::from_raw_parts(self.items.as_ptr().cast(), self.len) }
+		}
+	}
+	impl DerefMut for List {
+		fn deref_mut(&mut self) -> &mut [Dtor] {
+			unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+		}
+	}
+}
+crate::static_thread_local! {
+	static NEXT_ID: u64 = 0;
+	// The batch that new destructors are added to.
+	static BATCH: u32 = 0;
+	static ORDER: Order = Order::Lifo;
+	// Static thread locals are never destroyed.
+	static GENERATION: u32 = 0;
+	// The nodes waiting for the next pass, newest first.
+	static NODES: NodePtr = NodePtr(core::ptr::null_mut());
+	// The rest of the nodes in the pass that's running.
+	static RUNNING_NODES: NodePtr = NodePtr(core::ptr::null_mut());
+}
+#[derive(Clone, Copy)]
+struct NodePtr(*mut DtorNode);
+// Each thread only uses its own nodes.
+unsafe impl Send for NodePtr {}
+
+/// The order that destructors with the same priority are run in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+	/// Last registered to first. This is the default.
+	Lifo,
+	/// First registered to last.
+	Fifo,
+}
+
+/// Sets the order that the current thread's destructors are run in.
+///
+/// This must be called before the thread registers any destructors. Remember
+/// that thread locals may register destructors the first time they're used.
+///
+/// In either order, destructors registered while the destructors are being
+/// run are run after those with the same priority that were registered
+/// before.
+///
+/// # Panics
+///
+/// Panics if destructors have been registered by this thread and `order` is
+/// different to the current order.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::dtor::{self, Order};
+///
+/// # fn main() {
+/// std::thread::spawn(|| {
+///     dtor::set_order(Order::Fifo);
+///     dtor::register_dtor(|| println!("stopping the first stage"));
+///     dtor::register_dtor(|| println!("stopping the second stage"));
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+pub fn set_order(order: Order) {
+	if order != ORDER.get() {
+		assert_eq!(
+			NEXT_ID.get(),
+			0,
+			"the destructor order can't be changed after destructors have been registered"
+		);
+		ORDER.set(order);
+	}
+}
+
 #[derive(Clone, Copy)]
 pub enum DtorState {
 	/// Passively waiting to run destructors, either when the thread exits or
@@ -40,6 +385,9 @@ pub enum DtorState {
 	Passive,
 	/// Destructors are currently being run.
 	Dropping,
+	/// The thread is exiting and its destructors have all been run. No more
+	/// destructors can be registered.
+	Done,
 }
 crate::static_thread_local! {
 	static STATE: DtorState = DtorState::Passive;
@@ -48,6 +396,150 @@ crate::static_thread_local! {
 pub fn state() -> DtorState {
 	STATE.get()
 }
+fn is_done() -> bool {
+	matches!(STATE.get(), DtorState::Done)
+}
+
+/// Returns the number of times the current thread's destructors have been
+/// run, either by [`drop_locals`] or by the thread exiting.
+///
+/// State that's set up again after [`drop_locals`] can store the generation
+/// next to its own flag. If the generation has changed since, the state
+/// belongs to an earlier generation and should be set up again.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::dtor;
+///
+/// wintls::static_thread_local! {
+///     // The generation the connection was opened in.
+///     static CONNECTED: Option<u32> = None;
+/// }
+///
+/// fn connect() {
+///     if CONNECTED.get() != Some(dtor::generation()) {
+///         // Open the connection and register a destructor to close it.
+///         CONNECTED.set(Some(dtor::generation()));
+///     }
+/// }
+/// # fn main() { connect(); }
+/// ```
+pub fn generation() -> u32 {
+	GENERATION.get()
+}
+
+/// Returns the number of the current thread's destructors that haven't been
+/// run or cancelled.
+///
+/// While the destructors are being run, this only counts those that are still
+/// to run. Once the state is [`DtorState::Done`] it's 0.
+pub fn registered_count() -> usize {
+	if is_done() {
+		return 0;
+	}
+	let list = unsafe { DESTRUCTORS.as_ref_mut() };
+	let nodes = unsafe { count_nodes(NODES.get().0) + count_nodes(RUNNING_NODES.get().0) };
+	list.iter().filter(|dtor| dtor.f.is_some()).count() + nodes
+}
+
+unsafe fn count_nodes(mut node: *const DtorNode) -> usize {
+	let mut count = 0;
+	while let Some(current) = node.as_ref() {
+		count += 1;
+		node = current.next;
+	}
+	count
+}
+
+/// Returns `true` if the current thread has no destructors left to run.
+///
+/// This is the same as `registered_count() == 0`.
+pub fn is_empty() -> bool {
+	registered_count() == 0
+}
+
+#[cfg(debug_assertions)]
+#[link(name = "kernel32")]
+extern "system" {
+	fn OutputDebugStringA(output: *const u8);
+}
+
+/// Writes the current thread's destructors to the debugger with
+/// `OutputDebugStringA`, next to run first.
+///
+/// Each line has the priority, the function pointer and its data. For the
+/// destructors registered with a `fn()`, such as with [`register_dtor`], the
+/// data is the function. This is only available in debug builds.
+#[cfg(debug_assertions)]
+pub fn dump() {
+	// Formats a line without allocating, cutting it short if it's too long.
+	struct Line {
+		buffer: [u8; 128],
+		len: usize,
+	}
+	impl fmt::Write for Line {
+		fn write_str(&mut self, s: &str) -> fmt::Result {
+			// Leave room for the newline and terminator.
+			let len = s.len().min(self.buffer.len() - 2 - self.len);
+			self.buffer[self.len..][..len].copy_from_slice(&s.as_bytes()[..len]);
+			self.len += len;
+			Ok(())
+		}
+	}
+	fn output(args: fmt::Arguments<'_>) {
+		let mut line = Line {
+			buffer: [0; 128],
+			len: 0,
+		};
+		let _ = fmt::Write::write_fmt(&mut line, args);
+		line.buffer[line.len] = b'\n';
+		unsafe { OutputDebugStringA(line.buffer.as_ptr()) };
+	}
+
+	output(format_args!(
+		"wintls: thread {} has {} destructors",
+		crate::raw_internal::current_thread_id(),
+		registered_count()
+	));
+	if is_done() {
+		return;
+	}
+	let mut i = unsafe { DESTRUCTORS.as_ref_mut().len() };
+	// The list isn't borrowed while formatting, in case it registers a
+	// destructor.
+	while i > 0 {
+		i -= 1;
+		let (priority, f, data) = {
+			let list = unsafe { DESTRUCTORS.as_ref_mut() };
+			let Some(dtor) = list.get(i) else { continue };
+			match dtor.f {
+				Some(Call::Fn) => (dtor.priority, dtor.data as *const u8, dtor.data),
+				Some(Call::Rust(f)) => (dtor.priority, f as *const u8, dtor.data),
+				Some(Call::C(f)) => (dtor.priority, f as *const u8, dtor.data),
+				None => continue,
+			}
+		};
+		output(format_args!("  priority {priority}: {f:p} with {data:p}"));
+	}
+}
+
+static MAX_PASSES: AtomicU32 = AtomicU32::new(8);
+
+/// Sets the most passes that a thread's destructors are run in, for all
+/// threads. The default is 8.
+///
+/// See [Registering Destructors in
+/// Destructors](self#registering-destructors-in-destructors).
+///
+/// # Panics
+///
+/// Panics if `passes` is 0.
+pub fn set_max_passes(passes: u32) {
+	assert!(passes > 0, "destructors must be run in at least one pass");
+	MAX_PASSES.store(passes, Ordering::Relaxed);
+}
 
 /// Register a destructor for a thread local on this thread only.
 ///
@@ -65,32 +557,1145 @@ pub fn state() -> DtorState {
 /// * Some combination of the above.
 ///
 /// My preference is currently for the first option.
-pub fn register_dtor(f: fn()) {
-	unsafe { DESTRUCTORS.as_ref_mut().push(f) };
+///
+/// The returned handle can be used to cancel the destructor. The destructor
+/// has a priority of 0.
+///
+/// # Panics
+///
+/// Panics if the state is [`DtorState::Done`], or if the destructors are
+/// stored inline and there's no room (see [Storage](self#storage)). The other
+/// functions that register destructors panic in the same way.
+#[track_caller]
+pub fn register_dtor(f: fn()) -> DtorHandle {
+	register_dtor_with_priority(0, f)
+}
+
+/// Register a destructor, or return an error if the thread's destructors have
+/// already been run or there's no room for it.
+///
+/// Otherwise this works the same as [`register_dtor`].
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// if wintls::dtor::try_register_dtor(|| println!("Goodbye Thread!")).is_err() {
+///     // Clean up now instead.
+/// }
+/// # }
+/// ```
+pub fn try_register_dtor(f: fn()) -> Result<DtorHandle, RegisterError> {
+	unsafe { try_register(f as *mut u8, Call::Fn, None, None, 0) }
+}
+
+/// Register a destructor, and pin the module that `f` is in so that it's never
+/// unloaded.
+///
+/// This is the alternative to having the destructor skipped if its DLL is
+/// unloaded before the thread exits (see [Limitations](self#limitations)).
+/// The trade-off is that the DLL can't be unloaded at all. `FreeLibrary` still
+/// succeeds, but the DLL stays loaded until the process exits and is never
+/// reloaded. Its thread locals, including its destructors when it has its own
+/// copy of this crate, keep working on every thread.
+///
+/// The module is only pinned the first time a destructor is registered from it.
+/// Otherwise this works the same as [`register_dtor`].
+///
+/// # Panics
+///
+/// Panics if `f` isn't in a module, as well as when [`register_dtor`] does.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// // In a plugin that may be unloaded while the host's threads are running.
+/// wintls::dtor::register_dtor_pinned(|| println!("closing the plugin's log"));
+/// # }
+/// ```
+#[track_caller]
+pub fn register_dtor_pinned(f: fn()) -> DtorHandle {
+	assert!(
+		unload::pin(f as usize),
+		"the destructor's module couldn't be pinned"
+	);
+	register_dtor(f)
+}
+
+/// Register a destructor that runs before the destructors with a lower
+/// priority.
+///
+/// Destructors with the same priority are run in the thread's [`Order`].
+/// Otherwise this works the same as [`register_dtor`], which uses a priority of
+/// 0.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// std::thread::spawn(|| {
+///     wintls::dtor::register_dtor_with_priority(-1, || println!("closing the log"));
+///     wintls::dtor::register_dtor(|| println!("flushing buffers to the log"));
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+#[track_caller]
+pub fn register_dtor_with_priority(priority: i8, f: fn()) -> DtorHandle {
+	unsafe { register(f as *mut u8, Call::Fn, None, None, priority) }
+}
+
+/// Register a destructor that drops the value at `ptr` in place.
+///
+/// # Safety
+///
+/// `ptr` must point to a valid `T` when the destructors are run, and nothing
+/// else may drop it. So it must point into thread local storage or a leaked
+/// allocation. The same caveat about stale pointers applies as for
+/// [`register_dtor_with`].
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// wintls::unsafe_local!{
+///     static NAME: String = String::new();
+/// }
+///
+/// # fn main() {
+/// std::thread::spawn(|| unsafe {
+///     NAME.with_mut(|name| name.push_str("worker"));
+///     wintls::dtor::register_drop(NAME.as_ptr());
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+#[track_caller]
+pub unsafe fn register_drop<T>(ptr: *mut T) -> DtorHandle {
+	unsafe fn drop<T>(ptr: *mut u8) {
+		core::ptr::drop_in_place(ptr.cast::<T>());
+	}
+	debug_assert!(!ptr.is_null(), "`register_drop` was given a null pointer");
+	register(ptr.cast(), Call::Rust(drop::<T>), None, None, 0)
+}
+
+/// Register a closure as a destructor for this thread.
+///
+/// This is for destructors that need some context. Otherwise it works the same
+/// as [`register_dtor`], and the closure can register more destructors.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// std::thread::spawn(|| {
+///     let name = String::from("worker");
+///     wintls::dtor::register_dtor_boxed(Box::new(move || println!("Goodbye {name}!")));
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[track_caller]
+pub fn register_dtor_boxed(f: Box<dyn FnOnce() + 'static>) -> DtorHandle {
+	unsafe fn call(f: *mut u8) {
+		let f = Box::from_raw(f.cast::<Box<dyn FnOnce()>>());
+		f()
+	}
+	unsafe fn free(f: *mut u8) {
+		drop(Box::from_raw(f.cast::<Box<dyn FnOnce()>>()));
+	}
+	// The closure is boxed again to get a thin pointer.
+	let f = Box::into_raw(Box::new(f));
+	unsafe { register(f.cast(), Call::Rust(call), Some(free), None, 0) }
+}
+
+/// Register a destructor that's passed `data` when it's run.
+///
+/// This allows one function to be used for many thread locals, e.g. by passing
+/// it the address of the thread local. Otherwise it works the same as
+/// [`register_dtor`].
+///
+/// A pointer to a static thread local goes stale if the thread locals are
+/// reallocated (see [`UnsafeLocal`](crate::UnsafeLocal)'s notes on stale
+/// pointers). So if libraries are loaded after it's registered, the pointer
+/// may not point to the current copy of the data.
+///
+/// # Safety
+///
+/// `f` must be safe to call with `data` when the destructors are run.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// wintls::unsafe_local!{
+///     static A: Vec<u8> = Vec::new();
+///     static B: Vec<u8> = Vec::new();
+/// }
+///
+/// unsafe extern "C" fn drop_vec(data: *mut u8) {
+///     core::ptr::drop_in_place(data.cast::<Vec<u8>>());
+/// }
+///
+/// # fn main() {
+/// std::thread::spawn(|| unsafe {
+///     A.with_mut(|a| a.push(1));
+///     B.with_mut(|b| b.push(2));
+///     wintls::dtor::register_dtor_with(A.as_ptr().cast(), drop_vec);
+///     wintls::dtor::register_dtor_with(B.as_ptr().cast(), drop_vec);
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+#[track_caller]
+pub unsafe fn register_dtor_with(data: *mut u8, f: unsafe extern "C" fn(*mut u8)) -> DtorHandle {
+	register(data, Call::C(f), None, None, 0)
+}
+
+/// Register a destructor as part of a group identified by `key`.
+///
+/// The group can be run early with [`run_dtors_for_key`], or removed with
+/// [`remove_dtors_for_key`], without affecting other destructors. Otherwise it
+/// works the same as [`register_dtor`].
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// const PLUGIN: usize = 1;
+///
+/// # fn main() {
+/// wintls::dtor::register_dtor_keyed(PLUGIN, || println!("plugin unloaded"));
+/// // When the plugin is reloaded.
+/// wintls::dtor::run_dtors_for_key(PLUGIN);
+/// # }
+/// ```
+#[track_caller]
+pub fn register_dtor_keyed(key: usize, f: fn()) -> DtorHandle {
+	unsafe { register(f as *mut u8, Call::Fn, None, Some(key), 0) }
+}
+
+/// Runs the current thread's destructors that were registered with `key`, in
+/// the order they'd be run when the thread exits.
+///
+/// They're removed first, so they won't be run again when the thread exits.
+/// Destructors registered while they run are kept. If any panic, the first
+/// panic is resumed after they've all run.
+pub fn run_dtors_for_key(key: usize) {
+	let registered = NEXT_ID.get();
+	let mut panic = None;
+	while let Some(dtor) = unsafe { take_keyed(key, registered) } {
+		if let Some(f) = dtor.runnable() {
+			if let Err(payload) = unwind::catch(|| unsafe { f.call(dtor.data) }) {
+				unwind::keep_first(&mut panic, payload);
+			}
+		}
+	}
+	if let Some(panic) = panic {
+		unwind::resume(panic);
+	}
+}
+
+/// Removes the current thread's destructors that were registered with `key`
+/// without running them.
+pub fn remove_dtors_for_key(key: usize) {
+	let registered = NEXT_ID.get();
+	while let Some(dtor) = unsafe { take_keyed(key, registered) } {
+		if let Dtor {
+			f: Some(_),
+			free: Some(free),
+			data,
+			..
+		} = dtor
+		{
+			unsafe { free(data) };
+		}
+	}
+}
+
+/// Removes the next destructor to run for `key`, out of the first `registered`
+/// destructors registered by the thread.
+unsafe fn take_keyed(key: usize, registered: u64) -> Option<Dtor> {
+	if is_done() {
+		return None;
+	}
+	let list = DESTRUCTORS.as_ref_mut();
+	let i = list.iter().rposition(|dtor| {
+		let count = match ORDER.get() {
+			Order::Lifo => dtor.id,
+			Order::Fifo => !dtor.id,
+		};
+		dtor.key == Some(key) && count < registered
+	})?;
+	Some(list.remove(i))
+}
+
+#[track_caller]
+unsafe fn register(
+	data: *mut u8,
+	f: Call,
+	free: Option<unsafe fn(*mut u8)>,
+	key: Option<usize>,
+	priority: i8,
+) -> DtorHandle {
+	match try_register(data, f, free, key, priority) {
+		Ok(handle) => handle,
+		Err(error) => {
+			// The destructor would never be run.
+			if let Some(free) = free {
+				free(data);
+			}
+			panic!("{error}")
+		}
+	}
+}
+
+unsafe fn try_register(
+	data: *mut u8,
+	f: Call,
+	free: Option<unsafe fn(*mut u8)>,
+	key: Option<usize>,
+	priority: i8,
+) -> Result<DtorHandle, RegisterError> {
+	// The list has been freed.
+	if is_done() {
+		return Err(RegisterError { full: false });
+	}
+	let count = NEXT_ID.get();
+	NEXT_ID.set(count + 1);
+	let id = match ORDER.get() {
+		Order::Lifo => count,
+		Order::Fifo => !count,
+	};
+	let dtor = Dtor {
+		priority,
+		batch: BATCH.get(),
+		id,
+		data,
+		f: Some(f),
+		free,
+		key,
+		module: unload::module_of(f.address(data)),
+	};
+	#[cfg(feature = "std")]
+	exit_drain::record_thread();
+	let list = DESTRUCTORS.as_ref_mut();
+	// Usually this is the end of the list.
+	let i = list.partition_point(|other| other.order() < dtor.order());
+	let handle = DtorHandle {
+		order: dtor.order(),
+		thread: crate::thread_token::current(),
+	};
+	match list.try_insert(i, dtor) {
+		Ok(()) => Ok(handle),
+		Err(_) => Err(RegisterError { full: true }),
+	}
+}
+
+/// A destructor that can be registered without allocating, with
+/// [`register_dtor_node`].
+///
+/// The node is linked into a list, so it can only be registered once at a
+/// time. It's unlinked just before it's run, and can be registered again
+/// after that.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::dtor::{self, DtorNode};
+///
+/// wintls::unsafe_local!{
+///     static NODE: DtorNode = DtorNode::new(|_| println!("Goodbye Thread!"), core::ptr::null_mut());
+/// }
+///
+/// # fn main() {
+/// std::thread::spawn(|| {
+///     // The node lives as long as the thread, and isn't used by anything
+///     // else.
+///     let node = unsafe { &mut *NODE.as_ptr() };
+///     dtor::register_dtor_node(node).unwrap();
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+pub struct DtorNode {
+	f: fn(*mut u8),
+	data: *mut u8,
+	next: *mut DtorNode,
+	linked: bool,
+}
+// A node is only used by the thread it's registered on.
+unsafe impl Send for DtorNode {}
+impl DtorNode {
+	/// Creates a node that calls `f` with `data` when it's run.
+	pub const fn new(f: fn(*mut u8), data: *mut u8) -> Self {
+		Self {
+			f,
+			data,
+			next: core::ptr::null_mut(),
+			linked: false,
+		}
+	}
+
+	/// Returns `true` if the node is registered and hasn't been run yet.
+	pub fn is_registered(&self) -> bool {
+		self.linked
+	}
+}
+impl fmt::Debug for DtorNode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("DtorNode")
+			.field("data", &self.data)
+			.field("registered", &self.linked)
+			.finish_non_exhaustive()
+	}
 }
 
+/// Register a destructor node for the current thread, without allocating.
+///
+/// This does nothing if the node is already registered. Returns an error if
+/// the thread's destructors have already been run. See [Registering Without
+/// Allocating](self#registering-without-allocating).
+pub fn register_dtor_node(node: &'static mut DtorNode) -> Result<(), RegisterError> {
+	if is_done() {
+		return Err(RegisterError { full: false });
+	}
+	if !node.linked {
+		node.linked = true;
+		node.next = NODES.get().0;
+		NODES.set(NodePtr(node));
+	}
+	Ok(())
+}
+
+/// Runs or discards a pass of nodes, returning how many there were.
+unsafe fn drain_nodes(head: *mut DtorNode, run: bool, panic: &mut Option<unwind::Panic>) -> usize {
+	let mut count = 0;
+	RUNNING_NODES.set(NodePtr(head));
+	while let Some(node) = RUNNING_NODES.get().0.as_mut() {
+		// The node may be registered again while it runs.
+		RUNNING_NODES.set(NodePtr(node.next));
+		node.next = core::ptr::null_mut();
+		node.linked = false;
+		count += 1;
+		if run {
+			let (f, data) = (node.f, node.data);
+			if let Err(payload) = unwind::catch(|| f(data)) {
+				unwind::keep_first(panic, payload);
+			}
+		}
+	}
+	count
+}
+
+/// The error returned by [`try_register_dtor`] when the thread's destructors
+/// have already been run, or the inline list of destructors is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterError {
+	full: bool,
+}
+impl RegisterError {
+	/// Returns `true` if the destructor couldn't be registered because the
+	/// inline list is full (see [Storage](self#storage)).
+	pub fn is_full(&self) -> bool {
+		self.full
+	}
+}
+impl fmt::Display for RegisterError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.full {
+			write!(
+				f,
+				"the thread already has {INLINE_CAPACITY} destructors, which is as many as can be stored"
+			)
+		} else {
+			f.write_str("a destructor was registered after the thread's destructors were run")
+		}
+	}
+}
+#[cfg(feature = "std")]
+impl std::error::Error for RegisterError {}
+
+/// A registered destructor, which can be cancelled.
+///
+/// Dropping the handle doesn't cancel the destructor. A [`DtorGuard`] does,
+/// and runs it instead.
+#[derive(Debug)]
+pub struct DtorHandle {
+	order: (i8, Reverse<u32>, u64),
+	thread: u64,
+}
+impl DtorHandle {
+	/// Stops the destructor from being run.
+	///
+	/// This does nothing if the destructor has already run, or is running.
+	///
+	/// # Panics
+	///
+	/// Panics if this isn't the thread that registered the destructor.
+	pub fn cancel(self) {
+		self.remove();
+	}
+
+	fn remove(&self) {
+		assert_eq!(
+			self.thread,
+			crate::thread_token::current(),
+			"a destructor can only be cancelled by the thread that registered it"
+		);
+		if is_done() {
+			return;
+		}
+		let cancelled = unsafe { cancel(DESTRUCTORS.as_ref_mut(), self.order) };
+		if let Some(Dtor {
+			data,
+			free: Some(free),
+			..
+		}) = cancelled
+		{
+			unsafe { free(data) };
+		}
+	}
+}
+
+/// Runs a closure when it's dropped, or when the thread exits if it's still
+/// alive then, but never both.
+///
+/// The closure is registered as a destructor, which is cancelled when the
+/// guard is dropped. If the destructor runs first, dropping the guard does
+/// nothing. A guard that's leaked, or kept in a thread local that's dropped
+/// after the destructor has run, still runs the closure exactly once.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::dtor::DtorGuard;
+///
+/// # fn main() {
+/// std::thread::spawn(|| {
+///     let guard = DtorGuard::new(|| println!("cleaned up"));
+///     // Runs when the thread exits instead of at the end of the scope.
+///     core::mem::forget(guard);
+/// })
+/// .join()
+/// .unwrap();
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub struct DtorGuard {
+	// Owned by the guard. The destructor only takes the closure out of it.
+	f: *mut Option<Box<dyn FnOnce()>>,
+	handle: DtorHandle,
+}
+#[cfg(feature = "std")]
+impl DtorGuard {
+	/// Registers `f` to be run when the guard is dropped or the thread exits.
+	///
+	/// # Panics
+	///
+	/// Panics if the thread's destructors have already run, or can't be
+	/// registered.
+	#[track_caller]
+	pub fn new(f: impl FnOnce() + 'static) -> Self {
+		unsafe fn call(f: *mut u8) {
+			// The guard may be dropped while the closure runs, so the closure
+			// is taken out first.
+			if let Some(f) = (*f.cast::<Option<Box<dyn FnOnce()>>>()).take() {
+				f()
+			}
+		}
+		let f: *mut Option<Box<dyn FnOnce()>> = Box::into_raw(Box::new(Some(Box::new(f))));
+		match unsafe { try_register(f.cast(), Call::Rust(call), None, None, 0) } {
+			Ok(handle) => Self { f, handle },
+			Err(error) => {
+				drop(unsafe { Box::from_raw(f) });
+				panic!("{error}")
+			}
+		}
+	}
+}
+#[cfg(feature = "std")]
+impl Drop for DtorGuard {
+	fn drop(&mut self) {
+		// The guard can't be sent to another thread, so this is the thread that
+		// registered it.
+		self.handle.remove();
+		let f = unsafe { Box::from_raw(self.f) };
+		if let Some(f) = *f {
+			f()
+		}
+	}
+}
+#[cfg(feature = "std")]
+impl fmt::Debug for DtorGuard {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("DtorGuard").finish_non_exhaustive()
+	}
+}
+
+/// Marks the destructor as cancelled, returning a copy of it.
+fn cancel(list: &mut list::List, order: (i8, Reverse<u32>, u64)) -> Option<Dtor> {
+	let i = list.binary_search_by_key(&order, Dtor::order).ok()?;
+	let dtor = &mut list[i];
+	let f = dtor.f.take()?;
+	let cancelled = Dtor {
+		priority: dtor.priority,
+		batch: dtor.batch,
+		id: dtor.id,
+		data: dtor.data,
+		f: Some(f),
+		free: dtor.free,
+		key: dtor.key,
+		module: dtor.module,
+	};
+	// Cancelled destructors at the end would be skipped next, so remove them
+	// now.
+	while list.last().is_some_and(|dtor| dtor.f.is_none()) {
+		list.pop();
+	}
+	Some(cancelled)
+}
+
+// The callback is found through the TLS directory. That's included by the
+// thread locals the destructors are stored in, like any other thread local, so
+// a binary that never registers a destructor doesn't get one.
 #[link_section = ".CRT$XLB"]
 #[doc(hidden)]
 #[used]
 pub static TLS_CALLBACK: unsafe extern "system" fn(*mut i8, u32, *mut i8) = tls_callback;
-extern "system" fn tls_callback(_: *mut i8, reason: u32, _: *mut i8) {
+extern "system" fn tls_callback(_: *mut i8, reason: u32, reserved: *mut i8) {
 	const DLL_THREAD_DETACH: u32 = 3;
 	const DLL_PROCESS_DETACH: u32 = 0;
 
 	if reason == DLL_THREAD_DETACH || reason == DLL_PROCESS_DETACH {
 		unsafe {
 			STATE.set(DtorState::Dropping);
-			drop_locals_internal();
+			// The reserved pointer is null if a DLL is being unloaded, rather
+			// than the process exiting, so the other threads are still
+			// running. Anything they register is run with this thread's
+			// destructors.
+			#[cfg(feature = "std")]
+			if reason == DLL_PROCESS_DETACH && !reserved.is_null() {
+				exit_drain::drain_other_threads();
+			}
+			if let Some(panic) = drop_locals_internal() {
+				unwind::report(panic);
+			}
+			// The table isn't used again once the process is exiting, and
+			// its lock may be held by a terminated thread.
+			#[cfg(feature = "std")]
+			if reason == DLL_THREAD_DETACH {
+				exit_drain::remove_thread();
+			}
+			// This module is being unloaded.
+			if reason == DLL_PROCESS_DETACH && reserved.is_null() {
+				unload::detach();
+			}
 			// The thread local memory is never used after this point.
 			DESTRUCTORS.drop_value();
+			STATE.set(DtorState::Done);
+		}
+	}
+}
+
+// `exit` runs the `atexit` handlers while the CRT and the rest of the process
+// are still intact, which isn't true by the time the TLS callbacks are run for
+// `DLL_PROCESS_DETACH`. This initializer registers the handler at startup,
+// before `main`.
+//
+// Without `std` there may not be a CRT to run the initializer or provide
+// `atexit`. In a DLL the CRT runs the `atexit` handlers when the DLL is
+// unloaded, on whichever thread unloads it, so the handler is only registered
+// by the EXE.
+#[cfg(feature = "std")]
+#[link_section = ".CRT$XCU"]
+#[doc(hidden)]
+#[used]
+pub static EXIT_HOOK: unsafe extern "C" fn() = register_exit_hook;
+#[cfg(feature = "std")]
+unsafe extern "C" fn register_exit_hook() {
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetModuleHandleW(name: *const u16) -> *const u8;
+	}
+	extern "C" {
+		static __ImageBase: u8;
+		fn atexit(f: unsafe extern "C" fn()) -> i32;
+	}
+	if GetModuleHandleW(core::ptr::null()) == core::ptr::addr_of!(__ImageBase) {
+		atexit(exit_hook);
+	}
+}
+#[cfg(feature = "std")]
+unsafe extern "C" fn exit_hook() {
+	// `exit` was called by a destructor, which is already draining the list.
+	if !matches!(STATE.get(), DtorState::Passive) {
+		return;
+	}
+	STATE.set(DtorState::Dropping);
+	if let Some(panic) = drop_locals_internal() {
+		unwind::report(panic);
+	}
+	// Each destructor is removed from the list before it's run, so the TLS
+	// callback only runs those registered after this.
+	STATE.set(DtorState::Passive);
+}
+
+/// Runs the destructors, returning the first panic.
+unsafe fn drop_locals_internal() -> Option<unwind::Panic> {
+	let max_passes = MAX_PASSES.load(Ordering::Relaxed);
+	let mut panic = None;
+	let mut discarded = 0_usize;
+	let mut node_passes = 0;
+	loop {
+		// The nodes are taken a pass at a time, so that the nodes can register
+		// more for the next pass.
+		while !NODES.get().0.is_null() {
+			let NodePtr(head) = NODES.replace(NodePtr(core::ptr::null_mut()));
+			let run = node_passes < max_passes;
+			node_passes += 1;
+			let count = drain_nodes(head, run, &mut panic);
+			if !run {
+				discarded += count;
+			}
+		}
+		if DESTRUCTORS.as_ref_mut().is_empty() {
+			break;
+		}
+		// Each destructor is removed before it's run so the destructors are
+		// free to register more, which are sorted into the rest of the list.
+		// Each batch is a pass.
+		while let Some(dtor) = DESTRUCTORS.as_ref_mut().pop() {
+			if dtor.batch >= max_passes {
+				// Freeing the data could register more destructors, so it's
+				// leaked.
+				discarded += usize::from(dtor.f.is_some());
+				continue;
+			}
+			BATCH.set(dtor.batch + 1);
+			if let Some(f) = dtor.runnable() {
+				if let Err(payload) = unwind::catch(|| f.call(dtor.data)) {
+					unwind::keep_first(&mut panic, payload);
+				}
+			}
+		}
+		BATCH.set(0);
+	}
+	GENERATION.set(GENERATION.get().wrapping_add(1));
+	if cfg!(debug_assertions) && discarded > 0 {
+		let result = unwind::catch(|| {
+			panic!("{discarded} thread local destructors were discarded after {max_passes} passes")
+		});
+		if let Err(payload) = result {
+			unwind::keep_first(&mut panic, payload);
+		}
+	}
+	panic
+}
+
+// Destructors can be registered by other modules, e.g. by a DLL that's given a
+// function that calls `register_dtor_with`. Their code goes away when that
+// module is unloaded, so they must not be run after that.
+#[cfg(feature = "std")]
+mod unload {
+	use core::ffi::c_void;
+	use core::ptr;
+	use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+	use std::sync::{Mutex, Once, PoisonError};
+
+	pub struct Module {
+		base: usize,
+		unloaded: AtomicBool,
+		pinned: AtomicBool,
+	}
+	impl Module {
+		#[inline]
+		pub fn is_unloaded(&self) -> bool {
+			self.unloaded.load(Ordering::Acquire)
+		}
+	}
+
+	// The other modules that have registered destructors and haven't been
+	// unloaded. They're leaked so that they can be checked without a lock.
+	static MODULES: Mutex<Vec<&'static Module>> = Mutex::new(Vec::new());
+	static WATCH: Once = Once::new();
+	static PINNED: AtomicBool = AtomicBool::new(false);
+	static COOKIE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+	const GET_MODULE_HANDLE_EX_FLAG_PIN: u32 = 1;
+	const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 4;
+	const LDR_DLL_NOTIFICATION_REASON_UNLOADED: u32 = 2;
+
+	#[repr(C)]
+	struct NotificationData {
+		flags: u32,
+		full_dll_name: *const c_void,
+		base_dll_name: *const c_void,
+		dll_base: *mut c_void,
+		size_of_image: u32,
+	}
+	type NotificationFn = unsafe extern "system" fn(u32, *const NotificationData, *mut c_void);
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetModuleHandleExW(flags: u32, name: *const u16, module: *mut *mut c_void) -> i32;
+		fn FreeLibrary(module: *mut c_void) -> i32;
+	}
+	#[link(name = "ntdll")]
+	extern "system" {
+		fn LdrRegisterDllNotification(
+			flags: u32,
+			f: NotificationFn,
+			context: *mut c_void,
+			cookie: *mut *mut c_void,
+		) -> i32;
+		fn LdrUnregisterDllNotification(cookie: *mut c_void) -> i32;
+	}
+	extern "C" {
+		static __ImageBase: u8;
+	}
+
+	/// Returns the module that `address` is in, unless it's this module or
+	/// isn't in a module at all.
+	pub fn module_of(address: usize) -> Option<&'static Module> {
+		if in_this_module(address) {
+			return None;
+		}
+		// This keeps the module loaded until it's in the list, so that its
+		// unload can't be missed.
+		let mut handle = ptr::null_mut();
+		let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS;
+		if unsafe { GetModuleHandleExW(flags, address as *const u16, &mut handle) } == 0 {
+			return None;
+		}
+		WATCH.call_once(|| unsafe {
+			let mut cookie = ptr::null_mut();
+			if LdrRegisterDllNotification(0, notify, ptr::null_mut(), &mut cookie) >= 0 {
+				COOKIE.store(cookie, Ordering::Relaxed);
+			}
+		});
+		let module = {
+			// The loader lock isn't taken while this is locked, as `notify`
+			// is called with it held.
+			let mut modules = MODULES.lock().unwrap_or_else(PoisonError::into_inner);
+			match modules.iter().find(|module| module.base == handle as usize) {
+				Some(module) => *module,
+				None => {
+					let module: &Module = Box::leak(Box::new(Module {
+						base: handle as usize,
+						unloaded: AtomicBool::new(false),
+						pinned: AtomicBool::new(false),
+					}));
+					modules.push(module);
+					module
+				}
+			}
+		};
+		unsafe { FreeLibrary(handle) };
+		Some(module)
+	}
+
+	/// Pins the module that `address` is in, unless it's already pinned.
+	/// Returns `false` if it isn't in a module.
+	pub fn pin(address: usize) -> bool {
+		let pinned = match module_of(address) {
+			Some(module) => &module.pinned,
+			None if in_this_module(address) => &PINNED,
+			None => return false,
+		};
+		if pinned.swap(true, Ordering::AcqRel) {
+			return true;
+		}
+		let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_PIN;
+		let mut handle = ptr::null_mut();
+		let pinned_now =
+			unsafe { GetModuleHandleExW(flags, address as *const u16, &mut handle) } != 0;
+		if !pinned_now {
+			pinned.store(false, Ordering::Release);
+		}
+		pinned_now
+	}
+
+	fn in_this_module(address: usize) -> bool {
+		unsafe {
+			let base = ptr::addr_of!(__ImageBase);
+			// `SizeOfImage` is at the same offset in 32 and 64 bit PE headers.
+			let pe = base.add(base.add(0x3c).cast::<u32>().read_unaligned() as usize);
+			let size = pe.add(80).cast::<u32>().read_unaligned() as usize;
+			address.wrapping_sub(base as usize) < size
+		}
+	}
+
+	unsafe extern "system" fn notify(reason: u32, data: *const NotificationData, _: *mut c_void) {
+		if reason != LDR_DLL_NOTIFICATION_REASON_UNLOADED {
+			return;
+		}
+		let base = (*data).dll_base as usize;
+		let mut modules = MODULES.lock().unwrap_or_else(PoisonError::into_inner);
+		modules.retain(|module| {
+			if module.base == base {
+				module.unloaded.store(true, Ordering::Release);
+			}
+			module.base != base
+		});
+	}
+
+	/// Stops watching for unloads, as `notify` is about to be unloaded along
+	/// with this module.
+	pub fn detach() {
+		let cookie = COOKIE.swap(ptr::null_mut(), Ordering::Relaxed);
+		if !cookie.is_null() {
+			unsafe { LdrUnregisterDllNotification(cookie) };
 		}
 	}
 }
-unsafe fn drop_locals_internal() {
-	// As noted in the docs, this is potentially an infinite loop.
-	// It's currently up to users of this API to prevent that.
-	while let Some(dtor) = DESTRUCTORS.as_ref_mut().pop() {
-		(dtor)();
+
+// Other modules' destructors aren't tracked, but their modules can be pinned.
+#[cfg(not(feature = "std"))]
+mod unload {
+	pub enum Module {}
+	impl Module {
+		pub fn is_unloaded(&self) -> bool {
+			match *self {}
+		}
+	}
+
+	#[inline(always)]
+	pub fn module_of(_: usize) -> Option<&'static Module> {
+		None
+	}
+
+	pub fn detach() {}
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetModuleHandleExW(flags: u32, name: *const u16, module: *mut *mut u8) -> i32;
+	}
+
+	// Pinning a module again does nothing, so this isn't tracked.
+	pub fn pin(address: usize) -> bool {
+		// `GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_PIN`
+		let flags = 4 | 1;
+		let mut handle = core::ptr::null_mut();
+		unsafe { GetModuleHandleExW(flags, address as *const u16, &mut handle) != 0 }
+	}
+}
+
+/// Runs the destructors of every other thread that's still registered when
+/// the process exits.
+///
+/// **This runs code on behalf of threads that have been terminated**, possibly
+/// in the middle of using the data their destructors use, or in the middle of
+/// registering a destructor. It's only appropriate for destructors that flush
+/// buffers or close handles, and must not be relied on.
+///
+/// After this is called, each thread that registers a destructor adds its list
+/// of destructors to a table shared by all threads, and removes it when it
+/// exits normally. When the process exits, `ExitProcess` terminates the other
+/// threads without running their destructors. Then the TLS callback for
+/// `DLL_PROCESS_DETACH` runs the destructors of the threads left in the table,
+/// one thread at a time, before those of the exiting thread. This isn't done
+/// when a DLL is unloaded, as the other threads are still running.
+///
+/// The destructors are run on the exiting thread, so any thread locals they
+/// use are the exiting thread's. Destructors they register are run with the
+/// exiting thread's. Nodes registered with [`register_dtor_node`] aren't run.
+/// If a terminated thread was holding the table's lock, no other thread's
+/// destructors are run.
+///
+/// The table is updated when a thread registers a destructor after its thread
+/// locals have been reallocated. If they're reallocated after its last
+/// registration, its destructors are run from the stale copy of the list (see
+/// [`UnsafeLocal`](crate::UnsafeLocal)'s notes on stale pointers), which may be
+/// out of date.
+///
+/// This can't be turned off again. It should be called at startup, as threads
+/// that registered their destructors before it was called aren't added to the
+/// table until they register another.
+///
+/// # Safety
+///
+/// From the point this is called, for as long as the process runs:
+///
+/// * No thread may be terminated while it's updating data that any thread's
+///   destructors use, including while it's registering a destructor. In
+///   particular, the process must not exit while another thread is doing so.
+/// * Every destructor registered by any thread must be safe to run on a
+///   different thread, as if it were [`Send`], and must not rely on the
+///   thread locals of the thread that registered it.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// // Safety: the destructor only prints and the thread only waits.
+/// unsafe { wintls::dtor::enable_process_exit_drain() };
+/// std::thread::spawn(|| {
+///     wintls::dtor::register_dtor(|| println!("flushing the log"));
+///     // Runs the destructor if the process exits first.
+///     loop {
+///         std::thread::park();
+///     }
+/// });
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub unsafe fn enable_process_exit_drain() {
+	exit_drain::ENABLED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(feature = "std")]
+mod exit_drain {
+	use super::{list, unwind, DESTRUCTORS};
+	use core::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::{Mutex, PoisonError, TryLockError};
+
+	pub static ENABLED: AtomicBool = AtomicBool::new(false);
+
+	// The destructor lists of the threads that have registered destructors.
+	static THREADS: Mutex<Vec<ThreadList>> = Mutex::new(Vec::new());
+	struct ThreadList {
+		thread: u32,
+		list: *mut list::List,
+	}
+	// The lists are only used by other threads once their threads are gone.
+	unsafe impl Send for ThreadList {}
+
+	crate::static_thread_local! {
+		// The list in the table, if any.
+		static RECORDED: ListPtr = ListPtr(core::ptr::null_mut());
+	}
+	#[derive(Clone, Copy)]
+	struct ListPtr(*mut list::List);
+	unsafe impl Send for ListPtr {}
+
+	#[inline]
+	pub fn record_thread() {
+		// The list moves if the thread locals are reallocated, so this is
+		// checked every time.
+		let list = DESTRUCTORS.as_ptr();
+		if RECORDED.get().0 != list && ENABLED.load(Ordering::Relaxed) {
+			record_thread_slow(list);
+		}
+	}
+
+	#[cold]
+	fn record_thread_slow(list: *mut list::List) {
+		RECORDED.set(ListPtr(list));
+		let thread = crate::raw_internal::current_thread_id();
+		let mut threads = THREADS.lock().unwrap_or_else(PoisonError::into_inner);
+		threads.retain(|list| list.thread != thread);
+		threads.push(ThreadList { thread, list });
+	}
+
+	pub fn remove_thread() {
+		if !RECORDED.replace(ListPtr(core::ptr::null_mut())).0.is_null() {
+			let thread = crate::raw_internal::current_thread_id();
+			let mut threads = THREADS.lock().unwrap_or_else(PoisonError::into_inner);
+			threads.retain(|list| list.thread != thread);
+		}
+	}
+
+	/// Runs the destructors of the other threads in the table.
+	pub unsafe fn drain_other_threads() {
+		if !ENABLED.load(Ordering::Relaxed) {
+			return;
+		}
+		let thread = crate::raw_internal::current_thread_id();
+		// A terminated thread may never release the lock.
+		let lists = match THREADS.try_lock() {
+			Ok(mut threads) => core::mem::take(&mut *threads),
+			Err(TryLockError::Poisoned(error)) => core::mem::take(&mut *error.into_inner()),
+			Err(TryLockError::WouldBlock) => return,
+		};
+		let mut panic = None;
+		for other in lists {
+			if other.thread == thread {
+				continue;
+			}
+			while let Some(dtor) = (*other.list).pop() {
+				if let Some(f) = dtor.runnable() {
+					if let Err(payload) = unwind::catch(|| f.call(dtor.data)) {
+						unwind::keep_first(&mut panic, payload);
+					}
+				}
+			}
+		}
+		if let Some(panic) = panic {
+			unwind::report(panic);
+		}
+	}
+}
+
+#[cfg(all(feature = "std", panic = "unwind"))]
+mod unwind {
+	use std::io::Write;
+	use std::panic::{self, AssertUnwindSafe};
+
+	pub type Panic = Box<dyn core::any::Any + Send>;
+
+	#[inline]
+	pub fn catch(f: impl FnOnce()) -> Result<(), Panic> {
+		panic::catch_unwind(AssertUnwindSafe(f))
+	}
+
+	pub fn keep_first(first: &mut Option<Panic>, payload: Panic) {
+		match first {
+			None => *first = Some(payload),
+			// Dropping the payload could panic again.
+			Some(_) => core::mem::forget(payload),
+		}
+	}
+
+	pub fn resume(panic: Panic) -> ! {
+		panic::resume_unwind(panic)
+	}
+
+	pub fn report(panic: Panic) {
+		// The panic hook has already printed the message.
+		let _ = writeln!(
+			std::io::stderr(),
+			"a thread local destructor panicked while the thread was exiting"
+		);
+		core::mem::forget(panic);
+	}
+}
+
+// Panics abort, so there's nothing to catch.
+#[cfg(not(all(feature = "std", panic = "unwind")))]
+mod unwind {
+	pub type Panic = core::convert::Infallible;
+
+	#[inline(always)]
+	pub fn catch(f: impl FnOnce()) -> Result<(), Panic> {
+		f();
+		Ok(())
+	}
+
+	pub fn keep_first(_: &mut Option<Panic>, payload: Panic) {
+		match payload {}
+	}
+
+	pub fn resume(panic: Panic) -> ! {
+		match panic {}
+	}
+
+	pub fn report(panic: Panic) {
+		match panic {}
 	}
 }
 
@@ -107,8 +1712,23 @@ unsafe fn drop_locals_internal() {
 /// storage.
 ///
 /// [1]: https://docs.microsoft.com/en-us/windows/win32/procthread/fibers
+///
+/// Afterwards the state is [`DtorState::Passive`] again, so destructors can
+/// still be registered. This does nothing if the state is
+/// [`DtorState::Done`].
+///
+/// # Panics
+///
+/// If any destructors panic, the first panic is resumed after all the
+/// destructors have run.
 pub unsafe fn drop_locals() {
+	if is_done() {
+		return;
+	}
 	STATE.set(DtorState::Dropping);
-	drop_locals_internal();
+	let panic = drop_locals_internal();
 	STATE.set(DtorState::Passive);
+	if let Some(panic) = panic {
+		unwind::resume(panic);
+	}
 }
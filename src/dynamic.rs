@@ -0,0 +1,280 @@
+//! Thread locals created at runtime.
+//!
+//! These use the `TlsAlloc` family of functions instead of static TLS.
+
+use crate::borrow::BorrowGuard;
+use crate::sentinel::{is_value, ResetOnUnwind, DESTROYED, INITIALIZING};
+use crate::AccessError;
+use core::ffi::c_void;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+const TLS_OUT_OF_INDEXES: u32 = u32::MAX;
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn TlsAlloc() -> u32;
+	fn TlsGetValue(index: u32) -> *mut c_void;
+	fn TlsSetValue(index: u32, value: *mut c_void) -> i32;
+}
+
+/// The values of every `DynamicThreadLocal` that's been used on this thread.
+struct Entry {
+	index: u32,
+	owner: u64,
+	drop: unsafe fn(*mut Header),
+}
+crate::unsafe_local!(
+	static VALUES: Vec<Entry> = Vec::new();
+);
+crate::static_thread_local! {
+	static REGISTERED: bool = false;
+}
+
+// Indexes are reused after they're freed, so each `DynamicThreadLocal` also
+// gets a unique id. That way a thread's list of values can't be confused by an
+// index that's been freed and then allocated again.
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(0);
+
+// The indexes of dropped `DynamicThreadLocal`s. These are never given back
+// with `TlsFree`, because other threads may still have values in them. If the
+// index could be allocated by someone else then those threads would mistake
+// whatever was stored in it for a `Header`. Instead they're only reused by
+// this module, so anything in them is either null, a marker or a `Header`.
+static FREE: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+#[repr(C)]
+struct Header {
+	owner: u64,
+	borrow: isize,
+}
+#[repr(C)]
+struct Slot<T> {
+	header: Header,
+	value: T,
+}
+
+/// A thread local with a key that's allocated at runtime.
+///
+/// Each `DynamicThreadLocal` allocates a TLS index with `TlsAlloc`. When it's
+/// dropped the index is kept for the next `DynamicThreadLocal` instead of
+/// being freed. A thread's value is boxed the first time the thread uses it
+/// and dropped when the thread exits.
+///
+/// Borrows are always checked, so calling [`with_mut`](Self::with_mut) while
+/// the value is borrowed panics, as does any access while it's mutably
+/// borrowed.
+///
+/// # Limits
+///
+/// A process has 64 TLS indexes that are stored directly in each thread's
+/// environment block, plus 1024 expansion slots. The expansion slots are
+/// allocated for a thread the first time it sets one, which can fail if
+/// there's no memory. Static thread locals don't use TLS indexes, other than
+/// one for each module.
+///
+/// # Drop
+///
+/// Dropping a `DynamicThreadLocal` drops the current thread's value. The
+/// values of any other threads are leaked, including when a thread uses a new
+/// `DynamicThreadLocal` that's been given the same index.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::DynamicThreadLocal;
+///
+/// # fn main() {
+/// let names = DynamicThreadLocal::new(|| vec!["main"]).unwrap();
+/// names.with_mut(|names| names.push("other"));
+/// assert_eq!(names.with(|names| names.len()), 2);
+/// # }
+/// ```
+pub struct DynamicThreadLocal<T: 'static> {
+	index: u32,
+	owner: u64,
+	init: Box<dyn Fn() -> T + Send + Sync>,
+}
+// Each thread only uses its own value.
+unsafe impl<T: Send> Sync for DynamicThreadLocal<T> {}
+unsafe impl<T: Send> Send for DynamicThreadLocal<T> {}
+impl<T> DynamicThreadLocal<T> {
+	/// Allocates a TLS index. Each thread's value is created by `init`.
+	///
+	/// Returns an error if no TLS indexes are available.
+	pub fn new(init: impl Fn() -> T + Send + Sync + 'static) -> Result<Self, OutOfIndexesError> {
+		let free = FREE.lock().unwrap_or_else(PoisonError::into_inner).pop();
+		let index = match free {
+			Some(index) => index,
+			None => unsafe { TlsAlloc() },
+		};
+		if index == TLS_OUT_OF_INDEXES {
+			return Err(OutOfIndexesError);
+		}
+		Ok(Self {
+			index,
+			owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
+			init: Box::new(init),
+		})
+	}
+
+	/// Returns the TLS index.
+	#[inline]
+	pub fn index(&self) -> u32 {
+		self.index
+	}
+
+	/// Calls `f` with a reference to the value, creating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, if it's being dropped or has
+	/// been dropped, or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a mutable reference to the value, creating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, if it's being dropped or has been
+	/// dropped, or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		match self.slot() {
+			Ok(slot) => unsafe {
+				let _guard = BorrowGuard::exclusive(core::ptr::addr_of_mut!((*slot).header.borrow));
+				f(&mut (*slot).value)
+			},
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the value, creating it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, or if this is called by the
+	/// initializer.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		let slot = self.slot()?;
+		unsafe {
+			let _guard = BorrowGuard::shared(core::ptr::addr_of_mut!((*slot).header.borrow));
+			Ok(f(&(*slot).value))
+		}
+	}
+
+	fn slot(&self) -> Result<*mut Slot<T>, AccessError> {
+		let ptr = unsafe { TlsGetValue(self.index) };
+		match ptr as usize {
+			0 => Ok(self.initialize()),
+			INITIALIZING => panic!("a `DynamicThreadLocal` was accessed by its initializer"),
+			DESTROYED => Err(AccessError::new(core::any::type_name::<Self>())),
+			// A value left by a dropped `DynamicThreadLocal` with the same index.
+			_ if unsafe { (*ptr.cast::<Header>()).owner } != self.owner => Ok(self.initialize()),
+			_ => Ok(ptr.cast()),
+		}
+	}
+
+	#[cold]
+	fn initialize(&self) -> *mut Slot<T> {
+		// Once this succeeds the thread's expansion slots, if needed, have been
+		// allocated.
+		if unsafe { TlsSetValue(self.index, INITIALIZING as *mut c_void) } == 0 {
+			panic!("failed to allocate TLS expansion slots");
+		}
+		let reset = ResetOnUnwind(|| unsafe {
+			TlsSetValue(self.index, core::ptr::null_mut());
+		});
+		let value = (self.init)();
+		core::mem::forget(reset);
+		let slot = Box::into_raw(Box::new(Slot {
+			header: Header {
+				owner: self.owner,
+				borrow: 0,
+			},
+			value,
+		}));
+		unsafe {
+			TlsSetValue(self.index, slot.cast());
+			VALUES.as_ref_mut().push(Entry {
+				index: self.index,
+				owner: self.owner,
+				drop: drop_slot::<T>,
+			});
+		}
+		if !REGISTERED.get() {
+			REGISTERED.set(true);
+			crate::dtor::register_dtor(drop_values);
+		}
+		slot
+	}
+}
+impl<T> Drop for DynamicThreadLocal<T> {
+	fn drop(&mut self) {
+		unsafe {
+			let ptr = TlsGetValue(self.index);
+			if is_value(ptr) && (*ptr.cast::<Header>()).owner == self.owner {
+				TlsSetValue(self.index, DESTROYED as *mut c_void);
+				drop_slot::<T>(ptr.cast());
+			}
+			TlsSetValue(self.index, core::ptr::null_mut());
+		}
+		FREE.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.push(self.index);
+	}
+}
+
+unsafe fn drop_slot<T>(header: *mut Header) {
+	drop(Box::from_raw(header.cast::<Slot<T>>()))
+}
+
+/// Drops the current thread's values.
+fn drop_values() {
+	unsafe {
+		// Dropping a value may create others.
+		loop {
+			let values = VALUES.take();
+			if values.is_empty() {
+				break;
+			}
+			for entry in values.into_iter().rev() {
+				let ptr = TlsGetValue(entry.index).cast::<Header>();
+				// The local may have been dropped, and its index reused.
+				if !is_value(ptr) || (*ptr).owner != entry.owner {
+					continue;
+				}
+				TlsSetValue(entry.index, DESTROYED as *mut c_void);
+				(entry.drop)(ptr);
+			}
+		}
+	}
+	// Anything used by later destructors will need dropping again.
+	REGISTERED.set(false);
+}
+
+/// The error returned by [`DynamicThreadLocal::new`] and
+/// [`FlsLocal::new`](crate::FlsLocal::new) when no indexes are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfIndexesError;
+impl fmt::Display for OutOfIndexesError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("no TLS or FLS indexes are available")
+	}
+}
+impl std::error::Error for OutOfIndexesError {}
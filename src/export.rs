@@ -0,0 +1,171 @@
+//! Sharing thread locals with other languages.
+
+/// Exports `extern "C"` functions to get and set thread locals.
+///
+/// By default the functions are called `wintls_get_`*name* and
+/// `wintls_set_`*name*, where *name* is the name of the static. Other names
+/// can be given in parentheses. The thread locals must have been declared with
+/// [`static_thread_local`](crate::static_thread_local) and their types should
+/// be FFI-safe.
+///
+/// The functions are never inlined. Code in another module (e.g. a DLL) that
+/// inlined the TLS lookup would use its own module's thread locals, but
+/// calling the function always uses the module the thread local was declared
+/// in. A panic (e.g. if the thread local is borrowed) can't unwind out of the
+/// functions.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     static COUNTER: u32 = 0;
+///     static DEPTH: i32 = 0;
+/// }
+///
+/// wintls::export_thread_locals!{
+///     // `wintls_get_COUNTER` and `wintls_set_COUNTER`.
+///     COUNTER,
+///     DEPTH(get = "get_depth", set = "set_depth"),
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The functions can then be declared in C:
+///
+/// ```c
+/// uint32_t wintls_get_COUNTER(void);
+/// void wintls_set_COUNTER(uint32_t value);
+/// int32_t get_depth(void);
+/// void set_depth(int32_t value);
+/// ```
+#[macro_export]
+macro_rules! export_thread_locals {
+	(@export $local:ident $get:expr, $set:expr) => {
+		const _: () = {
+			#[export_name = $get]
+			#[inline(never)]
+			extern "C" fn get() -> <$local as $crate::StaticKey>::Value {
+				$local.get()
+			}
+
+			#[export_name = $set]
+			#[inline(never)]
+			extern "C" fn set(value: <$local as $crate::StaticKey>::Value) {
+				$local.set(value)
+			}
+		};
+	};
+	(@munch) => {};
+	(@munch $local:ident (get = $get:literal, set = $set:literal) $(, $($rest:tt)*)?) => {
+		$crate::export_thread_locals!(@export $local $get, $set);
+		$crate::export_thread_locals!(@munch $($($rest)*)?);
+	};
+	(@munch $local:ident $(, $($rest:tt)*)?) => {
+		$crate::export_thread_locals!(@export $local
+			concat!("wintls_get_", stringify!($local)),
+			concat!("wintls_set_", stringify!($local))
+		);
+		$crate::export_thread_locals!(@munch $($($rest)*)?);
+	};
+	($($input:tt)*) => {
+		$crate::export_thread_locals!(@munch $($input)*);
+	};
+}
+
+/// Declares a thread local that's defined in C or C++.
+///
+/// This gives a [`StaticThreadLocal`](crate::StaticThreadLocal) handle for a
+/// `__declspec(thread)` or `thread_local` variable defined elsewhere. By
+/// default the symbol has the same name as the static. A different name (e.g.
+/// a mangled C++ name) can be given with `#[link_name = "..."]`. The x86
+/// underscore prefix is added automatically.
+///
+/// The variable must be defined in the same module (i.e. the same EXE or DLL)
+/// as the Rust code. Thread locals can't be imported from another module.
+///
+/// Since the initial value isn't known to Rust,
+/// [`reset`](crate::StaticThreadLocal::reset) fails to compile. Borrows made
+/// with `with` and `with_mut` are only tracked on the Rust side.
+///
+/// # Safety
+///
+/// Each static must be written as `unsafe extern static`, because the macro
+/// can't check the foreign definition. The handle's safe accessors are only
+/// sound if:
+///
+/// * The symbol is a thread local variable (not an ordinary global) defined
+///   in the same module as the Rust code.
+/// * Its type has the same size, alignment and layout as the Rust type, and
+///   any value the foreign code stores is a valid value of the Rust type.
+/// * Foreign code doesn't keep a pointer to the variable that it uses while
+///   Rust code on the same thread is accessing it.
+///
+/// # Example
+///
+/// ```no_run
+/// #![feature(asm)]
+///
+/// // In C: `__declspec(thread) int32_t g_depth = 0;`
+/// wintls::extern_thread_local!{
+///     #[link_name = "g_depth"]
+///     unsafe extern static DEPTH: i32;
+/// }
+///
+/// fn main() {
+///     DEPTH.set(DEPTH.get() + 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! extern_thread_local {
+	(@local [$($link:tt)*] $(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty;) => {
+		$(#[$attr])*
+		#[doc(hidden)]
+		#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+		$vis struct $name {}
+
+		$(#[$attr])*
+		const _: () = {
+			unsafe impl $crate::StaticKey for $name {
+				type Value = $ty;
+				const INIT: $ty = panic!(concat!(
+					"thread local `",
+					stringify!($name),
+					"` is defined in foreign code so its initial value isn't known",
+				));
+
+				#[inline(always)]
+				fn key() -> u32 {
+					extern "C" {
+						$($link)*
+						#[allow(non_upper_case_globals)]
+						static $name: $ty;
+					}
+					unsafe { $crate::static_key!($name) }
+				}
+
+				$crate::static_thread_local!{ @borrow_fn }
+			}
+		};
+
+		$(#[$attr])*
+		$vis static $name: $crate::StaticThreadLocal<$ty, $name> = $crate::StaticThreadLocal::new();
+	};
+	// `link_name` is picked out of the attributes and applied to the extern
+	// static instead.
+	(@attrs [$($attr:tt)*] [$($link:tt)*] #[link_name = $link_name:literal] $($rest:tt)*) => {
+		$crate::extern_thread_local!{ @attrs [$($attr)*] [#[link_name = $link_name]] $($rest)* }
+	};
+	(@attrs [$($attr:tt)*] [$($link:tt)*] #[$meta:meta] $($rest:tt)*) => {
+		$crate::extern_thread_local!{ @attrs [$($attr)* #[$meta]] [$($link)*] $($rest)* }
+	};
+	(@attrs [$($attr:tt)*] [$($link:tt)*] $vis:vis unsafe extern static $name:ident: $ty:ty; $($rest:tt)*) => {
+		$crate::extern_thread_local!{ @local [$($link)*] $($attr)* $vis static $name: $ty; }
+		$crate::extern_thread_local!{ $($rest)* }
+	};
+	() => {};
+	($($rest:tt)+) => {
+		$crate::extern_thread_local!{ @attrs [] [] $($rest)+ }
+	};
+}
@@ -0,0 +1,172 @@
+//! A thread local struct with an accessor for each field.
+
+use crate::{borrow, StaticKey};
+use core::marker::PhantomData;
+
+/// Declares a struct thread local with an accessor for each field.
+///
+/// This creates a struct with the given name and fields, along with a single
+/// thread local of that type. The thread local's handle has a [`StaticField`]
+/// for each field. These read or write only that field, without copying the
+/// rest of the struct. Keeping related thread locals in one struct also keeps
+/// them close together in memory.
+///
+/// The fields must be [`Copy`]. Attributes and visibility on the fields are
+/// applied to both the struct's fields and the handle's fields.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local_struct!{
+///     /// Per-thread scheduler context.
+///     pub static CTX: Context {
+///         pub scheduler: usize = 0,
+///         pub ticks: u64 = 0,
+///         flags: u8 = 0,
+///     }
+/// }
+///
+/// fn main() {
+///     CTX.ticks.set(CTX.ticks.get() + 1);
+///     let context: Context = CTX.get();
+///     assert_eq!(context.ticks, 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! static_thread_local_struct {
+	(
+		$(#[$attr:meta])*
+		$vis:vis static $name:ident: $struct:ident {
+			$(
+				$(#[$field_attr:meta])*
+				$field_vis:vis $field:ident: $field_ty:ty = $field_value:expr
+			),+ $(,)?
+		}
+	) => {
+		$(#[$attr])*
+		#[derive(Clone, Copy)]
+		$vis struct $struct {
+			$(
+				$(#[$field_attr])*
+				$field_vis $field: $field_ty,
+			)+
+		}
+
+		$(#[$attr])*
+		#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+		$vis struct $name {
+			$(
+				$(#[$field_attr])*
+				$field_vis $field: $crate::StaticField<
+					$field_ty,
+					$name,
+					{ ::core::mem::offset_of!($struct, $field) },
+				>,
+			)+
+		}
+		// The attributes are also applied here so that the checks and impls are
+		// removed if the static is `cfg`'d out.
+		$(#[$attr])*
+		const _: () = {
+			$(
+				$crate::static_thread_local!{ @assert_no_drop $name.$field: $field_ty }
+			)+
+
+			unsafe impl $crate::StaticKey for $name {
+				type Value = $struct;
+				// Doc comments on the fields are carried here along with `cfg`s.
+				#[allow(unused_doc_comments)]
+				const INIT: $struct = $struct {
+					$(
+						$(#[$field_attr])*
+						$field: $field_value,
+					)+
+				};
+
+				$crate::static_thread_local!{ @key_fns $name [] }
+			}
+			impl $name {
+				/// Returns a copy of the whole struct.
+				#[inline(always)]
+				#[allow(dead_code)]
+				$vis fn get(&self) -> $struct {
+					$crate::StaticThreadLocal::<$struct, $name>::new().get()
+				}
+
+				/// Sets the whole struct.
+				#[inline(always)]
+				#[allow(dead_code)]
+				$vis fn set(&self, value: $struct) {
+					$crate::StaticThreadLocal::<$struct, $name>::new().set(value)
+				}
+			}
+		};
+
+		// Safety: `offset_of!` gives the offset of a field of `$field_ty`.
+		$(#[$attr])*
+		#[allow(unused_doc_comments)]
+		$vis static $name: $name = $name {
+			$(
+				$(#[$field_attr])*
+				$field: unsafe { $crate::StaticField::new() },
+			)+
+		};
+	};
+}
+
+/// A field of a thread local declared with
+/// [`static_thread_local_struct`](crate::static_thread_local_struct).
+///
+/// `K` identifies the thread local and `OFFSET` is the offset of the field
+/// within it.
+pub struct StaticField<T, K, const OFFSET: usize> {
+	_marker: PhantomData<(T, K)>,
+}
+// A field is only ever read or written in the current thread's copy of the
+// struct. `T` must be `Send` because each thread starts with a copy of the
+// initial value.
+unsafe impl<T: Send, K, const OFFSET: usize> Sync for StaticField<T, K, OFFSET> {}
+unsafe impl<T: Send, K, const OFFSET: usize> Send for StaticField<T, K, OFFSET> {}
+impl<T: Copy, K: StaticKey, const OFFSET: usize> StaticField<T, K, OFFSET> {
+	/// Creates a handle for the field at `OFFSET` in `K`'s thread local.
+	///
+	/// This is used by
+	/// [`static_thread_local_struct`](crate::static_thread_local_struct).
+	///
+	/// # Safety
+	///
+	/// * `OFFSET` plus the size of `T` must be within the size of `K::Value`.
+	/// * `OFFSET` must be a multiple of the alignment of `T`.
+	/// * The bytes at `OFFSET` in `K::Value` must be a field of type `T`.
+	#[doc(hidden)]
+	pub const unsafe fn new() -> Self {
+		Self {
+			_marker: PhantomData,
+		}
+	}
+
+	/// Returns a pointer to the field for the current thread.
+	///
+	/// The same caveats apply as for
+	/// [`StaticThreadLocal::get_ptr`](crate::StaticThreadLocal::get_ptr).
+	#[inline(always)]
+	pub fn get_ptr(&self) -> *mut T {
+		unsafe { K::ptr().cast::<u8>().add(OFFSET).cast() }
+	}
+
+	/// Returns the value of the field.
+	#[inline(always)]
+	pub fn get(&self) -> T {
+		borrow::check_read(K::borrow());
+		unsafe { *self.get_ptr() }
+	}
+
+	/// Sets the value of the field.
+	#[inline(always)]
+	pub fn set(&self, value: T) {
+		borrow::check_write(K::borrow());
+		unsafe { *self.get_ptr() = value }
+	}
+}
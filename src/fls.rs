@@ -0,0 +1,241 @@
+//! Fiber local storage.
+
+use crate::borrow::BorrowGuard;
+use crate::sentinel::{is_value, ResetOnUnwind, DESTROYED, INITIALIZING};
+use crate::{AccessError, OutOfIndexesError};
+use core::ffi::c_void;
+
+const FLS_OUT_OF_INDEXES: u32 = u32::MAX;
+
+type FlsCallback = unsafe extern "system" fn(*mut c_void);
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn FlsAlloc(callback: Option<FlsCallback>) -> u32;
+	fn FlsFree(index: u32) -> i32;
+	fn FlsGetValue(index: u32) -> *mut c_void;
+	fn FlsSetValue(index: u32, value: *mut c_void) -> i32;
+}
+
+/// Another name for [`FlsLocal`], for finding it by what it does.
+///
+/// Static thread locals are shared by every fiber on a thread. An `FlsLocal`
+/// gives each fiber its own value, which is dropped when the fiber is deleted.
+pub type FiberLocal<T> = FlsLocal<T>;
+
+struct Slot<T> {
+	index: u32,
+	borrow: isize,
+	value: T,
+}
+
+/// A fiber local with a key that's allocated at runtime.
+///
+/// This works like [`DynamicThreadLocal`](crate::DynamicThreadLocal) but uses
+/// the `FlsAlloc` family of functions. Each fiber has its own value, and a
+/// thread that doesn't use fibers has one value for the whole thread.
+///
+/// The differences from static thread locals are:
+///
+/// * Access is slower as it's a function call into the OS.
+/// * The OS drops the values itself when a fiber is deleted or a thread exits.
+///   There's no need to call [`register_dtor`](crate::dtor::register_dtor),
+///   and values are dropped even if the thread was created before the
+///   `FlsLocal` or the DLL that uses it.
+/// * Dropping the `FlsLocal` drops the values of every thread and fiber.
+///
+/// As with `DynamicThreadLocal`, borrows are always checked and the number of
+/// FLS indexes is limited. A value that panics while being dropped aborts the
+/// process.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::FlsLocal;
+///
+/// # fn main() {
+/// let names = FlsLocal::new(|| vec!["main"]).unwrap();
+/// names.with_mut(|names| names.push("other"));
+/// assert_eq!(names.with(|names| names.len()), 2);
+/// # }
+/// ```
+pub struct FlsLocal<T: Send + 'static> {
+	index: u32,
+	init: Box<dyn Fn() -> T + Send + Sync>,
+}
+// Each fiber only uses its own value, and values may be dropped by the thread
+// that drops the `FlsLocal`.
+unsafe impl<T: Send> Sync for FlsLocal<T> {}
+unsafe impl<T: Send> Send for FlsLocal<T> {}
+impl<T: Send> FlsLocal<T> {
+	/// Allocates an FLS index. Each fiber's value is created by `init`.
+	///
+	/// Returns an error if no FLS indexes are available.
+	pub fn new(init: impl Fn() -> T + Send + Sync + 'static) -> Result<Self, OutOfIndexesError> {
+		let index = unsafe { FlsAlloc(Some(drop_slot::<T>)) };
+		if index == FLS_OUT_OF_INDEXES {
+			return Err(OutOfIndexesError);
+		}
+		Ok(Self {
+			index,
+			init: Box::new(init),
+		})
+	}
+
+	/// Returns the FLS index.
+	#[inline]
+	pub fn index(&self) -> u32 {
+		self.index
+	}
+
+	/// Calls `f` with a reference to the value, creating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, if it's being dropped or has
+	/// been dropped, or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a mutable reference to the value, creating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, if it's being dropped or has been
+	/// dropped, or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		match self.slot() {
+			Ok(slot) => unsafe {
+				let _guard = BorrowGuard::exclusive(core::ptr::addr_of_mut!((*slot).borrow));
+				f(&mut (*slot).value)
+			},
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the value, creating it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, or if this is called by the
+	/// initializer.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		let slot = self.slot()?;
+		unsafe {
+			let _guard = BorrowGuard::shared(core::ptr::addr_of_mut!((*slot).borrow));
+			Ok(f(&(*slot).value))
+		}
+	}
+
+	fn slot(&self) -> Result<*mut Slot<T>, AccessError> {
+		let ptr = unsafe { FlsGetValue(self.index) };
+		match ptr as usize {
+			0 => Ok(self.initialize()),
+			INITIALIZING => panic!("an `FlsLocal` was accessed by its initializer"),
+			DESTROYED => Err(AccessError::new(core::any::type_name::<Self>())),
+			_ => Ok(ptr.cast()),
+		}
+	}
+
+	/// Sets the value for the current fiber, dropping the old value.
+	///
+	/// The initializer isn't called if there's no value yet.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, if it's being dropped or has been
+	/// dropped, or if this is called by the initializer.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::FiberLocal;
+	/// # fn main() {
+	/// let id = FiberLocal::new(|| 0).unwrap();
+	/// id.set(5);
+	/// assert_eq!(id.with(|id| *id), 5);
+	/// # }
+	/// ```
+	#[track_caller]
+	pub fn set(&self, value: T) {
+		let ptr = unsafe { FlsGetValue(self.index) };
+		match ptr as usize {
+			0 => {
+				self.store(value);
+			}
+			INITIALIZING => panic!("an `FlsLocal` was accessed by its initializer"),
+			DESTROYED => AccessError::new(core::any::type_name::<Self>()).panic(),
+			_ => unsafe {
+				let slot = ptr.cast::<Slot<T>>();
+				let _guard = BorrowGuard::exclusive(core::ptr::addr_of_mut!((*slot).borrow));
+				(*slot).value = value;
+			},
+		}
+	}
+
+	#[cold]
+	fn initialize(&self) -> *mut Slot<T> {
+		// This also makes sure that `store` can't fail.
+		if unsafe { FlsSetValue(self.index, INITIALIZING as *mut c_void) } == 0 {
+			panic!("failed to allocate FLS slots");
+		}
+		let reset = ResetOnUnwind(|| unsafe {
+			FlsSetValue(self.index, core::ptr::null_mut());
+		});
+		let value = (self.init)();
+		core::mem::forget(reset);
+		self.store(value)
+	}
+
+	fn store(&self, value: T) -> *mut Slot<T> {
+		let slot = Box::into_raw(Box::new(Slot {
+			index: self.index,
+			borrow: 0,
+			value,
+		}));
+		unsafe {
+			if FlsSetValue(self.index, slot.cast()) == 0 {
+				drop(Box::from_raw(slot));
+				panic!("failed to allocate FLS slots");
+			}
+		}
+		slot
+	}
+}
+impl<T: Send> Drop for FlsLocal<T> {
+	fn drop(&mut self) {
+		// This runs the callback for every value.
+		unsafe { FlsFree(self.index) };
+	}
+}
+
+/// The FLS callback.
+unsafe extern "system" fn drop_slot<T>(data: *mut c_void) {
+	if !is_value(data) {
+		return;
+	}
+	let slot = data.cast::<Slot<T>>();
+	// If this is the current fiber's value then any later use, e.g. by another
+	// callback, must not see the freed value. When the index is being freed,
+	// the values of other fibers are dropped on this thread.
+	let index = (*slot).index;
+	if FlsGetValue(index) == data {
+		FlsSetValue(index, DESTROYED as *mut c_void);
+	}
+	drop(Box::from_raw(slot));
+}
@@ -0,0 +1,162 @@
+//! Per-thread buffers that are flushed to a shared sink.
+
+use crate::RegisteredLocal;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+/// Declares [`FlushLocal`]s.
+///
+/// The expression is the number of values each thread buffers before they're
+/// flushed.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// use std::sync::Mutex;
+///
+/// static RECORDED: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+///
+/// wintls::flush_local!{
+///     static EVENTS: u64 = 1024;
+/// }
+///
+/// fn main() {
+///     EVENTS.set_sink(|events| RECORDED.lock().unwrap().append(events));
+///     std::thread::spawn(|| EVENTS.push(1)).join().unwrap();
+///     assert_eq!(*RECORDED.lock().unwrap(), [1]);
+/// }
+/// ```
+#[macro_export]
+macro_rules! flush_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $capacity:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::FlushLocal<$ty> = {
+			static SINK: ::std::sync::OnceLock<fn(&mut ::std::vec::Vec<$ty>)> =
+				::std::sync::OnceLock::new();
+			// Shadows the handle so that errors use its name.
+			$crate::registered_local!{
+				static $name: $crate::FlushSlot<$ty> = $crate::FlushSlot::new(&SINK, $capacity);
+			}
+			$crate::FlushLocal::new(&$name, &SINK, $capacity)
+		};
+	)+};
+}
+
+/// A thread's buffer.
+#[doc(hidden)]
+pub struct FlushSlot<T: 'static> {
+	buffer: Mutex<Vec<T>>,
+	sink: &'static OnceLock<fn(&mut Vec<T>)>,
+}
+impl<T> FlushSlot<T> {
+	pub fn new(sink: &'static OnceLock<fn(&mut Vec<T>)>, capacity: usize) -> Self {
+		Self {
+			buffer: Mutex::new(Vec::with_capacity(capacity)),
+			sink,
+		}
+	}
+
+	fn buffer(&self) -> MutexGuard<'_, Vec<T>> {
+		// The sink may have panicked, but the buffer is still usable.
+		self.buffer.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+impl<T> Drop for FlushSlot<T> {
+	fn drop(&mut self) {
+		let buffer = self
+			.buffer
+			.get_mut()
+			.unwrap_or_else(PoisonError::into_inner);
+		flush(self.sink, buffer);
+	}
+}
+
+/// A per-thread buffer of values that are handed to a sink in batches.
+///
+/// Each thread's buffer is allocated the first time it's used. It's flushed
+/// when it fills up, when [`flush`](Self::flush) or
+/// [`flush_all`](Self::flush_all) is called, and when the thread exits.
+///
+/// The sink is set once with [`set_sink`](Self::set_sink). Values flushed
+/// before then are dropped. The sink is given the values in the order they
+/// were pushed by one thread, and should take them out of the `Vec`. Any that
+/// are left are dropped. It mustn't push to the same `FlushLocal`.
+///
+/// # Synchronization
+///
+/// Each thread's buffer has its own lock, so that
+/// [`flush_all`](Self::flush_all) can flush it from another thread. It's only
+/// contended while `flush_all` is running.
+pub struct FlushLocal<T: Send + 'static> {
+	slots: &'static RegisteredLocal<FlushSlot<T>>,
+	sink: &'static OnceLock<fn(&mut Vec<T>)>,
+	capacity: usize,
+}
+impl<T: Send> FlushLocal<T> {
+	#[doc(hidden)]
+	pub const fn new(
+		slots: &'static RegisteredLocal<FlushSlot<T>>,
+		sink: &'static OnceLock<fn(&mut Vec<T>)>,
+		capacity: usize,
+	) -> Self {
+		Self {
+			slots,
+			sink,
+			capacity,
+		}
+	}
+
+	/// Sets the sink that the buffers are flushed to.
+	///
+	/// Returns `false` if the sink has already been set.
+	pub fn set_sink(&self, sink: fn(&mut Vec<T>)) -> bool {
+		self.sink.set(sink).is_ok()
+	}
+
+	/// Adds `value` to the current thread's buffer, flushing it if it's full.
+	///
+	/// After the thread's buffer has been dropped, the value is flushed on its
+	/// own.
+	#[inline]
+	pub fn push(&self, value: T) {
+		let mut value = Some(value);
+		let _ = self.slots.try_with(|slot| {
+			let mut buffer = slot.buffer();
+			buffer.extend(value.take());
+			if buffer.len() >= self.capacity {
+				flush(self.sink, &mut buffer);
+			}
+		});
+		if let Some(value) = value {
+			flush(self.sink, &mut vec![value]);
+		}
+	}
+
+	/// Flushes the current thread's buffer.
+	pub fn flush(&self) {
+		let _ = self
+			.slots
+			.try_with(|slot| flush(self.sink, &mut slot.buffer()));
+	}
+
+	/// Flushes the buffer of every thread that's using this local, including
+	/// threads that are still running.
+	///
+	/// This is meant for shutdown, for threads that won't exit before the
+	/// process does.
+	pub fn flush_all(&self) {
+		self.slots
+			.for_each(|slot| flush(self.sink, &mut slot.buffer()));
+	}
+}
+
+#[cold]
+fn flush<T>(sink: &OnceLock<fn(&mut Vec<T>)>, buffer: &mut Vec<T>) {
+	if buffer.is_empty() {
+		return;
+	}
+	if let Some(sink) = sink.get() {
+		sink(buffer);
+	}
+	buffer.clear();
+}
@@ -0,0 +1,75 @@
+//! Detecting stale pointers to thread locals.
+
+use crate::raw_internal::{module_block, tls_array};
+
+/// Checks that the current thread's TLS array hasn't been reallocated.
+///
+/// Loading a DLL that uses static thread locals can make the loader allocate
+/// a new TLS array for each thread, leaving any pointers to thread locals
+/// pointing at the old copy. See the "Stale Pointers" section of
+/// [`UnsafeLocal`](crate::UnsafeLocal).
+///
+/// A guard records the TLS array and this module's block when it's created.
+/// [`check`](Self::check) panics if either has changed. In debug builds this
+/// is also checked when the guard is dropped, so a guard can be kept alongside
+/// a pointer for as long as the pointer is used.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// wintls::unsafe_local!{
+///     static COUNT: u32 = 0;
+/// }
+/// # fn main() {
+/// let guard = COUNT.guard();
+/// let ptr = COUNT.as_ptr();
+/// // ...
+/// guard.check();
+/// unsafe { *ptr += 1 };
+/// # }
+/// ```
+pub struct TlsGuard {
+	array: *mut *mut u8,
+	block: *mut u8,
+}
+impl TlsGuard {
+	/// Records the current thread's TLS array.
+	#[allow(clippy::new_without_default)]
+	#[inline]
+	pub fn new() -> Self {
+		Self {
+			array: tls_array(),
+			block: unsafe { module_block() },
+		}
+	}
+
+	/// Returns `true` if the TLS array is the same as when the guard was
+	/// created.
+	#[inline]
+	pub fn is_current(&self) -> bool {
+		self.array == tls_array() && self.block == unsafe { module_block() }
+	}
+
+	/// Panics if the TLS array has been reallocated since the guard was
+	/// created.
+	#[inline]
+	pub fn check(&self) {
+		if !self.is_current() {
+			reallocated();
+		}
+	}
+}
+impl Drop for TlsGuard {
+	fn drop(&mut self) {
+		#[cfg(debug_assertions)]
+		if !crate::panicking() {
+			self.check();
+		}
+	}
+}
+
+#[cold]
+fn reallocated() -> ! {
+	panic!("TLS array was reallocated while a pointer was held")
+}
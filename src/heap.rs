@@ -0,0 +1,227 @@
+//! A thread local that keeps its value on the heap.
+
+use crate::borrow::BorrowGuard;
+use crate::sentinel::{is_value, ResetOnUnwind, DESTROYED, INITIALIZING};
+use crate::AccessError;
+
+/// Declares thread locals with a [`HeapLocal`] handle.
+///
+/// Only a pointer and a borrow flag are stored in TLS. The value is boxed by
+/// the initializer closure the first time it's used on each thread, and
+/// dropped when that thread exits. A thread that never uses the local doesn't
+/// allocate.
+///
+/// As with [`local`](crate::local), the value can't be used on a thread once
+/// it's been dropped.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::heap_local!{
+///     static SCRATCH: [u8; 65536] = || [0; 65536];
+/// }
+///
+/// fn main() {
+///     SCRATCH.with_mut(|scratch| scratch[0] = 1);
+///     assert_eq!(SCRATCH.with(|scratch| scratch[0]), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! heap_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::HeapLocal<$ty> = {
+			// The storage is declared inside `get` so that the initializer can
+			// refer to the handle.
+			fn get() -> *mut $crate::HeapSlot<$ty> {
+				$crate::init_static!(
+					static $name: $crate::HeapSlot<$ty> = $crate::HeapSlot::NULL;
+				);
+				unsafe { $crate::static_ptr!($name) }
+			}
+			fn init() -> $ty {
+				($init)()
+			}
+			fn destroy() {
+				unsafe { $crate::HeapLocal::<$ty>::destroy(get) }
+			}
+			unsafe { $crate::HeapLocal::new(get, init, destroy, stringify!($name)) }
+		};
+	)+};
+}
+
+/// The TLS storage of a [`HeapLocal`].
+#[doc(hidden)]
+pub struct HeapSlot<T> {
+	ptr: *mut T,
+	borrow: isize,
+}
+impl<T> HeapSlot<T> {
+	pub const NULL: Self = Self {
+		ptr: core::ptr::null_mut(),
+		borrow: 0,
+	};
+}
+
+/// A handle to a thread local declared with [`heap_local`](crate::heap_local).
+///
+/// Borrows are always checked, so calling [`with_mut`](Self::with_mut) while
+/// the value is borrowed panics, as does any access while it's mutably
+/// borrowed.
+pub struct HeapLocal<T: 'static> {
+	get: fn() -> *mut HeapSlot<T>,
+	init: fn() -> T,
+	destroy: fn(),
+	name: &'static str,
+}
+// Each thread allocates its own value and keeps the pointer and borrow count
+// in its own slot. The value is freed by a destructor registered on that
+// thread. `T` must be `Send` because that destructor can be run by another
+// thread (see `dtor::enable_process_exit_drain`).
+unsafe impl<T: Send> Sync for HeapLocal<T> {}
+unsafe impl<T: Send> Send for HeapLocal<T> {}
+impl<T> HeapLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out as [`HeapSlot::NULL`] and is only used by
+	/// this handle. `destroy` must call [`destroy`](Self::destroy) with `get`.
+	#[doc(hidden)]
+	pub const unsafe fn new(
+		get: fn() -> *mut HeapSlot<T>,
+		init: fn() -> T,
+		destroy: fn(),
+		name: &'static str,
+	) -> Self {
+		Self {
+			get,
+			init,
+			destroy,
+			name,
+		}
+	}
+
+	/// Calls `f` with a reference to the value, allocating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, is being dropped or has been
+	/// dropped, or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a mutable reference to the value, allocating it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, is being dropped or has been dropped,
+	/// or if this is called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		match self.try_with_mut(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the value, allocating it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, or if this is called by the
+	/// initializer.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		let slot = self.slot()?;
+		unsafe {
+			let _guard =
+				BorrowGuard::shared_named(core::ptr::addr_of_mut!((*slot).borrow), Some(self.name));
+			Ok(f(&*(*slot).ptr))
+		}
+	}
+
+	/// Calls `f` with a mutable reference to the value, allocating it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, or if this is called by the
+	/// initializer.
+	#[inline]
+	pub fn try_with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, AccessError> {
+		let slot = self.slot()?;
+		unsafe {
+			let _guard = BorrowGuard::exclusive_named(
+				core::ptr::addr_of_mut!((*slot).borrow),
+				Some(self.name),
+			);
+			Ok(f(&mut *(*slot).ptr))
+		}
+	}
+
+	/// Returns `true` if the value has been allocated on the current thread.
+	#[inline]
+	pub fn is_initialized(&self) -> bool {
+		is_value(unsafe { (*(self.get)()).ptr })
+	}
+
+	#[inline]
+	fn slot(&self) -> Result<*mut HeapSlot<T>, AccessError> {
+		let slot = (self.get)();
+		unsafe {
+			match (*slot).ptr as usize {
+				0 => Ok(self.initialize()),
+				INITIALIZING => panic!(
+					"thread local `{}` was accessed while it was being initialized",
+					self.name
+				),
+				DESTROYED => Err(AccessError::new(self.name)),
+				_ => Ok(slot),
+			}
+		}
+	}
+
+	/// Drops the value.
+	///
+	/// # Safety
+	///
+	/// `get` must be the function passed to [`new`](Self::new). This must only
+	/// be called by the registered destructor.
+	#[doc(hidden)]
+	pub unsafe fn destroy(get: fn() -> *mut HeapSlot<T>) {
+		let slot = get();
+		let ptr = (*slot).ptr;
+		(*slot).ptr = DESTROYED as *mut T;
+		drop(Box::from_raw(ptr));
+	}
+
+	#[cold]
+	#[inline(never)]
+	unsafe fn initialize(&self) -> *mut HeapSlot<T> {
+		(*(self.get)()).ptr = INITIALIZING as *mut T;
+		let reset = ResetOnUnwind(|| (*(self.get)()).ptr = core::ptr::null_mut());
+		let value = Box::new((self.init)());
+		core::mem::forget(reset);
+
+		// The initializer may have loaded a library, which can move the thread
+		// locals, so the pointer needs to be looked up again.
+		let slot = (self.get)();
+		(*slot).ptr = Box::into_raw(value);
+		crate::dtor::register_dtor(self.destroy);
+		slot
+	}
+}
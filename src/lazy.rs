@@ -0,0 +1,189 @@
+//! Thread locals with a runtime initializer.
+
+use crate::sentinel::ResetOnUnwind;
+use crate::{borrow, StaticKey};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+pub(crate) const UNINIT: u8 = 0;
+pub(crate) const INITIALIZING: u8 = 1;
+pub(crate) const INIT: u8 = 2;
+// Only used by `LazyLocal` and `Local`, while the value is being dropped.
+pub(crate) const DROPPING: u8 = 3;
+// Only used by `LazyLocal` and `Local`, once the value has been dropped.
+pub(crate) const DESTROYED: u8 = 4;
+
+/// The storage for a lazy thread local.
+///
+/// The state is stored with the value so that both are in the same TLS block.
+#[doc(hidden)]
+pub struct LazyValue<T> {
+	pub(crate) state: u8,
+	pub(crate) value: MaybeUninit<T>,
+}
+impl<T> LazyValue<T> {
+	pub const UNINIT: Self = Self {
+		state: UNINIT,
+		value: MaybeUninit::uninit(),
+	};
+}
+
+/// Identifies a lazy thread local.
+///
+/// This is implemented by [`static_thread_local`](crate::static_thread_local)
+/// for locals declared with `lazy static`.
+///
+/// # Safety
+///
+/// The same requirements apply as for [`StaticKey`]. The thread local must
+/// start out uninitialized.
+pub unsafe trait LazyKey: StaticKey<Value = LazyValue<Self::Target>> {
+	/// The type of the value.
+	type Target;
+	/// The name of the static, used in panic messages.
+	const NAME: &'static str;
+
+	/// Creates the initial value for the current thread.
+	fn init() -> Self::Target;
+}
+
+/// A thread local that's initialized on first use.
+///
+/// The initializer runs once per thread, the first time the thread local is
+/// accessed. It's free to access other thread locals but accessing the same
+/// thread local will panic.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// fn seed_from_os() -> u64 {
+///     # 0x1234
+///     // ...
+/// }
+///
+/// wintls::static_thread_local!{
+///     lazy static SEED: u64 = seed_from_os();
+/// }
+///
+/// # fn main() {
+/// let seed = SEED.get();
+/// # }
+/// ```
+pub struct LazyThreadLocal<T, K> {
+	_marker: PhantomData<(T, K)>,
+}
+// Each thread runs the initializer for its own copy, and the value is never
+// dropped, so nothing is shared. `T` is still required to be `Send` so that
+// making a local `lazy` doesn't loosen the bounds of `StaticThreadLocal`.
+unsafe impl<T: Send, K> Sync for LazyThreadLocal<T, K> {}
+unsafe impl<T: Send, K> Send for LazyThreadLocal<T, K> {}
+impl<T, K: LazyKey<Target = T>> LazyThreadLocal<T, K> {
+	#[doc(hidden)]
+	#[allow(clippy::new_without_default)]
+	pub const fn new() -> Self {
+		Self {
+			_marker: PhantomData,
+		}
+	}
+
+	/// Returns a pointer to the current thread's value, initializing it if
+	/// necessary.
+	///
+	/// The same caveats apply as for
+	/// [`StaticThreadLocal::get_ptr`](crate::StaticThreadLocal::get_ptr).
+	#[inline(always)]
+	pub fn get_ptr(&self) -> *mut T {
+		let mut lazy = K::ptr();
+		unsafe {
+			if (*lazy).state != INIT {
+				lazy = Self::initialize(lazy);
+			}
+			(*lazy).value.as_mut_ptr()
+		}
+	}
+
+	/// Returns `true` if the thread local has been initialized on the current
+	/// thread.
+	#[inline(always)]
+	pub fn is_initialized(&self) -> bool {
+		unsafe { (*K::ptr()).state == INIT }
+	}
+
+	/// Returns the value of the thread local.
+	#[inline(always)]
+	pub fn get(&self) -> T
+	where
+		T: Copy,
+	{
+		let ptr = self.get_ptr();
+		borrow::check_read(K::borrow());
+		unsafe { *ptr }
+	}
+
+	/// Sets the value of the thread local.
+	///
+	/// If this is the first access on the current thread then the initializer
+	/// is run first.
+	#[inline(always)]
+	pub fn set(&self, value: T) {
+		let ptr = self.get_ptr();
+		borrow::check_write(K::borrow());
+		unsafe { ptr.write(value) }
+	}
+
+	/// Calls `f` with a reference to the thread local.
+	///
+	/// See [`StaticThreadLocal::with`](crate::StaticThreadLocal::with).
+	#[inline(always)]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		let ptr = self.get_ptr();
+		let _guard = borrow::BorrowGuard::shared(K::borrow());
+		unsafe { f(&*ptr) }
+	}
+
+	/// Calls `f` with a mutable reference to the thread local.
+	///
+	/// See [`StaticThreadLocal::with_mut`](crate::StaticThreadLocal::with_mut).
+	#[inline(always)]
+	pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		let ptr = self.get_ptr();
+		let _guard = borrow::BorrowGuard::exclusive(K::borrow());
+		unsafe { f(&mut *ptr) }
+	}
+
+	#[cold]
+	#[inline(never)]
+	unsafe fn initialize(lazy: *mut LazyValue<T>) -> *mut LazyValue<T> {
+		initialize(lazy, K::ptr, K::init, K::NAME)
+	}
+}
+
+/// Runs the initializer and stores the value.
+///
+/// Returns the new pointer to the value.
+pub(crate) unsafe fn initialize<T>(
+	lazy: *mut LazyValue<T>,
+	get: fn() -> *mut LazyValue<T>,
+	init: impl FnOnce() -> T,
+	name: &str,
+) -> *mut LazyValue<T> {
+	match (*lazy).state {
+		INITIALIZING => panic!("thread local `{name}` was accessed while it was being initialized"),
+		DROPPING => panic!("thread local `{name}` was accessed while it was being dropped"),
+		DESTROYED => panic!("thread local `{name}` was accessed after it was dropped"),
+		_ => {}
+	}
+	(*lazy).state = INITIALIZING;
+
+	let reset = ResetOnUnwind(|| (*get()).state = UNINIT);
+	let value = init();
+	core::mem::forget(reset);
+
+	// The initializer may have loaded a library, which can move the thread
+	// locals, so the pointer needs to be looked up again.
+	let lazy = get();
+	(*lazy).value = MaybeUninit::new(value);
+	(*lazy).state = INIT;
+	lazy
+}
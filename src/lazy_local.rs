@@ -0,0 +1,212 @@
+//! A thread local that's initialized on first use by a closure.
+
+use crate::lazy::{self, LazyValue, DESTROYED, DROPPING, INIT};
+use crate::AccessError;
+
+/// Declares thread locals with a [`LazyLocal`] handle.
+///
+/// The initializer is a closure that's called the first time the thread local
+/// is used on each thread. Unlike a `lazy static` in
+/// [`static_thread_local`](crate::static_thread_local), the type may need
+/// dropping. A drop hook can be registered for each thread with
+/// [`LazyLocal::register_drop`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// struct Config {
+///     verbose: bool,
+/// }
+///
+/// fn load_config() -> Config {
+///     Config {
+///         verbose: std::env::var_os("VERBOSE").is_some(),
+///     }
+/// }
+///
+/// wintls::lazy_local!{
+///     static CONFIG: Config = || load_config();
+/// }
+///
+/// fn main() {
+///     let verbose = CONFIG.with(|config| config.verbose);
+///     # let _ = verbose;
+/// }
+/// ```
+#[macro_export]
+macro_rules! lazy_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::LazyLocal<$ty> = {
+			// The storage is declared inside `get` so that the initializer can
+			// refer to the handle.
+			fn get() -> *mut $crate::LazyValue<$ty> {
+				$crate::init_static!(
+					static $name: $crate::LazyValue<$ty> = $crate::LazyValue::UNINIT;
+				);
+				unsafe { $crate::static_ptr!($name) }
+			}
+			fn init() -> $ty {
+				($init)()
+			}
+			fn drop_value() {
+				unsafe { $crate::LazyLocal::<$ty>::drop_value(get) }
+			}
+			unsafe { $crate::LazyLocal::new(get, init, drop_value, stringify!($name)) }
+		};
+	)+};
+}
+
+/// A handle to a thread local declared with [`lazy_local`](crate::lazy_local).
+///
+/// The value is only ever borrowed for the duration of a closure so a
+/// reallocation of the thread locals can't leave a reference dangling between
+/// calls. The same caveats as for
+/// [`StaticThreadLocal::with`](crate::StaticThreadLocal::with) apply within the
+/// closure.
+///
+/// If the initializer panics then the thread local is left uninitialized and
+/// the initializer will be called again on the next access.
+pub struct LazyLocal<T: 'static> {
+	get: fn() -> *mut LazyValue<T>,
+	init: fn() -> T,
+	drop: fn(),
+	name: &'static str,
+}
+// Each thread creates its own value with `init`, and `drop` is registered as a
+// destructor of that thread. `T` must be `Send` because that destructor can be
+// run by another thread (see `dtor::enable_process_exit_drain`).
+unsafe impl<T: Send> Sync for LazyLocal<T> {}
+unsafe impl<T: Send> Send for LazyLocal<T> {}
+impl<T> LazyLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out uninitialized and is only used by this
+	/// handle. `drop` must call [`drop_value`](Self::drop_value) with `get`.
+	#[doc(hidden)]
+	pub const unsafe fn new(
+		get: fn() -> *mut LazyValue<T>,
+		init: fn() -> T,
+		drop: fn(),
+		name: &'static str,
+	) -> Self {
+		Self {
+			get,
+			init,
+			drop,
+			name,
+		}
+	}
+
+	/// Calls `f` with a reference to the value, initializing it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called by the initializer, or if the value is being
+	/// dropped or has been dropped.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::lazy_local!{
+	///     static NAME: String = || String::from("main");
+	/// }
+	/// # fn main() {
+	/// assert_eq!(NAME.with(|name| name.len()), 4);
+	/// # }
+	/// ```
+	#[inline(always)]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the value, initializing it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called by the initializer.
+	#[inline(always)]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		let mut lazy = (self.get)();
+		unsafe {
+			if (*lazy).state != INIT {
+				if (*lazy).state == DROPPING || (*lazy).state == DESTROYED {
+					return Err(AccessError::new(self.name));
+				}
+				lazy = self.initialize(lazy);
+			}
+			Ok(f((*lazy).value.assume_init_ref()))
+		}
+	}
+
+	/// Returns `true` if the value has been initialized on the current thread.
+	#[inline(always)]
+	pub fn is_initialized(&self) -> bool {
+		unsafe { (*(self.get)()).state == INIT }
+	}
+
+	/// Drops the current thread's value when the thread exits.
+	///
+	/// This uses [`register_dtor`](crate::dtor::register_dtor) so it needs to
+	/// be called on every thread that should drop its value. It can be called
+	/// before or after the value is initialized. If the value isn't
+	/// initialized when the thread exits then nothing is dropped.
+	///
+	/// Once the value has been dropped it can't be used again on the same
+	/// thread, as for [`Local`](crate::Local). This includes destructors that
+	/// run afterwards.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::lazy_local!{
+	///     static BUFFER: Vec<u8> = || Vec::with_capacity(1024);
+	/// }
+	/// # fn main() {
+	/// std::thread::spawn(|| {
+	///     BUFFER.register_drop();
+	///     BUFFER.with(|buffer| assert!(buffer.capacity() >= 1024));
+	/// })
+	/// .join()
+	/// .unwrap();
+	/// # }
+	/// ```
+	pub fn register_drop(&self) {
+		crate::dtor::register_dtor(self.drop);
+	}
+
+	/// Drops the value if it's initialized, after which it can't be used.
+	///
+	/// # Safety
+	///
+	/// `get` must be the function passed to [`new`](Self::new).
+	#[doc(hidden)]
+	pub unsafe fn drop_value(get: fn() -> *mut LazyValue<T>) {
+		let lazy = get();
+		if (*lazy).state == INIT {
+			(*lazy).state = DROPPING;
+			(*lazy).value.assume_init_drop();
+			// The drop may have loaded a library, which can move the thread
+			// locals.
+			(*get()).state = DESTROYED;
+		}
+	}
+
+	#[cold]
+	#[inline(never)]
+	unsafe fn initialize(&self, lazy: *mut LazyValue<T>) -> *mut LazyValue<T> {
+		lazy::initialize(lazy, self.get, self.init, self.name)
+	}
+}
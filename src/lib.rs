@@ -8,14 +8,30 @@
 //!
 //! # Use
 //!
-//! This requires a nightly compiler. You will also need to add `#![feature(asm)]`
-//! to your `main.rs` or `lib.rs` file if you use the TLS macros.
+//! This requires a nightly compiler. The macros are allowed to use the unstable
+//! `asm` feature internally so your own crate doesn't need `#![feature(asm)]`.
+//!
+//! The `macros` feature adds a [`#[thread_local]`](thread_local) attribute,
+//! which can be used instead of [`static_thread_local`] for a single static.
+//!
+//! The `debug-borrows` feature adds borrow tracking to [`UnsafeLocal`] in
+//! debug builds.
+//!
+//! The `std` feature, which is on by default, adds the thread locals that
+//! allocate or lock, such as [`HeapLocal`], [`RegisteredLocal`] and
+//! [`StringLocal`], as well as [`dtor::register_dtor_boxed`]. Without it the
+//! crate is `no_std` and doesn't allocate. The `inline-dtors` feature stores
+//! the destructor list in thread local storage instead of a `Vec`, which is
+//! what happens without `std` (see [`dtor`](dtor#storage)).
+//!
+//! # Fibers
+//!
+//! Apart from [`FiberLocal`], every thread local in this crate is shared by
+//! all the fibers that run on a thread.
 //!
 //! # Example
 //!
 //! ```
-//! #![feature(asm)]
-//!
 //! wintls::static_thread_local!{
 //!     static TEST: u32 = 0xfeedface;
 //! }
@@ -32,13 +48,32 @@
 //! }
 //! ```
 //!
-//! <style>#macros + * > *:not(:is(:nth-last-child(2), :last-child)) { display:none } </style>
+//! <!-- Only list `assert_tls_budget`, `cell_local`, `dll_safe_thread_local`,
+//! `export_thread_locals`, `extern_thread_local`, `flush_local`, `get_many`,
+//! `heap_local`, `lazy_local`, `local`, `local_arena`, `local_pool`,
+//! `module_local`, `once_local`, `recursion_guard`, `ref_cell_local`,
+//! `registered_local`, `remote_local`, `scoped_thread_local`,
+//! `static_thread_local`, `static_thread_local_struct`, `string_local`,
+//! `thread_local_counter` and `unsafe_local`. The rest are re-exported from
+//! `raw`. -->
+//! <style>#macros + * > *:not(:is(:nth-child(-n+7), :nth-child(9), :nth-child(n+11):nth-child(-n+21), :nth-last-child(-n+5))) { display:none } </style>
 
 // TODO: aarch64 support
 #![cfg(all(windows, any(target_arch = "x86_64", target_arch = "x86")))]
-#![feature(asm)]
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(
+	not(wintls_not_nightly),
+	feature(asm, allow_internal_unstable, linkage)
+)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+#[cfg(wintls_not_nightly)]
+compile_error!(
+	"wintls requires a nightly compiler because it uses inline assembly (the \
+	unstable `asm` feature). Build with `cargo +nightly` or add a \
+	`rust-toolchain.toml` with `channel = \"nightly\"`."
+);
+
 // Some module jiggery pokery for the sake of macros.
 // TODO: move to a separate crate.
 #[cfg(feature = "raw")]
@@ -47,6 +82,118 @@ pub mod raw;
 pub mod raw_internal;
 
 pub mod dtor;
+pub mod ops;
+pub mod ref_cell;
+#[cfg(feature = "std")]
+pub mod scratch;
+
+#[cfg(feature = "std")]
+mod arena;
+mod array;
+mod borrow;
+mod cell;
+#[cfg(feature = "std")]
+mod counter;
+mod dll;
+#[cfg(feature = "std")]
+mod dynamic;
+mod export;
+mod field;
+#[cfg(feature = "std")]
+mod fls;
+#[cfg(feature = "std")]
+mod flush;
+mod guard;
+#[cfg(feature = "std")]
+mod heap;
+mod lazy;
+mod lazy_local;
+mod local;
+mod local_ptr;
+mod module;
+mod once;
+mod option;
+#[cfg(feature = "std")]
+mod pool;
+mod recursion;
+#[cfg(feature = "std")]
+mod registered;
+#[cfg(feature = "std")]
+mod remote;
+mod scope;
+mod scoped;
+mod sentinel;
+#[cfg(feature = "std")]
+mod string;
+mod thread_bound;
+#[cfg(feature = "std")]
+mod thread_index;
+mod thread_token;
+mod uninit;
+
+#[cfg(feature = "std")]
+pub use arena::{Arena, LocalArena};
+pub use cell::CellLocal;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use counter::CounterSlot;
+#[cfg(feature = "std")]
+pub use counter::ThreadLocalCounter;
+pub use dll::DllSafeThreadLocal;
+#[cfg(feature = "std")]
+pub use dynamic::{DynamicThreadLocal, OutOfIndexesError};
+pub use field::StaticField;
+#[cfg(feature = "std")]
+pub use fls::{FiberLocal, FlsLocal};
+#[cfg(feature = "std")]
+pub use flush::FlushLocal;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use flush::FlushSlot;
+pub use guard::TlsGuard;
+#[cfg(feature = "std")]
+pub use heap::HeapLocal;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use heap::HeapSlot;
+#[doc(hidden)]
+pub use lazy::LazyValue;
+pub use lazy::{LazyKey, LazyThreadLocal};
+pub use lazy_local::LazyLocal;
+pub use local::{AccessError, Local, LocalKey};
+pub use local_ptr::LocalPtr;
+pub use module::ModuleLocal;
+pub use once::OnceLocal;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use pool::FreeList;
+#[cfg(feature = "std")]
+pub use pool::{LocalPool, OnThreadExit, PoolConfig};
+pub use recursion::{Entered, RecursionGuard};
+pub use ref_cell::RefCellLocal;
+#[doc(hidden)]
+pub use ref_cell::RefCellSlot;
+#[cfg(feature = "std")]
+pub use registered::RegisteredLocal;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use remote::RemoteSlot;
+#[cfg(feature = "std")]
+pub use remote::{RemoteLocal, SendToError};
+pub use scope::ScopeGuard;
+pub use scoped::{NotSetError, ScopedThreadLocal};
+#[cfg(feature = "std")]
+pub use string::StringLocal;
+pub use thread_bound::{ThreadBound, WrongThreadDrop};
+#[cfg(all(feature = "std", feature = "test-hooks"))]
+#[doc(hidden)]
+pub use thread_index::{lock_thread_indexes, IndexLock};
+#[cfg(feature = "std")]
+pub use thread_index::{max_threads_seen, thread_index};
+#[cfg(feature = "macros")]
+pub use wintls_macros::thread_local;
+
+use core::marker::PhantomData;
 
 /// Statically initialize a thread local.
 ///
@@ -64,28 +211,461 @@ pub mod dtor;
 /// ```
 ///
 /// See [`StaticThreadLocal`] for more information.
+///
+/// Attributes (including doc comments and `cfg`) and visibility are applied
+/// to each declared static.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     /// The number of open spans.
+///     #[cfg(debug_assertions)]
+///     pub(crate) static SPANS: u32 = 0;
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Locals can also be declared inside a function, in which case they're only
+/// visible within that function.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// // Remembers the last input and output.
+/// fn square(input: u64) -> u64 {
+///     wintls::static_thread_local!{
+///         static CACHE: (u64, u64) = (0, 0);
+///     }
+///     let (last_input, output) = CACHE.get();
+///     if last_input == input {
+///         return output;
+///     }
+///     let output = input * input;
+///     CACHE.set((input, output));
+///     output
+/// }
+/// # fn main() { assert_eq!(square(3), 9); assert_eq!(square(3), 9); }
+/// ```
+///
+/// # Lazy Initialization
+///
+/// Declaring a `lazy static` allows the initializer to be any expression. It's
+/// run once per thread, the first time the local is accessed. See
+/// [`LazyThreadLocal`].
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     lazy static STARTED: std::time::Instant = std::time::Instant::now();
+/// }
+/// # fn main() { STARTED.get(); }
+/// ```
+///
+/// # Alignment
+///
+/// Each thread's copy of the locals is allocated from the heap so only its
+/// alignment is guaranteed: 16 bytes on x86_64 and 8 bytes on x86. Types with a
+/// greater alignment are rejected at compile time.
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// #[derive(Clone, Copy)]
+/// #[repr(align(64))]
+/// struct CacheLine([u8; 64]);
+///
+/// wintls::static_thread_local!{
+///     static LINE: CacheLine = CacheLine([0; 64]);
+/// }
+/// # fn main() {}
+/// ```
+///
+/// # Sections
+///
+/// Locals are placed in the `.tls$` section by default. A
+/// `#[tls_section = "..."]` attribute adds a suffix to the section name. The
+/// linker orders sections by their suffix so this can be used to keep locals
+/// that are used together in the same cache line, and rarely used locals out
+/// of the way. The suffix must sort before `ZZZ`.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     /// The hottest local.
+///     #[tls_section = "A"]
+///     static HOT: u64 = 0;
+///     #[tls_section = "Y"]
+///     static DIAGNOSTICS: [u32; 16] = [0; 16];
+/// }
+/// # fn main() {}
+/// ```
+///
+/// # Exporting
+///
+/// An `#[export_name = "..."]` attribute is applied to the underlying `.tls$`
+/// static, rather than the handle. C or C++ code in the same module can then
+/// use the thread local by declaring it as `extern __declspec(thread)`. As
+/// with any C symbol, an underscore is added to the name on x86. Thread locals
+/// can't be imported from another module.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     // extern __declspec(thread) int32_t g_depth;
+///     #[export_name = "g_depth"]
+///     static DEPTH: i32 = 0;
+/// }
+/// # fn main() {}
+/// ```
+///
+/// # Sharing
+///
+/// A `#[shared("...")]` attribute gives the local a fixed symbol name with
+/// weak linkage. If more than one crate defines a local with the same symbol
+/// then the linker keeps only one of them, so they all share a single slot.
+/// This allows crates that don't depend on each other to use the same local.
+///
+/// The linker doesn't check that the definitions match. Every definition must
+/// have the same type and initial value, otherwise reads will be reinterpreted
+/// as the wrong type, which is undefined behaviour. As with `export_name`, the
+/// locals are only shared within a module.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     // Also defined by `my_log_shim`.
+///     #[shared("my_log_context")]
+///     pub static LOG_CONTEXT: usize = 0;
+/// }
+/// # fn main() {}
+/// ```
+///
+/// # Image Size
+///
+/// Every thread's copy of the locals is made from a template stored in the
+/// image, so each local's initial value takes up space in the binary, even if
+/// it's all zeros. The TLS directory does have a field for zero-filled space
+/// after the template, but it's provided by the CRT, which always sets it to
+/// zero. Large buffers are better allocated when needed, e.g. in a
+/// `lazy static` or an [`unsafe_local`] with [`dtor::register_dtor`].
+///
+/// # Drop
+///
+/// Thread locals are never dropped so types that need dropping are rejected
+/// at compile time. The error names the static and its type.
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::static_thread_local!{
+///     static NAME: String = String::new();
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::static_thread_local!{
+///     static BYTES: Vec<u8> = Vec::new();
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// struct Guard;
+/// impl Drop for Guard {
+///     fn drop(&mut self) {}
+/// }
+/// struct Wrapper {
+///     guard: Guard,
+/// }
+/// wintls::static_thread_local!{
+///     static WRAPPER: Wrapper = Wrapper { guard: Guard };
+/// }
+/// # fn main() {}
+/// ```
+///
+/// To run code when a thread exits, use [`dtor::register_dtor`].
+///
+/// # Validation
+///
+/// A `validate` function can optionally be given after a local. In debug
+/// builds it's called with the raw bytes of the value before the value is read
+/// (e.g. by `get`, `with` or `with_mut`). If it returns `false` then that will
+/// panic. This can help catch invalid values (such as an enum with an invalid
+/// discriminant) written through a pointer. In release builds the check is
+/// removed entirely.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// #[derive(Clone, Copy)]
+/// #[repr(u8)]
+/// enum State {
+///     Idle,
+///     Busy,
+/// }
+/// impl State {
+///     fn is_valid(bytes: &[u8]) -> bool {
+///         bytes[0] <= State::Busy as u8
+///     }
+/// }
+///
+/// wintls::static_thread_local!{
+///     static STATE: State = State::Idle;
+///     validate = State::is_valid;
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The type must not contain any padding bytes.
 #[macro_export]
 macro_rules! static_thread_local {
-	($vis:vis static $name:ident: $ty:ty = $value:expr;) => {
-		$vis static $name: $crate::StaticThreadLocal<$ty> = {
-			if ::core::mem::needs_drop::<$ty>() {
-				panic!("static thread locals cannot be dropped");
-			};
-
-			$crate::init_static!(static $name: $ty = $value;);
-			unsafe {
-				$crate::StaticThreadLocal {
-					get: || $crate::get_static!($name),
-					set: |v| $crate::set_static!($name, v)
+	// The `key` and `borrow` functions of a `StaticKey` impl. Also used by
+	// `wintls-macros`.
+	(@key_fns $name:ident [$($storage:tt)*]) => {
+		#[inline(always)]
+		fn key() -> u32 {
+			$crate::init_static!(
+				$($storage)*
+				static VALUE: <$name as $crate::StaticKey>::Value =
+					<$name as $crate::StaticKey>::INIT;
+			);
+			unsafe { $crate::static_key!(VALUE) }
+		}
+
+		$crate::static_thread_local!{ @borrow_fn }
+	};
+	(@borrow_fn) => {
+		#[inline(always)]
+		fn borrow() -> *mut isize {
+			// Only track borrows when the declaring crate has debug assertions.
+			#[cfg(debug_assertions)]
+			$crate::init_static!(static BORROW: isize = 0;);
+			#[cfg(debug_assertions)]
+			return unsafe { $crate::static_ptr!(BORROW) };
+			#[cfg(not(debug_assertions))]
+			return ::core::ptr::null_mut();
+		}
+	};
+	// Fails to compile if the type needs dropping. A field name can be given for
+	// locals that are structs of fields.
+	(@assert_no_drop $name:ident $(. $field:ident)?: $ty:ty) => {
+		const _: () = assert!(
+			!::core::mem::needs_drop::<$ty>(),
+			concat!(
+				$("field `", stringify!($field), "` of ",)?
+				"thread local `",
+				stringify!($name),
+				"` has type `",
+				stringify!($ty),
+				"`, which needs to be dropped. Static thread locals are ",
+				"never dropped. Use a type that doesn't need dropping, ",
+				"or an `unsafe_local!` with `wintls::dtor::register_dtor`",
+			),
+		);
+	};
+	// The expansion of `#[wintls::thread_local]`. The attribute parses the item
+	// and builds the drop assertion, with the span of the user's type.
+	(
+		@attribute [$($storage:tt)*] repr($($repr:tt)*) [$($no_drop:tt)*]
+		$(#[$attr:meta])*
+		$vis:vis static $name:ident: $ty:ty = $value:expr;
+	) => {
+		$(#[$attr])*
+		#[doc(hidden)]
+		#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+		$vis struct $name {}
+
+		$(#[$attr])*
+		const _: () = {
+			unsafe impl $crate::StaticKey for $name {
+				type Value = $ty;
+				// `zeroed` transmutes from a byte array, which might be the same type.
+				#[allow(clippy::useless_transmute)]
+				const INIT: $ty = $value;
+
+				#[inline(always)]
+				fn key() -> u32 {
+					#[repr($($repr)*)]
+					struct Aligned($ty);
+					$crate::init_static!(
+						$($storage)*
+						static VALUE: Aligned = Aligned(<$name as $crate::StaticKey>::INIT);
+					);
+					unsafe { $crate::static_key!(VALUE) }
 				}
+
+				$crate::static_thread_local!{ @borrow_fn }
 			}
+
+			$($no_drop)*
 		};
+
+		$(#[$attr])*
+		$vis static $name: $crate::StaticThreadLocal<$ty, $name> = $crate::StaticThreadLocal::new();
+	};
+	(
+		@lazy [$($storage:tt)*]
+		$(#[$attr:meta])*
+		$vis:vis static $name:ident: $ty:ty = $init:expr;
+	) => {
+		$(#[$attr])*
+		#[doc(hidden)]
+		#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+		$vis struct $name {}
+
+		$(#[$attr])*
+		const _: () = {
+			unsafe impl $crate::StaticKey for $name {
+				type Value = $crate::LazyValue<$ty>;
+				const INIT: Self::Value = $crate::LazyValue::UNINIT;
+
+				$crate::static_thread_local!{ @key_fns $name [$($storage)*] }
+			}
+			unsafe impl $crate::LazyKey for $name {
+				type Target = $ty;
+				const NAME: &'static str = stringify!($name);
+
+				#[inline(always)]
+				fn init() -> $ty {
+					$init
+				}
+			}
+
+			$crate::static_thread_local!{ @assert_no_drop $name: $ty }
+		};
+
+		$(#[$attr])*
+		$vis static $name: $crate::LazyThreadLocal<$ty, $name> = $crate::LazyThreadLocal::new();
+	};
+	(
+		@local [$($storage:tt)*]
+		$(#[$attr:meta])*
+		$vis:vis static $name:ident: $ty:ty = $value:expr;
+		$(validate = $validate:expr;)?
+	) => {
+		// The key type has the same name as the static. It's a braced struct so
+		// it only uses the type namespace and doesn't clash with the static.
+		$(#[$attr])*
+		#[doc(hidden)]
+		#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+		$vis struct $name {}
+
+		// The attributes are also applied here so that the impl is removed if
+		// the static is `cfg`'d out.
+		$(#[$attr])*
+		const _: () = {
+			unsafe impl $crate::StaticKey for $name {
+				type Value = $ty;
+				const INIT: $ty = $value;
+
+				$crate::static_thread_local!{ @key_fns $name [$($storage)*] }
+
+				$(
+					#[inline(always)]
+					fn validate(ptr: *const $ty) {
+						if cfg!(debug_assertions) {
+							let bytes = unsafe {
+								::core::slice::from_raw_parts(
+									ptr.cast::<u8>(),
+									::core::mem::size_of::<$ty>(),
+								)
+							};
+							let validate: fn(&[u8]) -> bool = $validate;
+							if !validate(bytes) {
+								panic!(concat!(
+									"thread local `",
+									stringify!($name),
+									"` has an invalid value"
+								));
+							}
+						}
+					}
+				)?
+			}
+
+			$crate::static_thread_local!{ @assert_no_drop $name: $ty }
+		};
+
+		$(#[$attr])*
+		$vis static $name: $crate::StaticThreadLocal<$ty, $name> = $crate::StaticThreadLocal::new();
+	};
+	// The locals are munched one at a time because otherwise it's ambiguous
+	// whether `validate` starts the next local. Each local's attributes are
+	// also munched one at a time to pick out the ones that apply to the
+	// underlying `.tls$` static instead.
+	(@attrs [$($attr:tt)*] [$($storage:tt)*] #[tls_section = $section:literal] $($rest:tt)*) => {
+		$crate::static_thread_local!{
+			@attrs [$($attr)*] [$($storage)* #[tls_section = $section]] $($rest)*
+		}
+	};
+	(@attrs [$($attr:tt)*] [$($storage:tt)*] #[shared($symbol:literal)] $($rest:tt)*) => {
+		$crate::static_thread_local!{
+			@attrs [$($attr)*] [$($storage)* #[shared($symbol)]] $($rest)*
+		}
+	};
+	(@attrs [$($attr:tt)*] [$($storage:tt)*] #[export_name = $export:literal] $($rest:tt)*) => {
+		$crate::static_thread_local!{
+			@attrs [$($attr)*] [$($storage)* #[export_name = $export]] $($rest)*
+		}
+	};
+	(@attrs [$($attr:tt)*] [$($storage:tt)*] #[$meta:meta] $($rest:tt)*) => {
+		$crate::static_thread_local!{ @attrs [$($attr)* #[$meta]] [$($storage)*] $($rest)* }
+	};
+	(
+		@attrs [$($attr:tt)*] [$($storage:tt)*]
+		$vis:vis lazy static $name:ident: $ty:ty = $init:expr;
+		$($rest:tt)*
+	) => {
+		$crate::static_thread_local!{
+			@lazy [$($storage)*]
+			$($attr)*
+			$vis static $name: $ty = $init;
+		}
+		$crate::static_thread_local!{ $($rest)* }
+	};
+	(
+		@attrs [$($attr:tt)*] [$($storage:tt)*]
+		$vis:vis static $name:ident: $ty:ty = $value:expr;
+		validate = $validate:expr;
+		$($rest:tt)*
+	) => {
+		$crate::static_thread_local!{
+			@local [$($storage)*]
+			$($attr)*
+			$vis static $name: $ty = $value;
+			validate = $validate;
+		}
+		$crate::static_thread_local!{ $($rest)* }
+	};
+	(
+		@attrs [$($attr:tt)*] [$($storage:tt)*]
+		$vis:vis static $name:ident: $ty:ty = $value:expr;
+		$($rest:tt)*
+	) => {
+		$crate::static_thread_local!{
+			@local [$($storage)*]
+			$($attr)*
+			$vis static $name: $ty = $value;
+		}
+		$crate::static_thread_local!{ $($rest)* }
+	};
+	(@attrs [$($attr:tt)*] [$($storage:tt)*] $($rest:tt)+) => {
+		::core::compile_error!(concat!(
+			"expected a thread local declaration, found `",
+			stringify!($($rest)+),
+			"`"
+		));
+	};
+	() => {};
+	($($rest:tt)+) => {
+		$crate::static_thread_local!{ @attrs [] [] $($rest)+ }
 	};
-	($(static $name:ident: $ty:ty = $value:expr;)+) => {
-		$(
-			$crate::static_thread_local!{static $name: $ty = $value;}
-		)+
-	}
 }
 
 /// Enables setting or getting a static thread local value.
@@ -95,6 +675,18 @@ macro_rules! static_thread_local {
 /// The thread local can be statically initialized using [`static_thread_local`].
 /// No [`Drop`] implementations will be run.
 ///
+/// A thread local of type [`MaybeUninit`](core::mem::MaybeUninit) can be used
+/// when the initial value doesn't matter, e.g. for scratch space. It has
+/// [`write`](Self::write) and `assume_init_*` methods. The loader will actually
+/// zero the memory (or copy whatever the compiler put in the template) but code
+/// must not rely on this.
+///
+/// # Keys
+///
+/// `K` is a zero-sized type that identifies the thread local (see
+/// [`StaticKey`]). The macro gives it the same name as the static. The handle
+/// itself is also zero-sized so using it doesn't involve any indirect calls.
+///
 /// # Example
 /// ```
 /// #![feature(asm)]
@@ -107,13 +699,59 @@ macro_rules! static_thread_local {
 ///     println!("{:x}", DATA.get());
 /// }
 /// ```
-pub struct StaticThreadLocal<T> {
-	#[doc(hidden)]
-	pub get: fn() -> T,
-	#[doc(hidden)]
-	pub set: fn(T),
+///
+/// # Thread Safety
+///
+/// The handle itself can be freely shared between threads because each thread
+/// only ever sees its own copy of the value. However, every thread starts with
+/// a copy of the initial value, which is much like sending the value to each
+/// thread. So `T` must be [`Send`].
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// struct NotSend(*mut u8);
+///
+/// wintls::static_thread_local!{
+///     static DATA: NotSend = NotSend(core::ptr::null_mut());
+/// }
+/// # fn main() {}
+/// ```
+///
+/// # Fibers
+///
+/// The value belongs to the thread, not the fiber. Fibers that run on the
+/// same thread share it. Use [`FiberLocal`] for a value per fiber.
+pub struct StaticThreadLocal<T, K> {
+	_marker: PhantomData<(T, K)>,
 }
-impl<T: Copy> StaticThreadLocal<T> {
+// See "Thread Safety" in the docs above.
+unsafe impl<T: Send, K> Sync for StaticThreadLocal<T, K> {}
+unsafe impl<T: Send, K> Send for StaticThreadLocal<T, K> {}
+impl<T, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	/// Creates a handle for the thread local identified by `K`.
+	///
+	/// You should use [`static_thread_local`] instead of calling this directly.
+	/// A handle can only be created from a [`StaticKey`], which is unsafe to
+	/// implement.
+	///
+	/// # Example
+	///
+	/// The handle can't be constructed from arbitrary functions.
+	///
+	/// ```compile_fail
+	/// # #![feature(asm)]
+	/// # use wintls::StaticThreadLocal;
+	/// # fn main() {
+	/// let local: StaticThreadLocal<u32, ()> = StaticThreadLocal::new();
+	/// # }
+	/// ```
+	#[allow(clippy::new_without_default)]
+	pub const fn new() -> Self {
+		Self {
+			_marker: PhantomData,
+		}
+	}
+
 	/// Returns the value of the the thread local.
 	///
 	/// # Example
@@ -130,12 +768,50 @@ impl<T: Copy> StaticThreadLocal<T> {
 	/// # }
 	/// ```
 	#[inline(always)]
-	pub fn get(&self) -> T {
-		(self.get)()
+	pub fn get(&self) -> T
+	where
+		T: Copy,
+	{
+		borrow::check_read(K::borrow());
+		let ptr = K::ptr();
+		K::validate(ptr);
+		unsafe { *ptr }
+	}
+
+	/// Returns a clone of the thread local.
+	///
+	/// This is for types that are [`Clone`] but not [`Copy`]. For `Copy` types
+	/// use [`get`](Self::get) instead.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// #[derive(Clone)]
+	/// struct Buffer([u8; 16]);
+	///
+	/// static_thread_local!{
+	///     static BUFFER: Buffer = Buffer([0; 16]);
+	/// }
+	/// # fn main() {
+	/// let buffer: Buffer = BUFFER.get_cloned();
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn get_cloned(&self) -> T
+	where
+		T: Clone,
+	{
+		self.with(T::clone)
 	}
 
 	/// Sets the value of the the thread local.
 	///
+	/// The old value is overwritten without being dropped. This is fine because
+	/// [`static_thread_local`] does not allow types that need to be dropped.
+	///
 	/// # Example
 	///
 	/// ```
@@ -151,79 +827,1046 @@ impl<T: Copy> StaticThreadLocal<T> {
 	/// ```
 	#[inline(always)]
 	pub fn set(&self, value: T) {
-		(self.set)(value)
+		borrow::check_write(K::borrow());
+		unsafe { K::ptr().write(value) }
 	}
-}
 
-/// Grants unsafe access to the thread local.
-///
-/// In general you should make sure that any references to the actual thread
-/// local are as short-lived as possible.
-///
-/// # Stale Pointers
-///
-/// If a new DLL is lazily loaded and that DLL requires the static TLS array to
-/// be expanded then, for each thread, a new TLS array will be created without
-/// dropping the old one. The old array will then be copied to the new one.
-///
-/// So now there are two copies of the thread local data. If a new pointer is
-/// made then it'll point to the new data but any old pointers will still point
-/// to the "stale" data.
-pub struct UnsafeLocal<T> {
-	#[doc(hidden)]
-	pub get: fn() -> *mut T,
-}
-impl<T> UnsafeLocal<T> {
-	/// Getting a pointer is safe.
-	/// Using it should be mostly safe (normal caveats aside) so long as there
-	/// aren't any active references. That said, you should almost certainly use
-	/// and discard the pointer asap.
-	pub fn as_ptr(&self) -> *mut T {
-		(self.get)()
-	}
-	/// There can be many shared references but there must not be a mutable
-	/// reference at all. Also no mutation should occur for the lifetime of this
-	/// reference.
-	pub unsafe fn as_ref(&self) -> &T {
-		&*self.as_ptr()
+	/// Sets the thread local back to the value it was declared with.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// DATA.set(5);
+	/// DATA.reset();
+	/// assert_eq!(DATA.get(), 0xfeedface);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn reset(&self) {
+		self.set(K::INIT)
 	}
-	/// There can only be one mutable reference at a time and there must not be
-	/// any shared references. Also mutation should only happen via this
-	/// reference and not through any pointer.
-	pub unsafe fn as_ref_mut(&self) -> &mut T {
-		&mut *self.as_ptr()
+
+	/// Sets the value of the thread local, returning the previous value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// assert_eq!(DATA.replace(5), 0xfeedface);
+	/// assert_eq!(DATA.get(), 5);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn replace(&self, value: T) -> T {
+		borrow::check_write(K::borrow());
+		let ptr = K::ptr();
+		K::validate(ptr);
+		unsafe { core::mem::replace(&mut *ptr, value) }
 	}
 
-	/// Drops the memory. No further use of the memory should occur after
-	/// calling this, unless a new value is created in place.
+	/// Takes the value of the thread local, leaving `Default::default()` in
+	/// its place.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// # use core::num::NonZeroU32;
+	/// #
+	/// # static_thread_local!{
+	/// #     static ID: Option<NonZeroU32> = NonZeroU32::new(1);
+	/// # }
+	/// # fn main() {
+	/// assert_eq!(ID.take(), NonZeroU32::new(1));
+	/// assert_eq!(ID.get(), None);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn take(&self) -> T
+	where
+		T: Default,
+	{
+		self.replace(T::default())
+	}
+
+	/// Replaces the value of the thread local with the result of calling `f` on
+	/// the current value. Returns the new value.
+	///
+	/// The thread local is only looked up once, before `f` is called. So `f`
+	/// should not load a library that might cause the TLS array to be
+	/// reallocated (see [`UnsafeLocal`]'s notes on stale pointers).
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static COUNTER: u32 = 0;
+	/// # }
+	/// # fn main() {
+	/// assert_eq!(COUNTER.update(|n| n + 1), 1);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn update(&self, f: impl FnOnce(T) -> T) -> T
+	where
+		T: Copy,
+	{
+		let ptr = K::ptr();
+		let value = f(self.get_at_ptr(ptr));
+		self.set_at_ptr(ptr, value);
+		value
+	}
+
+	/// Calls `f` with a reference to the thread local.
+	///
+	/// Unlike [`get`](Self::get) this does not copy the value, which can be
+	/// useful for large types. The reference cannot escape the closure.
+	///
+	/// # Reentrancy
+	///
+	/// It's fine to call `get` or `with` on the same local from within the
+	/// closure, as that only reads the value. However, the closure must not
+	/// `set` the same local or call `with_mut` on it while the reference is
+	/// live. In debug builds this will panic.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static BUFFER: [u8; 4096] = [0; 4096];
+	/// # }
+	/// # fn main() {
+	/// let sum: u32 = BUFFER.with(|buffer| buffer.iter().map(|&b| b as u32).sum());
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		let _guard = borrow::BorrowGuard::shared(K::borrow());
+		let ptr = K::ptr();
+		K::validate(ptr);
+		unsafe { f(&*ptr) }
+	}
+
+	/// Calls `f` with a mutable reference to the thread local.
+	///
+	/// This allows modifying the value in place instead of using
+	/// [`get`](Self::get) and [`set`](Self::set) to copy it out and back in
+	/// again. The reference cannot escape the closure.
+	///
+	/// # Reentrancy
+	///
+	/// The closure must not access the same local in any way while the mutable
+	/// reference is live. That includes calling `with_mut` again. In debug
+	/// builds this will panic.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static SLOTS: [u64; 64] = [0; 64];
+	/// # }
+	/// # fn main() {
+	/// SLOTS.with_mut(|slots| slots[5] = 1);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		let _guard = borrow::BorrowGuard::exclusive(K::borrow());
+		let ptr = K::ptr();
+		K::validate(ptr);
+		unsafe { f(&mut *ptr) }
+	}
+}
+
+impl<T, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	/// Returns a pointer to the current thread's value.
+	///
+	/// Getting a pointer is safe but using it is not. The same rules apply as
+	/// for [`UnsafeLocal::as_ptr`]. In particular, there must be no live
+	/// references from [`with`](Self::with) or [`with_mut`](Self::with_mut)
+	/// when writing through it.
+	///
+	/// # Stale Pointers
+	///
+	/// If a new DLL is lazily loaded and that DLL requires the static TLS array
+	/// to be expanded then the pointer may point to a "stale" copy of the data.
+	/// See [`UnsafeLocal`] for details. So the pointer should be used and then
+	/// discarded as soon as possible.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// let ptr: *mut u32 = DATA.get_ptr();
+	/// unsafe { ptr.write(5) };
+	/// assert_eq!(DATA.get(), 5);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn get_ptr(&self) -> *mut T {
+		K::ptr()
+	}
+
+	/// Returns the raw key that identifies this thread local.
+	///
+	/// The key is only meaningful when used with the `raw` APIs and only for the
+	/// module (exe or DLL) that declared the local. To access the local from a
+	/// different module, the key must be used together with the declaring
+	/// module's `_tls_index` (e.g. with `raw::get_static_from_module`).
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// let key: u32 = DATA.key();
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn key(&self) -> u32 {
+		K::key()
+	}
+
+	/// Returns an [`UnsafeLocal`] view of the same thread local.
+	///
+	/// Both refer to the same memory so changes made through one are visible
+	/// through the other. Note that the `UnsafeLocal` does not take part in the
+	/// borrow checks done by [`with`](Self::with) and [`with_mut`](Self::with_mut).
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// let local = DATA.as_unsafe_local();
+	/// unsafe { *local.as_ref_mut() = 5 };
+	/// assert_eq!(DATA.get(), 5);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn as_unsafe_local(&self) -> UnsafeLocal<T> {
+		UnsafeLocal {
+			get: K::ptr,
+			#[cfg(feature = "debug-borrows")]
+			borrow: K::borrow,
+			#[cfg(feature = "debug-borrows")]
+			name: None,
+			dtor: None,
+			_marker: PhantomData,
+		}
+	}
+}
+
+/// Formats the current thread's value.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     static DATA: u32 = 1;
+/// }
+///
+/// fn main() {
+///     DATA.set(2);
+///     println!("{:?}", DATA); // StaticThreadLocal(2)
+///     std::thread::spawn(|| {
+///         println!("{:?}", DATA); // StaticThreadLocal(1)
+///     }).join();
+/// }
+/// ```
+impl<T: Copy + core::fmt::Debug, K: StaticKey<Value = T>> core::fmt::Debug
+	for StaticThreadLocal<T, K>
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_tuple("StaticThreadLocal").field(&self.get()).finish()
+	}
+}
+
+// Accessors for a pointer that's already been looked up.
+impl<T: Copy, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	// Used by `get_many!`.
+	#[doc(hidden)]
+	#[inline(always)]
+	pub unsafe fn get_from_block(&self, block: *mut u8) -> T {
+		self.get_at_ptr(block.add(self.key() as usize).cast())
+	}
+	#[inline(always)]
+	fn get_at_ptr(&self, ptr: *mut T) -> T {
+		borrow::check_read(K::borrow());
+		K::validate(ptr);
+		unsafe { *ptr }
+	}
+	#[inline(always)]
+	fn set_at_ptr(&self, ptr: *mut T, value: T) {
+		borrow::check_write(K::borrow());
+		unsafe { *ptr = value }
+	}
+}
+
+/// Gets the values of several thread locals at once.
+///
+/// This looks up the current thread's TLS block once and then reads every
+/// thread local from it, which is cheaper than calling `get` on each one. The
+/// values are returned as a tuple in the same order as the arguments.
+///
+/// Note that while the values are all read at the same point in the code, this
+/// is not some kind of atomic snapshot. If a value is modified between reads
+/// (e.g. from a destructor) then that would be reflected in the result.
+///
+/// All the thread locals must have been declared in the same module (e.g. the
+/// same DLL or exe) as the code that uses this macro.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     static A: u8 = 1;
+///     static B: u64 = 2;
+///     static C: [u16; 3] = [3; 3];
+/// }
+///
+/// fn main() {
+///     let (a, b, c) = wintls::get_many!(A, B, C);
+///     assert_eq!((a, b, c), (1, 2, [3; 3]));
+/// }
+/// ```
+#[macro_export]
+macro_rules! get_many {
+	($($local:expr),+ $(,)?) => {{
+		let block = unsafe { $crate::raw_internal::module_block() };
+		($(unsafe { $crate::StaticThreadLocal::get_from_block(&$local, block) },)+)
+	}};
+}
+
+/// Asserts at compile time that the given thread locals fit within a budget.
+///
+/// Every thread gets its own copy of every thread local in the module, so it
+/// can be useful to limit their total size. This adds up the sizes of the
+/// listed locals and fails the build if they're larger than the budget (in
+/// bytes). The compiler's error then shows both the budget and the total.
+///
+/// Only locals declared with [`static_thread_local`] or
+/// [`static_thread_local_struct`] can be listed. There's no way to find every
+/// local automatically, so each crate should assert the budget of its own
+/// locals. In debug builds each local also has an `isize` sized borrow flag,
+/// which isn't counted.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     static BUFFER: [u8; 1024] = [0; 1024];
+///     static COUNT: u32 = 0;
+/// }
+/// wintls::assert_tls_budget!(2048; BUFFER, COUNT);
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::static_thread_local!{
+///     static BUFFER: [u8; 4096] = [0; 4096];
+///     static COUNT: u32 = 0;
+/// }
+/// wintls::assert_tls_budget!(4096; BUFFER, COUNT);
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! assert_tls_budget {
+	(@size $($local:ident),+) => {
+		0 $(+ ::core::mem::size_of::<<$local as $crate::StaticKey>::Value>())+
+	};
+	($budget:expr; $($local:ident),+ $(,)?) => {
+		const _: () = assert!(
+			$crate::assert_tls_budget!(@size $($local),+) <= $budget,
+			concat!(
+				"the thread locals `",
+				stringify!($($local),+),
+				"` are larger than the budget of ",
+				stringify!($budget),
+				" bytes"
+			),
+		);
+		// If the assertion fails then this also fails, with an error that
+		// shows the total.
+		const _: () = {
+			const BUDGET_REMAINING: usize = $budget - $crate::assert_tls_budget!(@size $($local),+);
+			let _ = BUDGET_REMAINING;
+		};
+	};
+}
+
+/// Swaps the values of two thread locals for the current thread.
+///
+/// This swaps the values in place so no temporary copy of the whole value is
+/// made, which matters for large types. If `a` and `b` are the same thread local
+/// then this does nothing.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::static_thread_local!{
+///     static CURRENT: [u8; 1024] = [1; 1024];
+///     static PREVIOUS: [u8; 1024] = [0; 1024];
+/// }
+///
+/// fn main() {
+///     wintls::swap(&CURRENT, &PREVIOUS);
+///     assert_eq!(CURRENT.get_at(0), 0);
+/// }
+/// ```
+#[inline(always)]
+pub fn swap<T, A, B>(a: &StaticThreadLocal<T, A>, b: &StaticThreadLocal<T, B>)
+where
+	A: StaticKey<Value = T>,
+	B: StaticKey<Value = T>,
+{
+	let (a_ptr, b_ptr) = (a.get_ptr(), b.get_ptr());
+	if a_ptr == b_ptr {
+		return;
+	}
+	borrow::check_write(A::borrow());
+	borrow::check_write(B::borrow());
+	// Different thread locals never overlap.
+	unsafe { core::ptr::swap_nonoverlapping(a_ptr, b_ptr, 1) }
+}
+
+/// Identifies a static thread local.
+///
+/// This is implemented by [`static_thread_local`] for a zero-sized type that's
+/// unique to each thread local. Because the type is known at compile time,
+/// accessing the thread local through a [`StaticThreadLocal`] compiles straight
+/// down to the TLS lookup without going through any function pointers.
+///
+/// # Safety
+///
+/// * [`key`](Self::key) must return the key of a static thread local in the
+///   implementing crate's module, with the type `Value` and initialized to
+///   [`INIT`](Self::INIT).
+/// * [`borrow`](Self::borrow) must return either null or a pointer to the
+///   current thread's copy of an `isize` thread local, initialized to `0`,
+///   that's only used by this key.
+pub unsafe trait StaticKey: 'static {
+	/// The type of the thread local.
+	type Value;
+	/// The value each thread's copy starts out with.
+	const INIT: Self::Value;
+
+	/// Returns the raw key for the thread local.
+	fn key() -> u32;
+
+	/// Returns the borrow flag used by `with` and `with_mut`.
+	fn borrow() -> *mut isize;
+
+	/// Returns a pointer to the current thread's value.
+	#[inline(always)]
+	fn ptr() -> *mut Self::Value {
+		unsafe { raw_internal::static_ptr(Self::key()) }
+	}
+
+	/// Checks the value is valid before it's read, panicking if it isn't.
+	///
+	/// By default this does nothing.
+	#[inline(always)]
+	fn validate(ptr: *const Self::Value) {
+		let _ = ptr;
+	}
+}
+
+/// Grants unsafe access to the thread local.
+///
+/// In general you should make sure that any references to the actual thread
+/// local are as short-lived as possible.
+///
+/// # Stale Pointers
+///
+/// If a new DLL is lazily loaded and that DLL requires the static TLS array to
+/// be expanded then, for each thread, a new TLS array will be created without
+/// dropping the old one. The old array will then be copied to the new one.
+///
+/// So now there are two copies of the thread local data. If a new pointer is
+/// made then it'll point to the new data but any old pointers will still point
+/// to the "stale" data. A [`TlsGuard`] can detect this.
+///
+/// # Borrow Tracking
+///
+/// With the `debug-borrows` feature, each [`unsafe_local`] also gets a borrow
+/// flag in crates with debug assertions enabled, as [`StaticThreadLocal`]
+/// does. [`with`](Self::with) and [`with_mut`](Self::with_mut) then panic if
+/// they would alias a mutable reference. [`as_ref`](Self::as_ref),
+/// [`as_ref_mut`](Self::as_ref_mut) and the other methods check the flag but
+/// can't track the references they return. The panic names the local.
+///
+/// Without the feature, or without debug assertions, there's no flag and
+/// nothing is checked.
+///
+/// # Thread Safety
+///
+/// As with [`StaticThreadLocal`], the handle can be shared between threads but
+/// `T` must be [`Send`] because each thread starts with a copy of the initial
+/// value. Likewise, fibers on the same thread share the value.
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::unsafe_local!{
+///     static DATA: *mut u8 = core::ptr::null_mut();
+/// }
+/// # fn main() {}
+/// ```
+pub struct UnsafeLocal<T> {
+	get: fn() -> *mut T,
+	#[cfg(feature = "debug-borrows")]
+	borrow: fn() -> *mut isize,
+	#[cfg(feature = "debug-borrows")]
+	name: Option<&'static str>,
+	dtor: Option<LocalDtor>,
+	_marker: PhantomData<T>,
+}
+// The "destructor is registered" flag and the function that drops the value.
+type LocalDtor = (fn() -> *mut bool, fn());
+// See "Thread Safety" in the docs above.
+unsafe impl<T: Send> Sync for UnsafeLocal<T> {}
+unsafe impl<T: Send> Send for UnsafeLocal<T> {}
+impl<T> UnsafeLocal<T> {
+	/// Creates a handle from a function that returns a pointer to the thread
+	/// local.
+	///
+	/// You should use [`unsafe_local`] instead of calling this directly.
+	///
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local with the type `T`.
+	///
+	/// # Example
+	///
+	/// The field can't be accessed directly, only through the methods.
+	///
+	/// ```compile_fail
+	/// # #![feature(asm)]
+	/// # wintls::unsafe_local!{
+	/// #     static DATA: u32 = 0xfeedface;
+	/// # }
+	/// # fn main() {
+	/// let ptr = (DATA.get)();
+	/// # }
+	/// ```
+	pub const unsafe fn new(get: fn() -> *mut T) -> Self {
+		Self {
+			get,
+			#[cfg(feature = "debug-borrows")]
+			borrow: core::ptr::null_mut,
+			#[cfg(feature = "debug-borrows")]
+			name: None,
+			dtor: None,
+			_marker: PhantomData,
+		}
+	}
+
+	/// Allows registering `drop` as a destructor.
+	///
+	/// # Safety
+	///
+	/// `registered` must return a pointer to the current thread's copy of a
+	/// flag that's only used by this local. `drop` must clear the flag and
+	/// drop the current thread's value.
+	#[doc(hidden)]
+	pub const unsafe fn with_dtor(self, registered: fn() -> *mut bool, drop: fn()) -> Self {
+		Self {
+			dtor: Some((registered, drop)),
+			..self
+		}
+	}
+
+	/// Tracks borrows using the flag returned by `borrow`.
+	///
+	/// # Safety
+	///
+	/// `borrow` must return a pointer to the current thread's copy of a flag
+	/// that's only used by this local, or null.
+	#[cfg(feature = "debug-borrows")]
+	#[doc(hidden)]
+	pub const unsafe fn with_borrow_flag(
+		self,
+		borrow: fn() -> *mut isize,
+		name: &'static str,
+	) -> Self {
+		Self {
+			borrow,
+			name: Some(name),
+			..self
+		}
+	}
+
+	#[inline(always)]
+	fn check_read(&self) {
+		#[cfg(feature = "debug-borrows")]
+		borrow::check_read_named((self.borrow)(), self.name);
+	}
+
+	#[inline(always)]
+	fn check_write(&self) {
+		#[cfg(feature = "debug-borrows")]
+		borrow::check_write_named((self.borrow)(), self.name);
+	}
+
+	/// Getting a pointer is safe.
+	/// Using it should be mostly safe (normal caveats aside) so long as there
+	/// aren't any active references. That said, you should almost certainly use
+	/// and discard the pointer asap.
+	pub fn as_ptr(&self) -> *mut T {
+		(self.get)()
+	}
+	/// Returns a pointer that checks it's only used on the current thread.
+	///
+	/// In debug builds the returned [`LocalPtr`] panics if it's used on
+	/// another thread. In release builds it's the same as
+	/// [`as_ptr`](Self::as_ptr).
+	#[inline(always)]
+	pub fn local_ptr(&self) -> LocalPtr<T> {
+		LocalPtr::new(self.as_ptr())
+	}
+	/// Returns a [`TlsGuard`] that checks pointers to the local haven't gone
+	/// stale.
+	#[inline]
+	pub fn guard(&self) -> TlsGuard {
+		TlsGuard::new()
+	}
+	/// Calls `f` with a reference to the current thread's value.
+	///
+	/// Unlike [`as_ref`](Self::as_ref), the reference can't outlive the call.
+	///
+	/// # Safety
+	///
+	/// There must not be a mutable reference to the value and the value must
+	/// not be mutated while `f` runs. In particular `f` must not call
+	/// [`with_mut`](Self::with_mut) on the same local.
+	///
+	/// # Example
+	///
+	/// A per-thread string that's dropped when the thread exits.
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// use wintls::dtor::register_dtor;
+	///
+	/// wintls::unsafe_local!{
+	///     static BUFFER: String = String::new();
+	/// }
+	///
+	/// fn push_str(s: &str) {
+	///     unsafe {
+	///         if BUFFER.with(String::is_empty) {
+	///             register_dtor(|| BUFFER.drop_value());
+	///         }
+	///         BUFFER.with_mut(|buffer| buffer.push_str(s));
+	///     }
+	/// }
+	///
+	/// fn main() {
+	///     push_str("Hello!");
+	///     std::thread::spawn(|| {
+	///         push_str(" World!");
+	///         unsafe { BUFFER.with(|buffer| assert_eq!(buffer, " World!")) };
+	///     })
+	///     .join()
+	///     .unwrap();
+	///     unsafe { BUFFER.with(|buffer| assert_eq!(buffer, "Hello!")) };
+	/// }
+	/// ```
+	#[inline]
+	pub unsafe fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		#[cfg(feature = "debug-borrows")]
+		let _guard = borrow::BorrowGuard::shared_named((self.borrow)(), self.name);
+		f(&*self.as_ptr())
+	}
+	/// Calls `f` with a mutable reference to the current thread's value.
+	///
+	/// Unlike [`as_ref_mut`](Self::as_ref_mut), the reference can't outlive
+	/// the call.
+	///
+	/// # Safety
+	///
+	/// There must not be any other references to the value while `f` runs. In
+	/// particular `f` must not call [`with`](Self::with) or `with_mut` on the
+	/// same local.
+	#[inline]
+	pub unsafe fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		#[cfg(feature = "debug-borrows")]
+		let _guard = borrow::BorrowGuard::exclusive_named((self.borrow)(), self.name);
+		f(&mut *self.as_ptr())
+	}
+
+	/// There can be many shared references but there must not be a mutable
+	/// reference at all. Also no mutation should occur for the lifetime of this
+	/// reference.
+	///
+	/// The reference lives as long as `&self`, which is usually much longer
+	/// than needed. Prefer [`with`](Self::with).
+	pub unsafe fn as_ref(&self) -> &T {
+		self.check_read();
+		&*self.as_ptr()
+	}
+	/// There can only be one mutable reference at a time and there must not be
+	/// any shared references. Also mutation should only happen via this
+	/// reference and not through any pointer.
+	///
+	/// Prefer [`with_mut`](Self::with_mut), which limits the reference's
+	/// lifetime.
+	pub unsafe fn as_ref_mut(&self) -> &mut T {
+		self.check_write();
+		&mut *self.as_ptr()
+	}
+
+	/// Replaces the current thread's value, returning the old value.
+	///
+	/// # Safety
+	///
+	/// There must not be any references to the value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::unsafe_local!{
+	///     static QUEUE: Vec<u32> = Vec::new();
+	/// }
+	/// # fn main() {
+	/// unsafe {
+	///     QUEUE.with_mut(|queue| queue.push(1));
+	///     let old = QUEUE.replace(vec![2]);
+	///     assert_eq!(old, [1]);
+	/// }
+	/// # }
+	/// ```
+	#[inline]
+	pub unsafe fn replace(&self, value: T) -> T {
+		self.check_write();
+		core::ptr::replace(self.as_ptr(), value)
+	}
+	/// Takes the current thread's value, leaving `Default::default()` in its
+	/// place.
+	///
+	/// # Safety
+	///
+	/// There must not be any references to the value.
+	#[inline]
+	pub unsafe fn take(&self) -> T
+	where
+		T: Default,
+	{
+		self.replace(T::default())
+	}
+
+	/// Writes a new value without dropping the old one.
+	///
+	/// This is the way to create a new value after
+	/// [`drop_value`](Self::drop_value). Otherwise the old value is leaked.
+	///
+	/// # Safety
+	///
+	/// There must not be any references to the value.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::unsafe_local!{
+	///     static NAME: String = String::new();
+	/// }
+	/// # fn main() {
+	/// unsafe {
+	///     NAME.drop_value();
+	///     NAME.write(String::from("main"));
+	///     NAME.with(|name| assert_eq!(name, "main"));
+	///     NAME.drop_value();
+	/// }
+	/// # }
+	/// ```
+	#[inline]
+	pub unsafe fn write(&self, value: T) {
+		self.check_write();
+		self.as_ptr().write(value)
+	}
+	/// Sets a new value, dropping the old one.
+	///
+	/// This must not be used after [`drop_value`](Self::drop_value) because
+	/// that would drop the value twice. Use [`write`](Self::write) instead.
+	///
+	/// # Safety
+	///
+	/// There must not be any references to the value.
+	#[inline]
+	pub unsafe fn set(&self, value: T) {
+		self.check_write();
+		*self.as_ptr() = value;
+	}
+	/// Returns a copy of the thread local's value.
+	///
+	/// This is the same as [`StaticThreadLocal::get`]. The pointer is only
+	/// looked up once and no reference escapes.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::unsafe_local!{
+	///     static COUNT: u32 = 0;
+	/// }
+	/// # fn main() {
+	/// COUNT.set_copy(COUNT.get_copy() + 1);
+	/// assert_eq!(COUNT.get_copy(), 1);
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn get_copy(&self) -> T
+	where
+		T: Copy,
+	{
+		self.check_read();
+		unsafe { *self.as_ptr() }
+	}
+	/// Sets the value of the thread local.
+	///
+	/// This is the same as [`StaticThreadLocal::set`]. Unlike [`set`](Self::set)
+	/// it's safe, because a `Copy` value doesn't need dropping.
+	#[inline(always)]
+	pub fn set_copy(&self, value: T)
+	where
+		T: Copy,
+	{
+		self.check_write();
+		unsafe { self.as_ptr().write(value) }
+	}
+
+	/// Registers a destructor that drops the current thread's value when the
+	/// thread exits.
+	///
+	/// Only the first call on each thread registers the destructor. Later calls
+	/// just check a flag. Once the destructor has run, the next call registers
+	/// it again.
+	///
+	/// This does nothing for handles made by
+	/// [`StaticThreadLocal::as_unsafe_local`], which never need dropping.
+	///
+	/// # Safety
+	///
+	/// The value must not be used after the destructor has run, unless a new
+	/// value is created with [`write`](Self::write).
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::unsafe_local!{
+	///     static NAME: String = String::new();
+	/// }
+	///
+	/// fn push_str(s: &str) {
+	///     unsafe {
+	///         NAME.register_drop_for_current_thread();
+	///         NAME.with_mut(|name| name.push_str(s));
+	///     }
+	/// }
+	/// # fn main() {
+	/// # push_str("main");
+	/// # }
+	/// ```
+	#[inline]
+	pub unsafe fn register_drop_for_current_thread(&self) {
+		if let Some((registered, drop)) = self.dtor {
+			let registered = registered();
+			if !*registered {
+				*registered = true;
+				dtor::register_dtor(drop);
+			}
+		}
+	}
+
+	/// Drops the memory. No further use of the memory should occur after
+	/// calling this, unless a new value is created in place with
+	/// [`write`](Self::write).
 	pub unsafe fn drop_value(&self) {
+		self.check_write();
 		core::ptr::drop_in_place(self.as_ptr());
 	}
 }
 
+/// Formats the current thread's value.
+///
+/// The value is briefly borrowed, as with [`UnsafeLocal::with`]. This is
+/// unsound if there's a mutable reference to the value at the same time. With
+/// the `debug-borrows` feature that panics instead.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::unsafe_local!{
+///     static NAMES: Vec<&'static str> = Vec::new();
+/// }
+///
+/// fn main() {
+///     unsafe { NAMES.with_mut(|names| names.push("main")) };
+///     println!("{:?}", NAMES); // UnsafeLocal(["main"])
+///     std::thread::spawn(|| {
+///         unsafe { NAMES.with_mut(|names| names.push("spawned")) };
+///         println!("{:?}", NAMES); // UnsafeLocal(["spawned"])
+///     }).join();
+/// }
+/// ```
+impl<T: core::fmt::Debug> core::fmt::Debug for UnsafeLocal<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		unsafe { self.with(|value| f.debug_tuple("UnsafeLocal").field(value).finish()) }
+	}
+}
+
+/// Displays the current thread's value.
+///
+/// The same caveats apply as for the [`Debug`](core::fmt::Debug) impl.
+impl<T: core::fmt::Display> core::fmt::Display for UnsafeLocal<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		unsafe { self.with(|value| value.fmt(f)) }
+	}
+}
+
 /// Create an [`UnsafeLocal`].
+///
+/// Any number of locals can be declared at once. The visibility applies to the
+/// handle. The underlying `.tls$` static is always private.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// pub mod tls {
+///     wintls::unsafe_local!{
+///         pub static NAMES: Vec<&'static str> = Vec::new();
+///     }
+/// }
+///
+/// fn main() {
+///     unsafe { tls::NAMES.as_ref_mut().push("main") };
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// mod tls {
+///     wintls::unsafe_local!{
+///         static NAMES: Vec<&'static str> = Vec::new();
+///     }
+/// }
+///
+/// fn main() {
+///     unsafe { tls::NAMES.as_ref_mut().push("main") };
+/// }
+/// ```
 #[macro_export]
 macro_rules! unsafe_local {
-	($vis:vis static $name:ident: $ty:ty = $value:expr;) => {
-		static $name: $crate::UnsafeLocal<$ty> = {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::UnsafeLocal<$ty> = {
 			$crate::init_static!(
 				static $name: $ty = $value;
+				static REGISTERED: bool = false;
 			);
-			$crate::UnsafeLocal {
-				get: || unsafe { $crate::static_ptr!($name) },
+			fn registered() -> *mut bool {
+				unsafe { $crate::static_ptr!(REGISTERED) }
+			}
+			fn drop_value() {
+				unsafe {
+					*registered() = false;
+					::core::ptr::drop_in_place::<$ty>($crate::static_ptr!($name));
+				}
 			}
+			$crate::unsafe_local_new!($name, registered, drop_value)
 		};
+	)+};
+}
+
+// Creates an `UnsafeLocal`, with a borrow flag if `debug-borrows` is enabled.
+// This has to be decided by a macro in this crate so that it depends on this
+// crate's features rather than the user's.
+#[cfg(feature = "debug-borrows")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! unsafe_local_new {
+	($name:ident, $registered:ident, $drop:ident) => {{
+		$crate::static_thread_local! { @borrow_fn }
+		unsafe {
+			$crate::UnsafeLocal::new(|| $crate::static_ptr!($name))
+				.with_dtor($registered, $drop)
+				.with_borrow_flag(borrow, stringify!($name))
+		}
+	}};
+}
+#[cfg(not(feature = "debug-borrows"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! unsafe_local_new {
+	($name:ident, $registered:ident, $drop:ident) => {
+		unsafe {
+			$crate::UnsafeLocal::new(|| $crate::static_ptr!($name)).with_dtor($registered, $drop)
+		}
 	};
 }
 
-// `_tls_used` is where the TLS directory information is stored.
-// It must be included by the linker.
-#[cfg(not(target_arch = "x86"))]
-#[link_section = ".drectve"]
-#[used]
-static DIRECTIVE: [u8; 19] = *b"/INCLUDE:_tls_used ";
-// On x86 the name is mangled by prefixing another underscore.
-#[cfg(target_arch = "x86")]
-#[link_section = ".drectve"]
-#[used]
-static DIRECTIVE: [u8; 20] = *b"/INCLUDE:__tls_used ";
+// Without `std` there's no way to tell, so it's assumed there's no panic.
+#[cfg(feature = "std")]
+pub(crate) fn panicking() -> bool {
+	std::thread::panicking()
+}
+#[cfg(not(feature = "std"))]
+pub(crate) fn panicking() -> bool {
+	false
+}
@@ -0,0 +1,325 @@
+//! A replacement for `std`'s `thread_local!`.
+
+use crate::lazy::{self, LazyValue, DESTROYED, DROPPING, INIT};
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+/// Declares thread locals with a [`Local`] handle.
+///
+/// This works like `std`'s `thread_local!`. Each value is initialized the
+/// first time it's used on a thread, and dropped when that thread exits. A
+/// value used by the main thread is dropped when the process exits normally.
+///
+/// Once a value has been dropped it can't be used again on the same thread.
+/// [`Local::with`] panics, while [`Local::try_with`] returns an error.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// use std::cell::RefCell;
+///
+/// wintls::local!{
+///     static BUF: RefCell<String> = RefCell::new(String::new());
+/// }
+///
+/// fn main() {
+///     std::thread::spawn(|| {
+///         BUF.with(|buf| buf.borrow_mut().push_str("hello"));
+///         assert_eq!(BUF.with(|buf| buf.borrow().len()), 5);
+///         // `BUF` is dropped here.
+///     })
+///     .join()
+///     .unwrap();
+/// }
+/// ```
+#[macro_export]
+macro_rules! local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::Local<$ty> = {
+			// The storage is declared inside `get` so that the initializer can
+			// refer to the handle.
+			fn get() -> *mut $crate::LazyValue<$ty> {
+				$crate::init_static!(
+					static $name: $crate::LazyValue<$ty> = $crate::LazyValue::UNINIT;
+				);
+				unsafe { $crate::static_ptr!($name) }
+			}
+			fn init() -> $ty {
+				$value
+			}
+			fn destroy() {
+				unsafe { $crate::Local::<$ty>::destroy(get) }
+			}
+			unsafe { $crate::Local::new(get, init, destroy, stringify!($name)) }
+		};
+	)+};
+}
+
+/// A handle to a thread local declared with [`local`](crate::local).
+///
+/// This has the same methods as `std::thread::LocalKey`, so code can switch
+/// between the two by changing the declaration.
+///
+/// The value is only borrowed for the duration of a closure. The same caveats
+/// as for [`StaticThreadLocal::with`](crate::StaticThreadLocal::with) apply
+/// within the closure.
+pub struct Local<T: 'static> {
+	get: fn() -> *mut LazyValue<T>,
+	init: fn() -> T,
+	destroy: fn(),
+	name: &'static str,
+}
+// Each thread creates its own value with `init`, and `destroy` is registered
+// as a destructor of that thread. `T` must be `Send` because that destructor
+// can be run by another thread (see `dtor::enable_process_exit_drain`).
+unsafe impl<T: Send> Sync for Local<T> {}
+unsafe impl<T: Send> Send for Local<T> {}
+impl<T> Local<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out uninitialized and is only used by this
+	/// handle. `destroy` must call [`destroy`](Self::destroy) with `get`.
+	#[doc(hidden)]
+	pub const unsafe fn new(
+		get: fn() -> *mut LazyValue<T>,
+		init: fn() -> T,
+		destroy: fn(),
+		name: &'static str,
+	) -> Self {
+		Self {
+			get,
+			init,
+			destroy,
+			name,
+		}
+	}
+
+	/// Calls `f` with a reference to the value, initializing it first if
+	/// necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is being dropped or has been dropped, or if this is
+	/// called by the initializer.
+	#[inline(always)]
+	#[track_caller]
+	pub fn with<F, R>(&'static self, f: F) -> R
+	where
+		F: FnOnce(&T) -> R,
+	{
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the value, initializing it first if
+	/// necessary. Returns an error if the value is being dropped or has been
+	/// dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called by the initializer.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::local!{
+	///     static NAME: String = String::from("main");
+	/// }
+	/// # fn main() {
+	/// assert_eq!(NAME.try_with(|name| name.len()), Ok(4));
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn try_with<F, R>(&'static self, f: F) -> Result<R, AccessError>
+	where
+		F: FnOnce(&T) -> R,
+	{
+		let mut lazy = (self.get)();
+		unsafe {
+			if (*lazy).state != INIT {
+				if (*lazy).state == DROPPING || (*lazy).state == DESTROYED {
+					return Err(AccessError::new(self.name));
+				}
+				lazy = self.initialize(lazy);
+			}
+			Ok(f((*lazy).value.assume_init_ref()))
+		}
+	}
+
+	/// Drops the value.
+	///
+	/// # Safety
+	///
+	/// `get` must be the function passed to [`new`](Self::new). This must only
+	/// be called by the registered destructor.
+	#[doc(hidden)]
+	pub unsafe fn destroy(get: fn() -> *mut LazyValue<T>) {
+		let lazy = get();
+		debug_assert_eq!((*lazy).state, INIT);
+		(*lazy).state = DROPPING;
+		(*lazy).value.assume_init_drop();
+		// The drop may have loaded a library, which can move the thread locals.
+		(*get()).state = DESTROYED;
+	}
+
+	#[cold]
+	#[inline(never)]
+	unsafe fn initialize(&self, lazy: *mut LazyValue<T>) -> *mut LazyValue<T> {
+		let lazy = lazy::initialize(lazy, self.get, self.init, self.name);
+		crate::dtor::register_dtor(self.destroy);
+		lazy
+	}
+}
+
+impl<T: 'static> Local<Cell<T>> {
+	/// Sets the value, initializing the thread local first if necessary.
+	///
+	/// # Panics
+	///
+	/// Panics in the same cases as [`with`](Self::with).
+	#[track_caller]
+	pub fn set(&'static self, value: T) {
+		self.with(|cell| cell.set(value))
+	}
+
+	/// Returns a copy of the value.
+	///
+	/// # Panics
+	///
+	/// Panics in the same cases as [`with`](Self::with).
+	#[track_caller]
+	pub fn get(&'static self) -> T
+	where
+		T: Copy,
+	{
+		self.with(Cell::get)
+	}
+
+	/// Takes the value, leaving `Default::default()` in its place.
+	///
+	/// # Panics
+	///
+	/// Panics in the same cases as [`with`](Self::with).
+	#[track_caller]
+	pub fn take(&'static self) -> T
+	where
+		T: Default,
+	{
+		self.with(Cell::take)
+	}
+
+	/// Replaces the value, returning the old one.
+	///
+	/// # Panics
+	///
+	/// Panics in the same cases as [`with`](Self::with).
+	#[track_caller]
+	pub fn replace(&'static self, value: T) -> T {
+		self.with(|cell| cell.replace(value))
+	}
+}
+
+impl<T: 'static> Local<RefCell<T>> {
+	/// Calls `f` with a reference to the borrowed value.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed, or in the same cases as
+	/// [`with`](Self::with).
+	#[track_caller]
+	pub fn with_borrow<F, R>(&'static self, f: F) -> R
+	where
+		F: FnOnce(&T) -> R,
+	{
+		self.with(|cell| f(&cell.borrow()))
+	}
+
+	/// Calls `f` with a mutable reference to the borrowed value.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, or in the same cases as
+	/// [`with`](Self::with).
+	#[track_caller]
+	pub fn with_borrow_mut<F, R>(&'static self, f: F) -> R
+	where
+		F: FnOnce(&mut T) -> R,
+	{
+		self.with(|cell| f(&mut cell.borrow_mut()))
+	}
+
+	/// Sets the value, initializing the thread local first if necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, or in the same cases as
+	/// [`with`](Self::with).
+	#[track_caller]
+	pub fn set(&'static self, value: T) {
+		self.with_borrow_mut(|old| *old = value)
+	}
+
+	/// Takes the value, leaving `Default::default()` in its place.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, or in the same cases as
+	/// [`with`](Self::with).
+	#[track_caller]
+	pub fn take(&'static self) -> T
+	where
+		T: Default,
+	{
+		self.with(RefCell::take)
+	}
+
+	/// Replaces the value, returning the old one.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed, or in the same cases as
+	/// [`with`](Self::with).
+	#[track_caller]
+	pub fn replace(&'static self, value: T) -> T {
+		self.with(|cell| cell.replace(value))
+	}
+}
+
+/// The name of `std`'s type, for code that refers to it.
+pub type LocalKey<T> = Local<T>;
+
+/// The error returned by [`Local::try_with`], and the `try_with` methods of the
+/// other thread locals that drop their values, when the value is being dropped
+/// or has been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError {
+	name: &'static str,
+}
+impl AccessError {
+	pub(crate) fn new(name: &'static str) -> Self {
+		Self { name }
+	}
+
+	#[cold]
+	#[track_caller]
+	pub(crate) fn panic(self) -> ! {
+		panic!("{self}")
+	}
+}
+impl fmt::Display for AccessError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"thread local `{}` was accessed during or after its destruction",
+			self.name
+		)
+	}
+}
+#[cfg(feature = "std")]
+impl std::error::Error for AccessError {}
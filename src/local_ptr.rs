@@ -0,0 +1,102 @@
+//! A pointer to a thread local that checks it's used on the right thread.
+
+use crate::raw_internal::current_thread_id;
+
+/// A pointer to the thread local of the thread that created it.
+///
+/// This is returned by [`UnsafeLocal::local_ptr`](crate::UnsafeLocal::local_ptr).
+/// A pointer to a thread local still points to the creating thread's copy
+/// when it's used on another thread, which is almost certainly a bug. So in
+/// debug builds the creating thread's id is recorded and every access panics
+/// if it's made on a different thread. In release builds this is just a
+/// pointer.
+///
+/// Unlike a raw pointer, this can be sent to another thread so that the
+/// mistake can be caught.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// wintls::unsafe_local!{
+///     static COUNT: u32 = 0;
+/// }
+/// # fn main() {
+/// let ptr = COUNT.local_ptr();
+/// unsafe { ptr.write(ptr.read() + 1) };
+/// # }
+/// ```
+pub struct LocalPtr<T> {
+	ptr: *mut T,
+	#[cfg(debug_assertions)]
+	thread: u32,
+}
+// Every access checks the thread in debug builds.
+unsafe impl<T> Send for LocalPtr<T> {}
+impl<T> LocalPtr<T> {
+	#[inline(always)]
+	pub(crate) fn new(ptr: *mut T) -> Self {
+		Self {
+			ptr,
+			#[cfg(debug_assertions)]
+			thread: current_thread_id(),
+		}
+	}
+
+	/// Returns the pointer.
+	///
+	/// # Panics
+	///
+	/// In debug builds, panics if this isn't the thread that created the
+	/// pointer.
+	#[inline(always)]
+	pub fn as_ptr(&self) -> *mut T {
+		#[cfg(debug_assertions)]
+		{
+			let current = current_thread_id();
+			if current != self.thread {
+				wrong_thread(self.thread, current);
+			}
+		}
+		self.ptr
+	}
+
+	/// Reads the value.
+	///
+	/// # Safety
+	///
+	/// The same rules apply as for [`ptr::read`](core::ptr::read).
+	///
+	/// # Panics
+	///
+	/// In debug builds, panics if this isn't the thread that created the
+	/// pointer.
+	#[inline(always)]
+	pub unsafe fn read(&self) -> T
+	where
+		T: Copy,
+	{
+		self.as_ptr().read()
+	}
+
+	/// Writes the value without dropping the old one.
+	///
+	/// # Safety
+	///
+	/// The same rules apply as for [`ptr::write`](core::ptr::write).
+	///
+	/// # Panics
+	///
+	/// In debug builds, panics if this isn't the thread that created the
+	/// pointer.
+	#[inline(always)]
+	pub unsafe fn write(&self, value: T) {
+		self.as_ptr().write(value)
+	}
+}
+
+#[cfg(debug_assertions)]
+#[cold]
+fn wrong_thread(created: u32, current: u32) -> ! {
+	panic!("a pointer to a thread local created on thread {created} was used on thread {current}")
+}
@@ -0,0 +1,131 @@
+//! Handles to thread locals that remember which module they belong to.
+
+use crate::{borrow, raw_internal};
+use core::marker::PhantomData;
+
+/// Declares thread locals with a [`ModuleLocal`] handle.
+///
+/// The handle records the declaring module's TLS index and a function that
+/// returns the local's key. Both are resolved in the declaring module, so the
+/// handle can be given to other modules (e.g. from a DLL to the EXE) and still
+/// finds the right thread local, even when the accessors are inlined into the
+/// other module.
+///
+/// Compared to [`dll_safe_thread_local`](crate::dll_safe_thread_local), the
+/// lookup itself is inlined, which means an indirect call for the key rather
+/// than for every access. As with
+/// [`static_thread_local`](crate::static_thread_local), types that need
+/// dropping are rejected.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::module_local!{
+///     pub static COUNTER: u32 = 0;
+/// }
+///
+/// fn main() {
+///     COUNTER.set(COUNTER.get() + 1);
+///     assert_eq!(COUNTER.get(), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::ModuleLocal<$ty> = {
+			$crate::static_thread_local!{ @assert_no_drop $name: $ty }
+			// These are only called through the handle so they're always
+			// run in this module.
+			fn key() -> u32 {
+				$crate::init_static!(
+					static $name: $ty = $value;
+				);
+				unsafe { $crate::static_key!($name) }
+			}
+			$crate::static_thread_local!{ @borrow_fn }
+			unsafe { $crate::ModuleLocal::new(&$crate::raw_internal::_tls_index, key, borrow) }
+		};
+	)+};
+}
+
+/// A handle to a thread local declared with
+/// [`module_local`](crate::module_local).
+///
+/// Borrows are tracked in the same way as for
+/// [`StaticThreadLocal`](crate::StaticThreadLocal), when the declaring crate
+/// has debug assertions enabled.
+pub struct ModuleLocal<T: 'static> {
+	module: &'static u32,
+	key: fn() -> u32,
+	borrow: fn() -> *mut isize,
+	_marker: PhantomData<T>,
+}
+// The module's TLS index and the key are the same on every thread, and each
+// thread uses them to find its own copy. Borrows are tracked per thread. `T`
+// must be `Send` because each thread starts with a copy of the initial value.
+unsafe impl<T: Send> Sync for ModuleLocal<T> {}
+unsafe impl<T: Send> Send for ModuleLocal<T> {}
+impl<T> Clone for ModuleLocal<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<T> Copy for ModuleLocal<T> {}
+impl<T> ModuleLocal<T> {
+	/// # Safety
+	///
+	/// `module` must be the `_tls_index` of the module that declares the
+	/// thread local identified by `key`, which must have the type `T` and not
+	/// need dropping. `borrow` must return a borrow flag for it, or null.
+	#[doc(hidden)]
+	pub const unsafe fn new(
+		module: &'static u32,
+		key: fn() -> u32,
+		borrow: fn() -> *mut isize,
+	) -> Self {
+		Self {
+			module,
+			key,
+			borrow,
+			_marker: PhantomData,
+		}
+	}
+
+	/// Returns a pointer to the current thread's value.
+	///
+	/// The same caveats apply as for
+	/// [`StaticThreadLocal::get_ptr`](crate::StaticThreadLocal::get_ptr).
+	#[inline(always)]
+	pub fn get_ptr(&self) -> *mut T {
+		unsafe { raw_internal::static_ptr_from_module(*self.module, (self.key)()) }
+	}
+
+	/// Returns the value of the thread local.
+	#[inline(always)]
+	pub fn get(&self) -> T
+	where
+		T: Copy,
+	{
+		borrow::check_read((self.borrow)());
+		unsafe { *self.get_ptr() }
+	}
+
+	/// Sets the value of the thread local.
+	#[inline(always)]
+	pub fn set(&self, value: T) {
+		borrow::check_write((self.borrow)());
+		unsafe { self.get_ptr().write(value) }
+	}
+
+	/// Calls `f` with a reference to the thread local.
+	///
+	/// See [`StaticThreadLocal::with`](crate::StaticThreadLocal::with).
+	#[inline(always)]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		let _guard = borrow::BorrowGuard::shared((self.borrow)());
+		unsafe { f(&*self.get_ptr()) }
+	}
+}
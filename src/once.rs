@@ -0,0 +1,122 @@
+//! Running code once per thread.
+
+const NEW: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+const POISONED: u8 = 3;
+
+/// Declares [`OnceLocal`]s.
+///
+/// Each one is a single byte of TLS.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::once_local!{
+///     static THREAD_START;
+/// }
+///
+/// fn on_request() {
+///     THREAD_START.call_once(|| println!("first request on this thread"));
+///     // ...
+/// }
+///
+/// fn main() {
+///     on_request();
+///     on_request();
+///     assert!(THREAD_START.is_completed());
+/// }
+/// ```
+#[macro_export]
+macro_rules! once_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::OnceLocal = {
+			$crate::init_static!(
+				static $name: u8 = 0;
+			);
+			unsafe { $crate::OnceLocal::new(|| $crate::static_ptr!($name), stringify!($name)) }
+		};
+	)+};
+}
+
+/// Runs a closure at most once on each thread.
+///
+/// This is like [`std::sync::Once`] except that each thread has its own state,
+/// so no synchronization is needed. A panic in the closure poisons the
+/// `OnceLocal` for the current thread.
+///
+/// [`call_once`](Self::call_once) can be used from destructors and from the
+/// closure of a different `OnceLocal`.
+pub struct OnceLocal {
+	get: fn() -> *mut u8,
+	name: &'static str,
+}
+impl OnceLocal {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out as `0` and is only used by this handle.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> *mut u8, name: &'static str) -> Self {
+		Self { get, name }
+	}
+
+	/// Calls `f` if this is the first call on the current thread.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called from `f`, or if a previous `f` panicked on the
+	/// current thread.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::once_local!{
+	///     static REGISTER;
+	/// }
+	/// # fn main() {
+	/// REGISTER.call_once(|| wintls::dtor::register_dtor(|| println!("exiting")));
+	/// # }
+	/// ```
+	#[inline]
+	pub fn call_once(&self, f: impl FnOnce()) {
+		if unsafe { *(self.get)() } != COMPLETE {
+			self.call_slow(f);
+		}
+	}
+
+	/// Returns `true` if [`call_once`](Self::call_once) has completed on the
+	/// current thread.
+	#[inline]
+	pub fn is_completed(&self) -> bool {
+		unsafe { *(self.get)() == COMPLETE }
+	}
+
+	#[cold]
+	fn call_slow(&self, f: impl FnOnce()) {
+		unsafe {
+			match *(self.get)() {
+				NEW => {}
+				RUNNING => panic!("`{}.call_once` was called from its own closure", self.name),
+				_ => panic!("`{}` was poisoned by a panic in `call_once`", self.name),
+			}
+			*(self.get)() = RUNNING;
+			let poison = PoisonOnUnwind(self.get);
+			f();
+			core::mem::forget(poison);
+			// `f` may have loaded a library, which can move the thread locals.
+			*(self.get)() = COMPLETE;
+		}
+	}
+}
+
+struct PoisonOnUnwind(fn() -> *mut u8);
+impl Drop for PoisonOnUnwind {
+	fn drop(&mut self) {
+		unsafe { *(self.0)() = POISONED }
+	}
+}
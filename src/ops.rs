@@ -0,0 +1,168 @@
+//! Helpers for thread locals that store integers or flags.
+//!
+//! These all look up the thread local once and then modify it in place.
+//!
+//! # Example
+//!
+//! ```
+//! #![feature(asm)]
+//!
+//! wintls::static_thread_local!{
+//!     static EVENTS: u32 = 0;
+//! }
+//!
+//! fn main() {
+//!     EVENTS.increment();
+//!     assert_eq!(EVENTS.add(2), 3);
+//! }
+//! ```
+
+use crate::{StaticKey, StaticThreadLocal};
+
+mod private {
+	pub trait Sealed {}
+}
+
+/// Integer types that can be used with the arithmetic helpers.
+///
+/// This trait is sealed and cannot be implemented outside this crate.
+pub trait TlsInt:
+	Copy + core::ops::Add<Output = Self> + core::ops::Sub<Output = Self> + private::Sealed
+{
+	#[doc(hidden)]
+	const ONE: Self;
+	#[doc(hidden)]
+	fn wrapping_add(self, rhs: Self) -> Self;
+	#[doc(hidden)]
+	fn wrapping_sub(self, rhs: Self) -> Self;
+	#[doc(hidden)]
+	fn saturating_add(self, rhs: Self) -> Self;
+	#[doc(hidden)]
+	fn saturating_sub(self, rhs: Self) -> Self;
+}
+macro_rules! tls_int {
+	($($t:ty)+) => {
+		$(
+			impl private::Sealed for $t {}
+			impl TlsInt for $t {
+				const ONE: Self = 1;
+				#[inline(always)]
+				fn wrapping_add(self, rhs: Self) -> Self {
+					<$t>::wrapping_add(self, rhs)
+				}
+				#[inline(always)]
+				fn wrapping_sub(self, rhs: Self) -> Self {
+					<$t>::wrapping_sub(self, rhs)
+				}
+				#[inline(always)]
+				fn saturating_add(self, rhs: Self) -> Self {
+					<$t>::saturating_add(self, rhs)
+				}
+				#[inline(always)]
+				fn saturating_sub(self, rhs: Self) -> Self {
+					<$t>::saturating_sub(self, rhs)
+				}
+			}
+		)+
+	};
+}
+tls_int!(u8 u16 u32 u64 usize i8 i16 i32 i64 isize);
+
+/// Unsigned integer types that can be used as bit flags.
+///
+/// This trait is sealed and cannot be implemented outside this crate.
+pub trait TlsBits:
+	TlsInt
+	+ PartialEq
+	+ core::ops::BitAnd<Output = Self>
+	+ core::ops::BitOr<Output = Self>
+	+ core::ops::Not<Output = Self>
+{
+}
+impl TlsBits for u8 {}
+impl TlsBits for u16 {}
+impl TlsBits for u32 {}
+impl TlsBits for u64 {}
+impl TlsBits for usize {}
+
+impl<T: TlsInt, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	/// Adds `n` to the thread local and returns the new value.
+	///
+	/// This behaves like the `+` operator so it will panic on overflow if
+	/// overflow checks are enabled.
+	#[inline(always)]
+	pub fn add(&self, n: T) -> T {
+		self.update(|value| value + n)
+	}
+
+	/// Subtracts `n` from the thread local and returns the new value.
+	///
+	/// This behaves like the `-` operator so it will panic on overflow if
+	/// overflow checks are enabled.
+	#[inline(always)]
+	pub fn sub(&self, n: T) -> T {
+		self.update(|value| value - n)
+	}
+
+	/// Adds one to the thread local and returns the new value.
+	#[inline(always)]
+	pub fn increment(&self) -> T {
+		self.add(T::ONE)
+	}
+
+	/// Adds `n` to the thread local, wrapping around on overflow, and returns
+	/// the new value.
+	#[inline(always)]
+	pub fn wrapping_add(&self, n: T) -> T {
+		self.update(|value| value.wrapping_add(n))
+	}
+
+	/// Subtracts `n` from the thread local, wrapping around on overflow, and
+	/// returns the new value.
+	#[inline(always)]
+	pub fn wrapping_sub(&self, n: T) -> T {
+		self.update(|value| value.wrapping_sub(n))
+	}
+
+	/// Adds `n` to the thread local, saturating at the numeric bounds, and
+	/// returns the new value.
+	#[inline(always)]
+	pub fn saturating_add(&self, n: T) -> T {
+		self.update(|value| value.saturating_add(n))
+	}
+
+	/// Subtracts `n` from the thread local, saturating at the numeric bounds,
+	/// and returns the new value.
+	#[inline(always)]
+	pub fn saturating_sub(&self, n: T) -> T {
+		self.update(|value| value.saturating_sub(n))
+	}
+}
+
+impl<T: TlsBits, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	/// Sets the bits in `mask` and returns the new value.
+	#[inline(always)]
+	pub fn set_bits(&self, mask: T) -> T {
+		self.update(|value| value | mask)
+	}
+
+	/// Clears the bits in `mask` and returns the new value.
+	#[inline(always)]
+	pub fn clear_bits(&self, mask: T) -> T {
+		self.update(|value| value & !mask)
+	}
+
+	/// Returns `true` if all the bits in `mask` are set.
+	#[inline(always)]
+	pub fn test_bits(&self, mask: T) -> bool {
+		self.get() & mask == mask
+	}
+}
+
+impl<K: StaticKey<Value = bool>> StaticThreadLocal<bool, K> {
+	/// Flips the flag and returns the new value.
+	#[inline(always)]
+	pub fn toggle(&self) -> bool {
+		self.update(|value| !value)
+	}
+}
@@ -0,0 +1,53 @@
+//! Helpers for thread locals that store an `Option`.
+
+use crate::{borrow, StaticKey, StaticThreadLocal};
+
+impl<T: Copy, K: StaticKey<Value = Option<T>>> StaticThreadLocal<Option<T>, K> {
+	/// Returns the contained value, first setting it to the result of `f` if
+	/// it's `None`.
+	///
+	/// `f` is free to access other thread locals (or even this one, although
+	/// any value it sets will be overwritten). As with
+	/// [`update`](StaticThreadLocal::update), the thread local is only looked
+	/// up once.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// # use wintls::static_thread_local;
+	/// #
+	/// # static_thread_local!{
+	/// #     static HANDLE: Option<usize> = None;
+	/// # }
+	/// # fn main() {
+	/// let handle = HANDLE.get_or_set_with(|| 42);
+	/// assert_eq!(HANDLE.get(), Some(42));
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn get_or_set_with(&self, f: impl FnOnce() -> T) -> T {
+		let ptr = self.get_ptr();
+		borrow::check_read(K::borrow());
+		K::validate(ptr);
+		if let Some(value) = unsafe { *ptr } {
+			return value;
+		}
+		let value = f();
+		borrow::check_write(K::borrow());
+		unsafe { *ptr = Some(value) };
+		value
+	}
+
+	/// Returns `true` if the thread local contains a value.
+	#[inline(always)]
+	pub fn is_set(&self) -> bool {
+		self.get().is_some()
+	}
+
+	/// Sets the thread local to `None`.
+	#[inline(always)]
+	pub fn clear(&self) {
+		self.set(None)
+	}
+}
@@ -0,0 +1,190 @@
+//! Per-thread pools of reusable objects.
+
+use crate::HeapLocal;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Declares [`LocalPool`]s.
+///
+/// Each thread keeps a list of free objects in a [`heap_local`]. The
+/// configuration is a [`PoolConfig`], which must be a constant expression.
+///
+/// [`heap_local`]: crate::heap_local
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// use wintls::PoolConfig;
+///
+/// wintls::local_pool!{
+///     static BUFFERS: Vec<u8> = PoolConfig::new(|| Vec::with_capacity(4096)).max_free(16);
+/// }
+///
+/// fn main() {
+///     let mut buffer = BUFFERS.acquire();
+///     buffer.extend_from_slice(b"hello");
+///     buffer.clear();
+///     BUFFERS.release(buffer);
+///
+///     // The same buffer is handed out again.
+///     assert!(BUFFERS.acquire().capacity() >= 4096);
+/// }
+/// ```
+#[macro_export]
+macro_rules! local_pool {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $config:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::LocalPool<$ty> = {
+			$crate::heap_local!{
+				static FREE: $crate::FreeList<$ty> = || $crate::FreeList::new(&$name);
+			}
+			$crate::LocalPool::new(&FREE, $config)
+		};
+	)+};
+}
+
+/// What happens to a thread's free objects when it exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnThreadExit {
+	/// Drop them.
+	Drop,
+	/// Move them to a reservoir shared by all threads. A thread whose free
+	/// list is empty takes objects from the reservoir before constructing new
+	/// ones.
+	Reservoir,
+}
+
+/// The configuration of a [`LocalPool`].
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig<T> {
+	new: fn() -> T,
+	max_free: usize,
+	on_thread_exit: OnThreadExit,
+}
+impl<T> PoolConfig<T> {
+	/// The objects are constructed with `new`.
+	///
+	/// By default each thread keeps up to 32 free objects and drops them when
+	/// it exits.
+	pub const fn new(new: fn() -> T) -> Self {
+		Self {
+			new,
+			max_free: 32,
+			on_thread_exit: OnThreadExit::Drop,
+		}
+	}
+
+	/// Sets the number of free objects each thread keeps.
+	pub const fn max_free(mut self, max_free: usize) -> Self {
+		self.max_free = max_free;
+		self
+	}
+
+	/// Sets what happens to a thread's free objects when it exits.
+	pub const fn on_thread_exit(mut self, on_thread_exit: OnThreadExit) -> Self {
+		self.on_thread_exit = on_thread_exit;
+		self
+	}
+}
+
+/// A thread's free objects.
+#[doc(hidden)]
+pub struct FreeList<T: Send + 'static> {
+	objects: Vec<T>,
+	pool: &'static LocalPool<T>,
+}
+impl<T: Send> FreeList<T> {
+	pub fn new(pool: &'static LocalPool<T>) -> Self {
+		Self {
+			objects: Vec::new(),
+			pool,
+		}
+	}
+}
+impl<T: Send> Drop for FreeList<T> {
+	fn drop(&mut self) {
+		if self.pool.config.on_thread_exit == OnThreadExit::Reservoir {
+			self.pool.reservoir().append(&mut self.objects);
+		}
+	}
+}
+
+/// A pool of objects that are reused by the thread that released them.
+///
+/// [`acquire`](Self::acquire) and [`release`](Self::release) only use the
+/// current thread's free list, so they don't take any locks unless the list is
+/// empty or full. A full list gets rid of released objects in the same way as
+/// a thread that exits. The reservoir's lock is only taken when it's enabled
+/// with [`OnThreadExit::Reservoir`].
+///
+/// Objects are handed out as they were released, so reset them before
+/// releasing them if that matters.
+pub struct LocalPool<T: Send + 'static> {
+	free: &'static HeapLocal<FreeList<T>>,
+	config: PoolConfig<T>,
+	reservoir: Mutex<Vec<T>>,
+}
+impl<T: Send> LocalPool<T> {
+	#[doc(hidden)]
+	pub const fn new(free: &'static HeapLocal<FreeList<T>>, config: PoolConfig<T>) -> Self {
+		Self {
+			free,
+			config,
+			reservoir: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Takes a free object, or constructs one if there are none.
+	pub fn acquire(&self) -> T {
+		let object = self
+			.free
+			.try_with_mut(|free| free.objects.pop())
+			.ok()
+			.flatten();
+		match object {
+			Some(object) => object,
+			None => self.acquire_slow(),
+		}
+	}
+
+	#[cold]
+	fn acquire_slow(&self) -> T {
+		if self.config.on_thread_exit == OnThreadExit::Reservoir {
+			if let Some(object) = self.reservoir().pop() {
+				return object;
+			}
+		}
+		(self.config.new)()
+	}
+
+	/// Gives an object back to the current thread's free list.
+	pub fn release(&self, object: T) {
+		let mut object = Some(object);
+		let _ = self.free.try_with_mut(|free| {
+			if free.objects.len() < self.config.max_free {
+				free.objects.extend(object.take());
+			}
+		});
+		if let Some(object) = object {
+			self.discard(object);
+		}
+	}
+
+	#[cold]
+	fn discard(&self, object: T) {
+		if self.config.on_thread_exit == OnThreadExit::Reservoir {
+			self.reservoir().push(object);
+		}
+	}
+
+	/// Returns the number of objects in the reservoir.
+	pub fn reservoir_len(&self) -> usize {
+		self.reservoir().len()
+	}
+
+	fn reservoir(&self) -> MutexGuard<'_, Vec<T>> {
+		self.reservoir
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
@@ -20,21 +20,147 @@ unsafe impl<T> Send for Wrapper<T> {}
 /// );
 /// ```
 ///
+/// # Sections
+///
+/// A `#[tls_section = "..."]` attribute places the static in `.tls$` followed
+/// by the given suffix. The linker orders the sections by their suffix, so
+/// this can be used to keep statics that are used together next to each other.
+/// The default is no suffix, which sorts first. The suffix must sort before
+/// `ZZZ`, which is used by the CRT to mark the end of the thread locals.
+///
+/// ```
+/// wintls::raw::init_static!(
+///     #[tls_section = "HOT"]
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// ```
+///
+/// ```compile_fail
+/// wintls::raw::init_static!(
+///     #[tls_section = "zzz"]
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// ```
+///
+/// # Sharing
+///
+/// A `#[shared("...")]` attribute gives the static a fixed symbol name with
+/// weak linkage so that a static with the same name in another crate uses the
+/// same slot. See [`static_thread_local`](crate::static_thread_local) for the
+/// hazards.
+///
+/// # Alignment
+///
+/// The loader allocates each thread's copy of the thread locals from the heap,
+/// so only [`MAX_ALIGN`] is guaranteed. Types with a greater alignment (e.g.
+/// `u128` on x86) are rejected at compile time, otherwise reads that assume
+/// the alignment (such as SSE instructions) could silently go wrong.
+///
+/// ```compile_fail
+/// #[repr(align(64))]
+/// struct CacheLine([u8; 64]);
+///
+/// wintls::raw::init_static!(
+///     static DATA: CacheLine = CacheLine([0; 64]);
+/// );
+/// ```
+///
+/// ```compile_fail
+/// #[repr(align(32))]
+/// struct Avx([u8; 32]);
+///
+/// wintls::raw::init_static!(
+///     static DATA: Avx = Avx([0; 32]);
+/// );
+/// ```
+///
 /// # Safety
 ///
 /// This is very unsafe. The resulting static should never be accessed at all,
 /// except to use the identifier with the other macros in this crate.
 #[macro_export]
+#[cfg_attr(not(wintls_not_nightly), allow_internal_unstable(linkage))]
 macro_rules! init_static {
-	($vis:vis static $name:ident: $ty:ty = $value:expr;) => {
+	// Each static's attributes are munched one at a time to pick out
+	// `tls_section` and `shared`.
+	(@attrs [$($attr:tt)*] [$($section:tt)*] #[tls_section = $new:literal] $($rest:tt)*) => {
+		$crate::init_static!(@attrs [$($attr)*] [$new] $($rest)*);
+	};
+	(@attrs [$($attr:tt)*] [$($section:tt)*] #[shared($symbol:literal)] $($rest:tt)*) => {
+		$crate::init_static!(
+			@attrs [$($attr)* #[export_name = $symbol] #[linkage = "weak_odr"]] [$($section)*]
+			$($rest)*
+		);
+	};
+	(@attrs [$($attr:tt)*] [$($section:tt)*] #[$meta:meta] $($rest:tt)*) => {
+		$crate::init_static!(@attrs [$($attr)* #[$meta]] [$($section)*] $($rest)*);
+	};
+	(
+		@attrs [$($attr:tt)*] [$($section:literal)?]
+		$vis:vis static $name:ident: $ty:ty = $value:expr;
+		$($rest:tt)*
+	) => {
 		// This doesn't need to be `Cell` or anything. The trick is that we
 		// don't ever touch this memory. Instead thread-local copies are used.
-		#[link_section = ".tls$"]
+		$($attr)*
+		#[link_section = concat!(".tls$", $($section)?)]
 		#[used]
-		$vis static $name: $crate::raw_internal::Wrapper<$ty> = $crate::raw_internal::Wrapper($value);
+		$vis static $name: $crate::raw_internal::Wrapper<$ty> = {
+			const _: () = assert!(
+				::core::mem::align_of::<$ty>() <= $crate::raw_internal::MAX_ALIGN,
+				"the type's alignment is greater than thread locals are guaranteed to have"
+			);
+			$(
+				const _: () = assert!(
+					$crate::raw_internal::is_valid_tls_section($section),
+					concat!(
+						"`tls_section = \"",
+						$section,
+						"\"` must sort before \"ZZZ\", where the thread locals end"
+					)
+				);
+			)?
+			$crate::raw_internal::Wrapper($value)
+		};
+		// Only binaries that declare thread locals need the TLS directory.
+		const _: () = {
+			#[link_section = ".drectve"]
+			#[used]
+			static DIRECTIVE: [u8; $crate::raw_internal::TLS_USED_DIRECTIVE.len()] =
+				$crate::raw_internal::TLS_USED_DIRECTIVE;
+		};
+		$crate::init_static!($($rest)*);
+	};
+	(@attrs [$($attr:tt)*] [$($section:tt)*] $($rest:tt)+) => {
+		::core::compile_error!(concat!(
+			"expected a static declaration, found `",
+			stringify!($($rest)+),
+			"`"
+		));
+	};
+	() => {};
+	($($rest:tt)+) => {
+		$crate::init_static!(@attrs [] [] $($rest)+);
 	};
 }
 
+/// Returns `true` if `.tls$` followed by `suffix` sorts before `.tls$ZZZ`.
+///
+/// The linker orders the `.tls$` sections by their suffix. The CRT marks the
+/// start of the thread locals with `.tls` and the end with `.tls$ZZZ`.
+#[doc(hidden)]
+pub const fn is_valid_tls_section(suffix: &str) -> bool {
+	let (suffix, end) = (suffix.as_bytes(), b"ZZZ");
+	let mut i = 0;
+	while i < suffix.len() && i < end.len() {
+		if suffix[i] != end[i] {
+			return suffix[i] < end[i];
+		}
+		i += 1;
+	}
+	suffix.len() < end.len()
+}
+
 /// Returns a mutable pointer to a tls value.
 ///
 /// Generally it should not be stored as this pointer may point to old data when
@@ -61,9 +187,38 @@ pub unsafe fn static_ptr<T>(key: u32) -> *mut T {
 	static_ptr_from_module(_tls_index, key)
 }
 
+/// Returns a mutable pointer to a tls value of a given module.
+///
+/// `module` is the module's [`_tls_index`]. Each module (the EXE and every
+/// DLL) has its own, and a key is only meaningful for the module that created
+/// it. This allows a thread local to be found from code that may have been
+/// inlined into another module, so long as `module` and the key were read in
+/// the declaring module. See [`ModuleLocal`](crate::ModuleLocal) for a safe
+/// wrapper.
+///
+/// # Safety
+/// * The key must be a valid key returned by [`static_key`] in the module
+///   with the index `module`.
+/// * The type should be the same as when it was created.
+///
+/// The same caveats apply as for [`static_ptr`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// wintls::raw::init_static!(
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// unsafe {
+///     let module = wintls::raw::_tls_index;
+///     let key: u32 = wintls::raw::static_key!(DATA);
+///     let value: *mut u32 = wintls::raw::static_ptr_from_module(module, key);
+/// }
+/// ```
 #[inline(always)]
 pub unsafe fn static_ptr_from_module<T>(module: u32, key: u32) -> *mut T {
-	let mut ptr: *mut T = tls_array().cast();
+	let mut ptr: *mut u8 = tls_array().cast();
 	let key = key as usize;
 	let index = module as usize;
 	asm!(
@@ -75,7 +230,37 @@ pub unsafe fn static_ptr_from_module<T>(module: u32, key: u32) -> *mut T {
 		multiplier = const INDEX_MULTIPLIER,
 		options(pure, readonly, preserves_flags, nostack),
 	);
-	ptr
+	debug_assert!(
+		ptr as usize & (core::mem::align_of::<T>() - 1) == 0,
+		"thread local is not aligned for its type"
+	);
+	ptr.cast()
+}
+
+/// Returns a pointer to the start of this module's thread-local block.
+///
+/// Adding a key returned by [`static_key`] to this pointer gives the same
+/// pointer as [`static_ptr`]. This is useful for accessing many thread locals
+/// while only looking up the block once.
+///
+/// The same caveats apply as for [`static_ptr`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// wintls::raw::init_static!(
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// unsafe {
+///     let block = wintls::raw::module_block();
+///     let key: u32 = wintls::raw::static_key!(DATA);
+///     let value: *mut u32 = block.add(key as usize).cast();
+/// }
+/// ```
+#[inline(always)]
+pub unsafe fn module_block() -> *mut u8 {
+	static_ptr(0)
 }
 
 /// Sets a static thread-local value.
@@ -123,39 +308,83 @@ pub unsafe fn get_static<T: Copy>(key: u32) -> T {
 	*static_ptr(key)
 }
 
+/// Returns the value of a static thread-local of a given module.
+///
+/// # Safety
+/// The same rules apply as for [`static_ptr_from_module`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// wintls::raw::init_static!(
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// unsafe {
+///     let module = wintls::raw::_tls_index;
+///     let key: u32 = wintls::raw::static_key!(DATA);
+///     let value: u32 = wintls::raw::get_static_from_module(module, key);
+/// }
+/// ```
 #[inline(always)]
 pub unsafe fn get_static_from_module<T: Copy>(module: u32, key: u32) -> T {
 	*static_ptr_from_module(module, key)
 }
 
+/// Sets a static thread-local value of a given module.
+///
+/// # Safety
+/// The same rules apply as for [`static_ptr_from_module`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// wintls::raw::init_static!(
+///     static DATA: u32 = 0xfeedface;
+/// );
+/// unsafe {
+///     let module = wintls::raw::_tls_index;
+///     let key: u32 = wintls::raw::static_key!(DATA);
+///     wintls::raw::set_static_from_module(module, key, 5_u32);
+/// }
+/// ```
+#[inline(always)]
+pub unsafe fn set_static_from_module<T>(module: u32, key: u32, value: T) {
+	*static_ptr_from_module(module, key) = value
+}
+
 /// Convenience macro for setting the static thread-local value by its
-/// identifier.
+/// path.
 #[macro_export]
 macro_rules! set_static {
-	($name:ident, $value:expr) => {
+	($name:path, $value:expr) => {
 		$crate::raw_internal::set_static($crate::static_key!($name), $value)
 	};
 }
 /// Convenience macro for getting the static thread-local value by its
-/// identifier.
+/// path.
 #[macro_export]
 macro_rules! get_static {
-	($name:ident) => {
+	($name:path) => {
 		$crate::raw_internal::get_static($crate::static_key!($name))
 	};
 }
 
 /// Convenience macro for getting a static thread-local pointer by its
-/// identifier.
+/// path.
 #[macro_export]
 macro_rules! static_ptr {
-	($name:ident) => {
+	($name:path) => {
 		$crate::raw_internal::static_ptr($crate::static_key!($name))
 	};
 }
 
 /// Returns a key that identifies the thread local.
 ///
+/// The static can be given by its path (e.g. `crate::tls::DATA`), including a
+/// static re-exported from another crate.
+///
 /// # Safety
 /// Must only be used with static thread locals.
 ///
@@ -173,9 +402,12 @@ macro_rules! static_ptr {
 ///     let key: u32 = wintls::raw::static_key!(DATA);
 /// }
 /// ```
+// This is the only macro that expands to `asm!` in other crates. Allowing the
+// feature here means users don't need to enable it themselves.
 #[macro_export]
+#[cfg_attr(not(wintls_not_nightly), allow_internal_unstable(asm))]
 macro_rules! static_key {
-	($name:ident) => {{
+	($name:path) => {{
 		let offset: u32;
 		#[cfg(any(target_arch="x86_64", target_arch="x86"))]
 		asm!(
@@ -194,6 +426,49 @@ macro_rules! static_key {
 	}}
 }
 
+/// Returns a key for a field of a thread local declared with
+/// [`static_thread_local`](crate::static_thread_local).
+///
+/// The key can be used with [`get_static`], [`set_static`] and [`static_ptr`]
+/// to access just that field, without copying the whole struct. Nested fields
+/// are also supported.
+///
+/// # Safety
+///
+/// The key must be used with the type of the field. The same rules apply as
+/// for any other key.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// #[derive(Clone, Copy)]
+/// struct Inner {
+///     flag: bool,
+/// }
+/// #[derive(Clone, Copy)]
+/// struct Ctx {
+///     counter: u32,
+///     inner: Inner,
+/// }
+/// wintls::static_thread_local!{
+///     static CTX: Ctx = Ctx { counter: 0, inner: Inner { flag: false } };
+/// }
+/// unsafe {
+///     let key: u32 = wintls::raw::static_field!(CTX.counter);
+///     wintls::raw::set_static(key, 5_u32);
+///     let key: u32 = wintls::raw::static_field!(CTX.inner.flag);
+///     let flag: bool = wintls::raw::get_static(key);
+/// }
+/// ```
+#[macro_export]
+macro_rules! static_field {
+	($local:ident $(. $field:tt)+) => {
+		$local.key()
+			+ ::core::mem::offset_of!(<$local as $crate::StaticKey>::Value, $($field).+) as u32
+	};
+}
+
 /// Returns a pointer to thread-local memory for the current thread.
 ///
 /// Calling this twice on the same thread usually returns the same value.
@@ -245,6 +520,56 @@ fn tls_array_() -> *mut *mut u8 {
 		tls_array
 	}
 }
+
+/// Returns the current thread's id.
+///
+/// This is read from the thread environment block so it's much cheaper than
+/// calling `GetCurrentThreadId`, but returns the same value.
+#[inline(always)]
+pub fn current_thread_id() -> u32 {
+	let id: u32;
+	unsafe {
+		// `ClientId.UniqueThread` in the TEB.
+		#[cfg(target_arch = "x86_64")]
+		asm!(
+			"mov {:e}, DWORD PTR gs:[0x48]",
+			out(reg) id,
+			options(pure, readonly, preserves_flags, nostack),
+		);
+		#[cfg(target_arch = "x86")]
+		asm!(
+			"mov {}, DWORD PTR fs:[0x24]",
+			out(reg) id,
+			options(pure, readonly, preserves_flags, nostack),
+		);
+	}
+	id
+}
+
+/// The maximum alignment of a thread local.
+///
+/// This is the alignment of memory returned by the process heap, which is
+/// where the loader allocates each module's thread locals.
+#[cfg(target_arch = "x86")]
+pub const MAX_ALIGN: usize = 8;
+/// The maximum alignment of a thread local.
+///
+/// This is the alignment of memory returned by the process heap, which is
+/// where the loader allocates each module's thread locals.
+#[cfg(target_arch = "x86_64")]
+pub const MAX_ALIGN: usize = 16;
+
+// `_tls_used` is where the TLS directory information is stored. It must be
+// included by the linker. Every `init_static!` emits this directive but the
+// linker only needs to see it once.
+#[cfg(not(target_arch = "x86"))]
+#[doc(hidden)]
+pub const TLS_USED_DIRECTIVE: [u8; 19] = *b"/INCLUDE:_tls_used ";
+// On x86 the name is mangled by prefixing another underscore.
+#[cfg(target_arch = "x86")]
+#[doc(hidden)]
+pub const TLS_USED_DIRECTIVE: [u8; 20] = *b"/INCLUDE:__tls_used ";
+
 #[cfg(target_arch = "x86")]
 const INDEX_MULTIPLIER: usize = 4;
 #[cfg(target_arch = "x86_64")]
@@ -262,6 +587,8 @@ pub use crate::init_static;
 #[doc(inline)]
 pub use crate::set_static;
 #[doc(inline)]
+pub use crate::static_field;
+#[doc(inline)]
 pub use crate::static_key;
 #[doc(inline)]
 pub use crate::static_ptr;
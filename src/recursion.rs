@@ -0,0 +1,113 @@
+//! Per-thread protection against reentrancy.
+
+use core::marker::PhantomData;
+
+/// Declares a [`RecursionGuard`].
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::recursion_guard!(static IN_LOGGER);
+///
+/// fn log(message: &str) {
+///     // Anything logged while logging is dropped.
+///     let Some(_guard) = IN_LOGGER.enter() else { return };
+///     println!("{}", message);
+/// }
+///
+/// fn main() {
+///     log("hello");
+/// }
+/// ```
+#[macro_export]
+macro_rules! recursion_guard {
+	($(#[$attr:meta])* $vis:vis static $name:ident) => {
+		$(#[$attr])*
+		$vis static $name: $crate::RecursionGuard = {
+			$crate::init_static!(
+				static $name: u32 = 0;
+			);
+			unsafe { $crate::RecursionGuard::new(|| $crate::static_ptr!($name)) }
+		};
+	};
+}
+
+/// A per-thread flag, or depth counter, for detecting reentrancy.
+pub struct RecursionGuard {
+	get: fn() -> *mut u32,
+}
+impl RecursionGuard {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out as zero and is only used by this handle.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> *mut u32) -> Self {
+		Self { get }
+	}
+
+	/// Enters the guarded section, or returns `None` if this thread is already
+	/// inside it.
+	///
+	/// The thread leaves the section when the [`Entered`] guard is dropped,
+	/// including when unwinding from a panic.
+	#[inline]
+	pub fn enter(&self) -> Option<Entered> {
+		self.enter_max(1)
+	}
+
+	/// Enters the guarded section, or returns `None` if this thread is already
+	/// inside it `max_depth` times.
+	///
+	/// This is for allowing a bounded amount of recursion.
+	#[inline]
+	pub fn enter_max(&self, max_depth: u32) -> Option<Entered> {
+		let depth = (self.get)();
+		unsafe {
+			if *depth >= max_depth {
+				return None;
+			}
+			*depth += 1;
+		}
+		Some(Entered {
+			get: self.get,
+			_not_send: PhantomData,
+		})
+	}
+
+	/// Returns how many times the current thread is inside the guarded
+	/// section.
+	#[inline]
+	pub fn depth(&self) -> u32 {
+		unsafe { *(self.get)() }
+	}
+
+	/// Returns `true` if the current thread is inside the guarded section.
+	#[inline]
+	pub fn is_entered(&self) -> bool {
+		self.depth() != 0
+	}
+}
+
+/// Leaves the section guarded by a [`RecursionGuard`] when dropped.
+///
+/// It must be dropped on the thread that entered, so it can't be sent to
+/// another thread.
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<wintls::Entered>();
+/// ```
+#[must_use = "the section is left as soon as the guard is dropped"]
+pub struct Entered {
+	get: fn() -> *mut u32,
+	_not_send: PhantomData<*const ()>,
+}
+impl Drop for Entered {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { *(self.get)() -= 1 }
+	}
+}
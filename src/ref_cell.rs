@@ -0,0 +1,265 @@
+//! A thread local with the borrow checking of `RefCell`.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// Declares thread locals with a [`RefCellLocal`] handle.
+///
+/// This is like a `std` thread local holding a
+/// [`RefCell`](core::cell::RefCell). The borrow flag is stored in TLS next to
+/// the value.
+///
+/// # Drop
+///
+/// Values are never dropped automatically. A value that owns memory can be
+/// dropped when the thread exits by registering a destructor that takes it.
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::ref_cell_local!{
+///     static NAMES: Vec<String> = Vec::new();
+/// }
+///
+/// fn main() {
+///     std::thread::spawn(|| {
+///         wintls::dtor::register_dtor(|| drop(NAMES.take()));
+///         NAMES.borrow_mut().push(String::from("spawned"));
+///     })
+///     .join()
+///     .unwrap();
+/// }
+/// ```
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::ref_cell_local!{
+///     static STACK: [u32; 8] = [0; 8];
+/// }
+///
+/// fn main() {
+///     STACK.borrow_mut()[0] = 1;
+///     let stack = STACK.borrow();
+///     assert_eq!(stack[0], 1);
+///     assert!(STACK.try_borrow_mut().is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! ref_cell_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::RefCellLocal<$ty> = {
+			$crate::init_static!(
+				static $name: $crate::RefCellSlot<$ty> = $crate::RefCellSlot::new($value);
+			);
+			unsafe { $crate::RefCellLocal::new(|| $crate::static_ptr!($name), stringify!($name)) }
+		};
+	)+};
+}
+
+/// The value and borrow flag of a [`RefCellLocal`].
+#[doc(hidden)]
+pub struct RefCellSlot<T> {
+	// The number of `Ref`s, or -1 if there's a `RefMut`.
+	borrow: isize,
+	value: T,
+}
+impl<T> RefCellSlot<T> {
+	pub const fn new(value: T) -> Self {
+		Self { borrow: 0, value }
+	}
+}
+
+/// A handle to a thread local declared with
+/// [`ref_cell_local`](crate::ref_cell_local).
+///
+/// The borrows can't be sent to other threads. They must not be held while a
+/// library is loaded, as that could reallocate the thread locals (see
+/// [`UnsafeLocal`](crate::UnsafeLocal)).
+pub struct RefCellLocal<T: 'static> {
+	get: fn() -> *mut RefCellSlot<T>,
+	name: &'static str,
+}
+// Each thread's slot holds its own borrow count along with the value, so a
+// borrow on one thread never affects another, and `Ref`/`RefMut` can't leave
+// the thread. `T` must be `Send` because each thread starts with a copy of the
+// initial value.
+unsafe impl<T: Send> Sync for RefCellLocal<T> {}
+unsafe impl<T: Send> Send for RefCellLocal<T> {}
+impl<T> RefCellLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that's only used by this handle.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> *mut RefCellSlot<T>, name: &'static str) -> Self {
+		Self { get, name }
+	}
+
+	/// Borrows the value.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is mutably borrowed.
+	#[inline]
+	#[track_caller]
+	pub fn borrow(&self) -> Ref<'_, T> {
+		match self.try_borrow() {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Mutably borrows the value.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed.
+	#[inline]
+	#[track_caller]
+	pub fn borrow_mut(&self) -> RefMut<'_, T> {
+		match self.try_borrow_mut() {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Borrows the value, or returns an error if it's mutably borrowed.
+	#[inline]
+	pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+		unsafe {
+			let slot = (self.get)();
+			if (*slot).borrow < 0 {
+				return Err(BorrowError::new(self.name, true));
+			}
+			(*slot).borrow += 1;
+			Ok(Ref {
+				value: &(*slot).value,
+				borrow: core::ptr::addr_of_mut!((*slot).borrow),
+			})
+		}
+	}
+
+	/// Mutably borrows the value, or returns an error if it's borrowed.
+	#[inline]
+	pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowError> {
+		unsafe {
+			let slot = (self.get)();
+			if (*slot).borrow != 0 {
+				return Err(BorrowError::new(self.name, false));
+			}
+			(*slot).borrow = -1;
+			Ok(RefMut {
+				value: &mut (*slot).value,
+				borrow: core::ptr::addr_of_mut!((*slot).borrow),
+			})
+		}
+	}
+
+	/// Sets the value, returning the old value.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed.
+	#[inline]
+	#[track_caller]
+	pub fn replace(&self, value: T) -> T {
+		core::mem::replace(&mut *self.borrow_mut(), value)
+	}
+
+	/// Takes the value, leaving `Default::default()` in its place.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is borrowed.
+	#[inline]
+	#[track_caller]
+	pub fn take(&self) -> T
+	where
+		T: Default,
+	{
+		self.replace(T::default())
+	}
+}
+
+/// A borrow of a [`RefCellLocal`].
+pub struct Ref<'a, T> {
+	value: &'a T,
+	borrow: *mut isize,
+}
+impl<T> Deref for Ref<'_, T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		self.value
+	}
+}
+impl<T> Drop for Ref<'_, T> {
+	fn drop(&mut self) {
+		unsafe { *self.borrow -= 1 }
+	}
+}
+impl<T: fmt::Debug> fmt::Debug for Ref<'_, T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.value.fmt(f)
+	}
+}
+
+/// A mutable borrow of a [`RefCellLocal`].
+pub struct RefMut<'a, T> {
+	value: &'a mut T,
+	borrow: *mut isize,
+}
+impl<T> Deref for RefMut<'_, T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		self.value
+	}
+}
+impl<T> DerefMut for RefMut<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		self.value
+	}
+}
+impl<T> Drop for RefMut<'_, T> {
+	fn drop(&mut self) {
+		unsafe { *self.borrow = 0 }
+	}
+}
+impl<T: fmt::Debug> fmt::Debug for RefMut<'_, T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.value.fmt(f)
+	}
+}
+
+/// The error returned when a [`RefCellLocal`] can't be borrowed.
+#[derive(Debug)]
+pub struct BorrowError {
+	name: &'static str,
+	mutably: bool,
+}
+impl BorrowError {
+	fn new(name: &'static str, mutably: bool) -> Self {
+		Self { name, mutably }
+	}
+
+	#[cold]
+	#[track_caller]
+	fn panic(self) -> ! {
+		panic!("{self}")
+	}
+}
+impl fmt::Display for BorrowError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let how = if self.mutably {
+			"mutably borrowed"
+		} else {
+			"borrowed"
+		};
+		write!(f, "thread local `{}` is already {how}", self.name)
+	}
+}
+#[cfg(feature = "std")]
+impl std::error::Error for BorrowError {}
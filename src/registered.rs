@@ -0,0 +1,187 @@
+//! Thread locals whose values can be visited from any thread.
+
+use crate::sentinel::{ResetOnUnwind, DESTROYED, INITIALIZING};
+use crate::AccessError;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Declares thread locals with a [`RegisteredLocal`] handle.
+///
+/// Each thread's value is boxed the first time it's used on that thread and
+/// added to a registry shared by all threads. When the thread exits, the value
+/// is removed from the registry and dropped.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// wintls::registered_local!{
+///     static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+/// }
+///
+/// fn total() -> u64 {
+///     let mut total = 0;
+///     ALLOCATIONS.for_each(|count| total += count.load(Ordering::Relaxed));
+///     total
+/// }
+///
+/// fn main() {
+///     ALLOCATIONS.with(|count| count.fetch_add(1, Ordering::Relaxed));
+///     assert_eq!(total(), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! registered_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::RegisteredLocal<$ty> = {
+			// The storage is declared inside `get` so that the initializer can
+			// refer to the handle.
+			fn get() -> *mut *const $ty {
+				$crate::init_static!(
+					static $name: *const $ty = ::core::ptr::null();
+				);
+				unsafe { $crate::static_ptr!($name) }
+			}
+			fn init() -> $ty {
+				$value
+			}
+			fn destroy() {
+				unsafe { $name.destroy() }
+			}
+			unsafe { $crate::RegisteredLocal::new(get, init, destroy, stringify!($name)) }
+		};
+	)+};
+}
+
+struct Entry<T>(*const T);
+// The registry only hands out shared references.
+unsafe impl<T: Sync> Send for Entry<T> {}
+
+/// A handle to a thread local declared with
+/// [`registered_local`](crate::registered_local).
+///
+/// # Synchronization
+///
+/// [`for_each`](Self::for_each) gives out references to the values of other
+/// threads while those threads may be using them, so `T` must be [`Sync`] and
+/// only shared references are given out. Use atomics or locks to modify the
+/// value. Visiting the values holds the registry's lock, which blocks threads
+/// that are using the local for the first time or exiting.
+pub struct RegisteredLocal<T: Sync + 'static> {
+	get: fn() -> *mut *const T,
+	init: fn() -> T,
+	destroy: fn(),
+	name: &'static str,
+	registry: Mutex<Vec<Entry<T>>>,
+}
+impl<T: Sync> RegisteredLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out null and is only used by this handle.
+	/// `destroy` must call [`destroy`](Self::destroy) on this handle.
+	#[doc(hidden)]
+	pub const unsafe fn new(
+		get: fn() -> *mut *const T,
+		init: fn() -> T,
+		destroy: fn(),
+		name: &'static str,
+	) -> Self {
+		Self {
+			get,
+			init,
+			destroy,
+			name,
+			registry: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Calls `f` with a reference to the current thread's value, creating it
+	/// first if necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if the value is being dropped or has been dropped, or if this is
+	/// called by the initializer.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with a reference to the current thread's value, creating it
+	/// first if necessary. Returns an error if the value is being dropped or
+	/// has been dropped.
+	///
+	/// # Panics
+	///
+	/// Panics if this is called by the initializer.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		let ptr = unsafe { *(self.get)() };
+		let ptr = match ptr as usize {
+			0 => self.initialize(),
+			INITIALIZING => panic!(
+				"thread local `{}` was accessed while it was being initialized",
+				self.name
+			),
+			DESTROYED => return Err(AccessError::new(self.name)),
+			_ => ptr,
+		};
+		Ok(f(unsafe { &*ptr }))
+	}
+
+	/// Calls `f` with the value of every thread that's currently using the
+	/// thread local.
+	///
+	/// `f` must not use this thread local, as the registry is locked while it
+	/// runs.
+	pub fn for_each(&self, mut f: impl FnMut(&T)) {
+		for entry in self.registry().iter() {
+			f(unsafe { &*entry.0 });
+		}
+	}
+
+	fn registry(&self) -> MutexGuard<'_, Vec<Entry<T>>> {
+		// A panic in `for_each` leaves the list as it was.
+		self.registry.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	#[cold]
+	fn initialize(&self) -> *const T {
+		unsafe { *(self.get)() = INITIALIZING as *const T };
+		let reset = ResetOnUnwind(|| unsafe { *(self.get)() = core::ptr::null() });
+		let value = Box::into_raw(Box::new((self.init)()));
+		core::mem::forget(reset);
+
+		self.registry().push(Entry(value));
+		// The initializer may have loaded a library, which can move the thread
+		// locals, so the pointer needs to be looked up again.
+		unsafe { *(self.get)() = value };
+		crate::dtor::register_dtor(self.destroy);
+		value
+	}
+
+	/// Removes the current thread's value from the registry and drops it.
+	///
+	/// # Safety
+	///
+	/// This must only be called by the registered destructor.
+	#[doc(hidden)]
+	pub unsafe fn destroy(&self) {
+		let ptr = *(self.get)();
+		*(self.get)() = DESTROYED as *const T;
+		{
+			let mut registry = self.registry();
+			if let Some(i) = registry.iter().position(|entry| entry.0 == ptr) {
+				registry.swap_remove(i);
+			}
+		}
+		drop(Box::from_raw(ptr as *mut T));
+	}
+}
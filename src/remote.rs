@@ -0,0 +1,227 @@
+//! Thread locals that other threads can send new values to.
+
+use crate::raw_internal::current_thread_id;
+use crate::{AccessError, RegisteredLocal};
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Declares thread locals with a [`RemoteLocal`] handle.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+/// use std::sync::mpsc;
+///
+/// wintls::remote_local!{
+///     static LEVEL: u32 = 0;
+/// }
+///
+/// fn main() {
+///     let (id_sender, ids) = mpsc::channel();
+///     let (go, wait) = mpsc::channel();
+///     let worker = std::thread::spawn(move || {
+///         // Register this thread's mailbox.
+///         assert_eq!(LEVEL.get(), 0);
+///         id_sender.send(LEVEL.thread_id()).unwrap();
+///         wait.recv().unwrap();
+///         LEVEL.get()
+///     });
+///     LEVEL.send_to(ids.recv().unwrap(), 3).unwrap();
+///     go.send(()).unwrap();
+///     assert_eq!(worker.join().unwrap(), 3);
+/// }
+/// ```
+#[macro_export]
+macro_rules! remote_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $value:expr;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::RemoteLocal<$ty> = {
+			// Shadows the handle so that errors use its name.
+			$crate::registered_local!{
+				static $name: $crate::RemoteSlot<$ty> = $crate::RemoteSlot::new($value);
+			}
+			$crate::RemoteLocal::new(&$name)
+		};
+	)+};
+}
+
+/// A thread's value and mailbox.
+#[doc(hidden)]
+pub struct RemoteSlot<T> {
+	thread_id: u32,
+	// Set when there's something in the mailbox, so the owning thread doesn't
+	// need to lock it on every access.
+	pending: AtomicBool,
+	mailbox: Mutex<Option<T>>,
+	// Only used by the owning thread.
+	value: UnsafeCell<T>,
+	borrows: Cell<usize>,
+}
+// Other threads only use the mailbox.
+unsafe impl<T: Send> Sync for RemoteSlot<T> {}
+impl<T> RemoteSlot<T> {
+	pub fn new(value: T) -> Self {
+		Self {
+			thread_id: current_thread_id(),
+			pending: AtomicBool::new(false),
+			mailbox: Mutex::new(None),
+			value: UnsafeCell::new(value),
+			borrows: Cell::new(0),
+		}
+	}
+}
+
+/// A thread local that other threads can send new values to.
+///
+/// Each thread has a mailbox, which is added to a table shared by all threads
+/// the first time the thread uses the local. [`send_to`](Self::send_to) puts a
+/// value in a thread's mailbox, and that thread's next access replaces its
+/// value with it. If several values are sent before the thread looks, only the
+/// newest is kept.
+///
+/// The mailbox is removed when the thread exits. A value sent just before
+/// then may be dropped without being seen.
+pub struct RemoteLocal<T: Send + 'static> {
+	slots: &'static RegisteredLocal<RemoteSlot<T>>,
+}
+impl<T: Send> RemoteLocal<T> {
+	#[doc(hidden)]
+	pub const fn new(slots: &'static RegisteredLocal<RemoteSlot<T>>) -> Self {
+		Self { slots }
+	}
+
+	/// Calls `f` with the current thread's value, after taking any new value
+	/// from the mailbox.
+	///
+	/// A value that arrives while `f` runs isn't taken until the next access
+	/// after `f` returns.
+	///
+	/// # Panics
+	///
+	/// Panics if the thread local has been dropped.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with the current thread's value, after taking any new value
+	/// from the mailbox. Returns an error if the thread local has been dropped.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+		self.slots.try_with(|slot| {
+			if slot.pending.load(Ordering::Acquire) && slot.borrows.get() == 0 {
+				Self::receive(slot);
+			}
+			slot.borrows.set(slot.borrows.get() + 1);
+			let _release = Release(&slot.borrows);
+			f(unsafe { &*slot.value.get() })
+		})
+	}
+
+	/// Returns a copy of the current thread's value, after taking any new
+	/// value from the mailbox.
+	///
+	/// # Panics
+	///
+	/// Panics if the thread local has been dropped.
+	#[inline]
+	#[track_caller]
+	pub fn get(&self) -> T
+	where
+		T: Clone,
+	{
+		self.with(T::clone)
+	}
+
+	#[cold]
+	fn receive(slot: &RemoteSlot<T>) {
+		let value = {
+			let mut mailbox = slot.mailbox.lock().unwrap_or_else(PoisonError::into_inner);
+			slot.pending.store(false, Ordering::Relaxed);
+			mailbox.take()
+		};
+		if let Some(value) = value {
+			// Nothing is borrowing the value. The old one is dropped after the
+			// new one is in place, in case its destructor uses the local.
+			drop(unsafe { core::ptr::replace(slot.value.get(), value) });
+		}
+	}
+
+	/// Returns the id of the current thread, which other threads can use to
+	/// send it values.
+	///
+	/// This is only a convenience. The id is the same as the one returned by
+	/// `GetCurrentThreadId`.
+	#[inline]
+	pub fn thread_id(&self) -> u32 {
+		current_thread_id()
+	}
+
+	/// Puts `value` in the mailbox of the thread with the id `thread_id`.
+	///
+	/// Returns the value in an error if that thread isn't using this local,
+	/// either because it hasn't used it yet or because it has exited.
+	pub fn send_to(&self, thread_id: u32, value: T) -> Result<(), SendToError<T>> {
+		let mut value = Some(value);
+		self.slots.for_each(|slot| {
+			if slot.thread_id == thread_id {
+				if let Some(value) = value.take() {
+					let mut mailbox = slot.mailbox.lock().unwrap_or_else(PoisonError::into_inner);
+					*mailbox = Some(value);
+					slot.pending.store(true, Ordering::Release);
+				}
+			}
+		});
+		match value {
+			None => Ok(()),
+			Some(value) => Err(SendToError { value, thread_id }),
+		}
+	}
+}
+
+/// Ends a borrow of a thread's value.
+struct Release<'a>(&'a Cell<usize>);
+impl Drop for Release<'_> {
+	fn drop(&mut self) {
+		self.0.set(self.0.get() - 1);
+	}
+}
+
+/// The error returned by [`RemoteLocal::send_to`] when the thread has no
+/// mailbox.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SendToError<T> {
+	value: T,
+	thread_id: u32,
+}
+impl<T> SendToError<T> {
+	/// Returns the value that couldn't be sent.
+	pub fn into_inner(self) -> T {
+		self.value
+	}
+
+	/// Returns the id of the thread that the value was sent to.
+	pub fn thread_id(&self) -> u32 {
+		self.thread_id
+	}
+}
+impl<T> fmt::Debug for SendToError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SendToError")
+			.field("thread_id", &self.thread_id)
+			.finish_non_exhaustive()
+	}
+}
+impl<T> fmt::Display for SendToError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "thread {} isn't using the thread local", self.thread_id)
+	}
+}
+impl<T> std::error::Error for SendToError<T> {}
@@ -0,0 +1,96 @@
+//! Temporarily overriding the value of a thread local.
+
+use crate::{StaticKey, StaticThreadLocal};
+use core::marker::PhantomData;
+
+impl<T: Copy, K: StaticKey<Value = T>> StaticThreadLocal<T, K> {
+	/// Sets the thread local to `value` while `f` runs.
+	///
+	/// The previous value is restored afterwards, even if `f` panics. Nested
+	/// calls behave like a stack. Other threads are unaffected.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::static_thread_local!{
+	///     static LOG_LEVEL: u8 = 2;
+	/// }
+	/// # fn main() {
+	/// LOG_LEVEL.set_scoped(4, || {
+	///     assert_eq!(LOG_LEVEL.get(), 4);
+	/// });
+	/// assert_eq!(LOG_LEVEL.get(), 2);
+	/// # }
+	/// ```
+	#[inline]
+	pub fn set_scoped<R>(&self, value: T, f: impl FnOnce() -> R) -> R {
+		let _guard = self.push(value);
+		f()
+	}
+
+	/// Sets the thread local to `value` until the returned guard is dropped.
+	///
+	/// The guard then restores the previous value. Guards should be dropped in
+	/// the reverse order they were created in, otherwise an outer guard will
+	/// be overwritten by an inner one.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// wintls::static_thread_local!{
+	///     static LOG_LEVEL: u8 = 2;
+	/// }
+	/// # fn main() {
+	/// let guard = LOG_LEVEL.push(4);
+	/// assert_eq!(guard.previous(), 2);
+	/// assert_eq!(LOG_LEVEL.get(), 4);
+	/// drop(guard);
+	/// assert_eq!(LOG_LEVEL.get(), 2);
+	/// # }
+	/// ```
+	#[inline]
+	#[must_use = "the value is restored when the guard is dropped"]
+	pub fn push(&self, value: T) -> ScopeGuard<'_, T, K> {
+		ScopeGuard {
+			local: self,
+			previous: self.replace(value),
+			_not_send: PhantomData,
+		}
+	}
+}
+
+/// Restores the previous value of a thread local when dropped.
+///
+/// This is returned by [`StaticThreadLocal::push`]. It can't be sent to
+/// another thread, as it would then restore that thread's value.
+///
+/// ```compile_fail
+/// # #![feature(asm)]
+/// wintls::static_thread_local!{
+///     static LOG_LEVEL: u8 = 2;
+/// }
+/// # fn main() {
+/// let guard = LOG_LEVEL.push(4);
+/// std::thread::spawn(move || drop(guard));
+/// # }
+/// ```
+pub struct ScopeGuard<'a, T: Copy, K: StaticKey<Value = T>> {
+	local: &'a StaticThreadLocal<T, K>,
+	previous: T,
+	_not_send: PhantomData<*const ()>,
+}
+impl<T: Copy, K: StaticKey<Value = T>> ScopeGuard<'_, T, K> {
+	/// Returns the value that will be restored.
+	#[inline]
+	pub fn previous(&self) -> T {
+		self.previous
+	}
+}
+impl<T: Copy, K: StaticKey<Value = T>> Drop for ScopeGuard<'_, T, K> {
+	#[inline]
+	fn drop(&mut self) {
+		self.local.set(self.previous);
+	}
+}
@@ -0,0 +1,144 @@
+//! Thread locals that borrow a value for the duration of a closure.
+
+use core::fmt;
+
+/// Declares thread locals with a [`ScopedThreadLocal`] handle.
+///
+/// This follows the pattern of the `scoped-tls` crate. A reference is set for
+/// the duration of a closure and can be used by anything that closure calls.
+/// Only a pointer is stored in TLS.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// struct Context {
+///     depth: u32,
+/// }
+///
+/// wintls::scoped_thread_local!(static CTX: Context);
+///
+/// fn deep() -> u32 {
+///     CTX.with(|ctx| ctx.depth)
+/// }
+///
+/// fn main() {
+///     let ctx = Context { depth: 3 };
+///     assert_eq!(CTX.set(&ctx, deep), 3);
+///     assert!(!CTX.is_set());
+/// }
+/// ```
+#[macro_export]
+macro_rules! scoped_thread_local {
+	($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty) => {
+		$(#[$attr])*
+		$vis static $name: $crate::ScopedThreadLocal<$ty> = {
+			$crate::init_static!(
+				static $name: *const $ty = ::core::ptr::null();
+			);
+			unsafe { $crate::ScopedThreadLocal::new(|| $crate::static_ptr!($name), stringify!($name)) }
+		};
+	};
+}
+
+/// A handle to a thread local declared with
+/// [`scoped_thread_local`](crate::scoped_thread_local).
+pub struct ScopedThreadLocal<T: 'static> {
+	get: fn() -> *mut *const T,
+	name: &'static str,
+}
+// Each thread has its own pointer, and a reference is only handed out on the
+// thread that set it.
+unsafe impl<T> Sync for ScopedThreadLocal<T> {}
+unsafe impl<T> Send for ScopedThreadLocal<T> {}
+impl<T> ScopedThreadLocal<T> {
+	/// # Safety
+	///
+	/// `get` must return a pointer to the current thread's copy of a static
+	/// thread local that starts out null and is only used by this handle.
+	#[doc(hidden)]
+	pub const unsafe fn new(get: fn() -> *mut *const T, name: &'static str) -> Self {
+		Self { get, name }
+	}
+
+	/// Sets the thread local to `value` while `f` runs.
+	///
+	/// The previous value is restored afterwards, even if `f` panics, so calls
+	/// can be nested.
+	#[inline]
+	pub fn set<R>(&self, value: &T, f: impl FnOnce() -> R) -> R {
+		let _reset = Reset {
+			get: self.get,
+			previous: unsafe { (self.get)().replace(value) },
+		};
+		f()
+	}
+
+	/// Calls `f` with the value set by the innermost [`set`](Self::set).
+	///
+	/// # Panics
+	///
+	/// Panics if this isn't called within `set`.
+	#[inline]
+	#[track_caller]
+	pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		match self.try_with(f) {
+			Ok(value) => value,
+			Err(error) => error.panic(),
+		}
+	}
+
+	/// Calls `f` with the value set by the innermost [`set`](Self::set), or
+	/// returns an error if this isn't called within `set`.
+	#[inline]
+	pub fn try_with<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, NotSetError> {
+		let ptr = unsafe { *(self.get)() };
+		if ptr.is_null() {
+			return Err(NotSetError { name: self.name });
+		}
+		// The reference passed to `set` outlives this call.
+		Ok(f(unsafe { &*ptr }))
+	}
+
+	/// Returns `true` if this is called within [`set`](Self::set).
+	#[inline]
+	pub fn is_set(&self) -> bool {
+		unsafe { !(*(self.get)()).is_null() }
+	}
+}
+
+/// Restores the previous pointer when `set` returns or unwinds.
+struct Reset<T: 'static> {
+	get: fn() -> *mut *const T,
+	previous: *const T,
+}
+impl<T> Drop for Reset<T> {
+	fn drop(&mut self) {
+		unsafe { *(self.get)() = self.previous }
+	}
+}
+
+/// The error returned by [`ScopedThreadLocal::try_with`] outside of `set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotSetError {
+	name: &'static str,
+}
+impl NotSetError {
+	#[cold]
+	#[track_caller]
+	fn panic(self) -> ! {
+		panic!("{self}")
+	}
+}
+impl fmt::Display for NotSetError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"scoped thread local `{}` was used outside of `set`",
+			self.name
+		)
+	}
+}
+#[cfg(feature = "std")]
+impl std::error::Error for NotSetError {}
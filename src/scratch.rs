@@ -0,0 +1,60 @@
+//! A reusable per-thread byte buffer.
+//!
+//! # Example
+//!
+//! ```
+//! #![feature(asm)]
+//! use std::io::Write;
+//!
+//! fn main() {
+//!     let len = wintls::scratch::with_scratch(256, |buffer| {
+//!         write!(buffer, "{}-{}", "thread", 1).unwrap();
+//!         buffer.len()
+//!     });
+//!     assert_eq!(len, 8);
+//! }
+//! ```
+
+crate::heap_local! {
+	static SCRATCH: Vec<u8> = Vec::new;
+}
+crate::static_thread_local! {
+	static IN_USE: bool = false;
+}
+
+/// Calls `f` with the current thread's scratch buffer.
+///
+/// The buffer is empty and has a capacity of at least `min_capacity`. It's
+/// cleared once `f` returns, but keeps its capacity, so later calls on the same
+/// thread don't allocate unless they need a bigger buffer. The buffer is
+/// allocated the first time it's used on a thread and freed when the thread
+/// exits.
+///
+/// A nested call on the same thread gets a new buffer, which is freed when it
+/// returns. So does a call made from a thread local destructor after the
+/// buffer has been freed.
+pub fn with_scratch<R>(min_capacity: usize, f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+	if IN_USE.get() {
+		return f(&mut Vec::with_capacity(min_capacity));
+	}
+	let mut f = Some(f);
+	let result = SCRATCH.try_with_mut(|buffer| {
+		IN_USE.set(true);
+		let guard = Clear(buffer);
+		guard.0.reserve(min_capacity);
+		(f.take().unwrap())(guard.0)
+	});
+	match result {
+		Ok(result) => result,
+		Err(_) => (f.take().unwrap())(&mut Vec::with_capacity(min_capacity)),
+	}
+}
+
+/// Clears the buffer afterwards, even if `f` panics.
+struct Clear<'a>(&'a mut Vec<u8>);
+impl Drop for Clear<'_> {
+	fn drop(&mut self) {
+		self.0.clear();
+		IN_USE.set(false);
+	}
+}
@@ -0,0 +1,27 @@
+//! Pieces shared by the thread locals that initialize their values on first
+//! use.
+
+/// A pointer to a value that's still being initialized.
+#[cfg(feature = "std")]
+pub(crate) const INITIALIZING: usize = usize::MAX;
+/// A pointer to a value that's being dropped or has been dropped.
+#[cfg(feature = "std")]
+pub(crate) const DESTROYED: usize = usize::MAX - 1;
+
+/// Returns `true` if `ptr` points to a value, rather than being null (meaning
+/// uninitialized) or one of the sentinels above.
+#[cfg(feature = "std")]
+pub(crate) fn is_value<T>(ptr: *const T) -> bool {
+	!matches!(ptr as usize, 0 | INITIALIZING | DESTROYED)
+}
+
+/// Runs the closure when dropped, unless it's forgotten.
+///
+/// This is used to reset a thread local if its initializer panics, so that it
+/// can be tried again.
+pub(crate) struct ResetOnUnwind<F: FnMut()>(pub(crate) F);
+impl<F: FnMut()> Drop for ResetOnUnwind<F> {
+	fn drop(&mut self) {
+		(self.0)()
+	}
+}
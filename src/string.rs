@@ -0,0 +1,106 @@
+//! A per-thread `String`.
+
+use crate::{AccessError, Local};
+use core::cell::RefCell;
+use core::fmt;
+
+/// Declares [`StringLocal`]s.
+///
+/// # Example
+///
+/// ```
+/// #![feature(asm)]
+///
+/// wintls::string_local!{
+///     static LOG;
+/// }
+///
+/// fn main() {
+///     LOG.push_str("Hello!").unwrap();
+///     std::thread::spawn(|| {
+///         LOG.push_str(" World!").unwrap();
+///         assert_eq!(LOG.to_string(), " World!");
+///     })
+///     .join()
+///     .unwrap();
+///     assert_eq!(LOG.to_string(), "Hello!");
+/// }
+/// ```
+#[macro_export]
+macro_rules! string_local {
+	($($(#[$attr:meta])* $vis:vis static $name:ident;)+) => {$(
+		$(#[$attr])*
+		$vis static $name: $crate::StringLocal = {
+			// Shadows the handle so that errors use its name.
+			$crate::local!{
+				static $name: ::core::cell::RefCell<::std::string::String> =
+					::core::cell::RefCell::new(::std::string::String::new());
+			}
+			$crate::StringLocal::new(&$name)
+		};
+	)+};
+}
+
+/// A `String` with a different value on each thread.
+///
+/// The string is created empty the first time it's used on a thread and
+/// dropped when that thread exits. After that, every method returns an
+/// [`AccessError`] and [`Display`](fmt::Display) returns [`fmt::Error`].
+///
+/// The string is only borrowed for the duration of a method call, or of the
+/// closure passed to [`with_str`](Self::with_str).
+///
+/// # Panics
+///
+/// Modifying the string from inside the closure passed to
+/// [`with_str`](Self::with_str), or while it's being formatted, panics.
+pub struct StringLocal {
+	string: &'static Local<RefCell<String>>,
+}
+impl StringLocal {
+	#[doc(hidden)]
+	pub const fn new(string: &'static Local<RefCell<String>>) -> Self {
+		Self { string }
+	}
+
+	/// Calls `f` with the current thread's string.
+	#[inline]
+	pub fn with_str<R>(&self, f: impl FnOnce(&str) -> R) -> Result<R, AccessError> {
+		self.string.try_with(|string| f(&string.borrow()))
+	}
+
+	/// Appends `s` to the current thread's string.
+	#[inline]
+	pub fn push_str(&self, s: &str) -> Result<(), AccessError> {
+		self.string
+			.try_with(|string| string.borrow_mut().push_str(s))
+	}
+
+	/// Empties the current thread's string.
+	#[inline]
+	pub fn clear(&self) -> Result<(), AccessError> {
+		self.string.try_with(|string| string.borrow_mut().clear())
+	}
+
+	/// Takes the current thread's string, leaving it empty.
+	#[inline]
+	pub fn take(&self) -> Result<String, AccessError> {
+		self.string.try_with(|string| string.take())
+	}
+}
+impl fmt::Display for StringLocal {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.with_str(|s| s.fmt(f)) {
+			Ok(result) => result,
+			Err(_) => Err(fmt::Error),
+		}
+	}
+}
+impl fmt::Debug for StringLocal {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.with_str(|s| f.debug_tuple("StringLocal").field(&s).finish()) {
+			Ok(result) => result,
+			Err(_) => f.write_str("StringLocal(<destroyed>)"),
+		}
+	}
+}
@@ -0,0 +1,149 @@
+//! A value that can only be used on the thread that created it.
+
+use crate::raw_internal::current_thread_id;
+use crate::thread_token;
+use core::fmt;
+use core::mem::ManuallyDrop;
+
+/// What to do when a [`ThreadBound`] is dropped on a different thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrongThreadDrop {
+	/// Panic. If the thread is already panicking then the value is leaked
+	/// instead.
+	Panic,
+	/// Leak the value without dropping it.
+	Leak,
+}
+
+/// A value that can only be used on the thread that created it.
+///
+/// This is for values such as COM pointers and window handles that mustn't
+/// leave their thread. A `ThreadBound` can be shared with and sent to other
+/// threads, but only the creating thread can get at the value. Thread ids are
+/// reused once a thread has exited, so the check doesn't use them. Each thread
+/// is instead given a number that's never reused.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// use wintls::ThreadBound;
+/// use std::rc::Rc;
+///
+/// # fn main() {
+/// let value = std::sync::Arc::new(ThreadBound::new(Rc::new(5)));
+/// assert_eq!(value.get().map(|rc| **rc), Some(5));
+///
+/// let shared = value.clone();
+/// std::thread::spawn(move || assert!(shared.get().is_none()))
+///     .join()
+///     .unwrap();
+/// # }
+/// ```
+pub struct ThreadBound<T> {
+	value: ManuallyDrop<T>,
+	token: u64,
+	thread: u32,
+	on_wrong_thread: WrongThreadDrop,
+}
+// The value is only ever used on the creating thread, and it's never dropped
+// on another thread.
+unsafe impl<T> Send for ThreadBound<T> {}
+unsafe impl<T> Sync for ThreadBound<T> {}
+impl<T> ThreadBound<T> {
+	/// Binds `value` to the current thread.
+	///
+	/// Dropping it on a different thread panics.
+	#[inline]
+	pub fn new(value: T) -> Self {
+		Self::with_drop(value, WrongThreadDrop::Panic)
+	}
+
+	/// Binds `value` to the current thread, with the behaviour for dropping it
+	/// on a different thread.
+	#[inline]
+	pub fn with_drop(value: T, on_wrong_thread: WrongThreadDrop) -> Self {
+		Self {
+			value: ManuallyDrop::new(value),
+			token: thread_token::current(),
+			thread: current_thread_id(),
+			on_wrong_thread,
+		}
+	}
+
+	/// Returns `true` if this is the thread that created the value.
+	#[inline]
+	pub fn is_owner(&self) -> bool {
+		thread_token::current() == self.token
+	}
+
+	/// Returns the id of the thread that created the value.
+	#[inline]
+	pub fn thread_id(&self) -> u32 {
+		self.thread
+	}
+
+	/// Returns the value, or `None` if this isn't the thread that created it.
+	#[inline]
+	pub fn get(&self) -> Option<&T> {
+		if self.is_owner() {
+			Some(&self.value)
+		} else {
+			None
+		}
+	}
+
+	/// Returns the value mutably, or `None` if this isn't the thread that
+	/// created it.
+	#[inline]
+	pub fn get_mut(&mut self) -> Option<&mut T> {
+		if self.is_owner() {
+			Some(&mut self.value)
+		} else {
+			None
+		}
+	}
+
+	/// Returns the value without checking the thread.
+	///
+	/// # Safety
+	///
+	/// This must be the thread that created the value.
+	#[inline]
+	pub unsafe fn get_unchecked(&self) -> &T {
+		debug_assert!(self.is_owner());
+		&self.value
+	}
+
+	/// Returns the value, or gives back `self` if this isn't the thread that
+	/// created it.
+	pub fn into_inner(self) -> Result<T, Self> {
+		if self.is_owner() {
+			let mut this = ManuallyDrop::new(self);
+			Ok(unsafe { ManuallyDrop::take(&mut this.value) })
+		} else {
+			Err(self)
+		}
+	}
+}
+impl<T> Drop for ThreadBound<T> {
+	fn drop(&mut self) {
+		if self.is_owner() {
+			unsafe { ManuallyDrop::drop(&mut self.value) };
+		} else if self.on_wrong_thread == WrongThreadDrop::Panic && !crate::panicking() {
+			panic!(
+				"a `ThreadBound` created on thread {} was dropped on thread {}",
+				self.thread,
+				current_thread_id()
+			);
+		}
+	}
+}
+impl<T: fmt::Debug> fmt::Debug for ThreadBound<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.get() {
+			Some(value) => f.debug_tuple("ThreadBound").field(value).finish(),
+			None => f.write_str("ThreadBound(<other thread>)"),
+		}
+	}
+}
@@ -0,0 +1,100 @@
+//! Small per-thread indexes for sharding.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+#[cfg(feature = "test-hooks")]
+use std::sync::MutexGuard;
+use std::sync::{Mutex, PoisonError};
+
+crate::static_thread_local! {
+	static INDEX: Option<u32> = None;
+}
+
+struct Indexes {
+	// The indexes that have been given back, smallest first.
+	free: BinaryHeap<Reverse<u32>>,
+	next: u32,
+}
+static INDEXES: Mutex<Indexes> = Mutex::new(Indexes {
+	free: BinaryHeap::new(),
+	next: 0,
+});
+// The same as `INDEXES.next`, but can be read without the lock.
+static MAX_THREADS: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a small index that's unique to the current thread.
+///
+/// Unlike a thread id, the indexes are dense. Each thread gets the smallest
+/// index that isn't being used by another thread, and gives it back when it
+/// exits. So the index can be used to pick a shard of a data structure that
+/// has [`max_threads_seen`] shards.
+///
+/// Only the first call for a thread takes a lock.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(asm)]
+/// # fn main() {
+/// let index = wintls::thread_index();
+/// assert_eq!(wintls::thread_index(), index);
+/// assert!(index < wintls::max_threads_seen());
+/// # }
+/// ```
+#[inline]
+pub fn thread_index() -> u32 {
+	match INDEX.get() {
+		Some(index) => index,
+		None => allocate(),
+	}
+}
+
+/// Returns the number of indexes that have been handed out by
+/// [`thread_index`].
+///
+/// This is the most threads that have used an index at the same time. Every
+/// index is less than this.
+#[inline]
+pub fn max_threads_seen() -> u32 {
+	MAX_THREADS.load(Ordering::Acquire)
+}
+
+#[cold]
+fn allocate() -> u32 {
+	let index = {
+		let mut indexes = INDEXES.lock().unwrap_or_else(PoisonError::into_inner);
+		match indexes.free.pop() {
+			Some(Reverse(index)) => index,
+			None => {
+				let index = indexes.next;
+				indexes.next = index.checked_add(1).expect("too many thread indexes");
+				MAX_THREADS.store(indexes.next, Ordering::Release);
+				index
+			}
+		}
+	};
+	INDEX.set(Some(index));
+	crate::dtor::register_dtor(release);
+	index
+}
+
+fn release() {
+	if let Some(index) = INDEX.take() {
+		let mut indexes = INDEXES.lock().unwrap_or_else(PoisonError::into_inner);
+		indexes.free.push(Reverse(index));
+	}
+}
+
+/// Holds the lock taken by the first call to [`thread_index`] on each thread.
+#[cfg(feature = "test-hooks")]
+#[doc(hidden)]
+pub struct IndexLock(#[allow(dead_code)] MutexGuard<'static, Indexes>);
+
+/// Takes the lock used to allocate indexes, for testing that the fast path
+/// doesn't need it.
+#[cfg(feature = "test-hooks")]
+#[doc(hidden)]
+pub fn lock_thread_indexes() -> IndexLock {
+	IndexLock(INDEXES.lock().unwrap_or_else(PoisonError::into_inner))
+}
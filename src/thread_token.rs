@@ -0,0 +1,24 @@
+//! A number for each thread that, unlike its id, is never reused.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+crate::static_thread_local! {
+	// Zero until the thread first asks for its token.
+	static TOKEN: u64 = 0;
+}
+static NEXT: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the current thread's token.
+///
+/// Windows reuses thread ids once a thread has exited, so these are used
+/// instead where something is tied to the thread that created it.
+pub(crate) fn current() -> u64 {
+	match TOKEN.get() {
+		0 => {
+			let token = NEXT.fetch_add(1, Ordering::Relaxed);
+			TOKEN.set(token);
+			token
+		}
+		token => token,
+	}
+}
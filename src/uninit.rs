@@ -0,0 +1,178 @@
+//! Helpers for thread locals that store a `MaybeUninit`.
+//!
+//! These are useful for scratch space where the initial value doesn't matter.
+//! Note that the loader will actually copy the thread local's template (which
+//! is most likely zeros) but code must not rely on that.
+
+use crate::{borrow, StaticKey, StaticThreadLocal, UnsafeLocal};
+use core::mem::MaybeUninit;
+
+impl<T, K: StaticKey<Value = MaybeUninit<T>>> StaticThreadLocal<MaybeUninit<T>, K> {
+	/// Returns a pointer to the current thread's value.
+	///
+	/// The same caveats apply as for [`get_ptr`](StaticThreadLocal::get_ptr).
+	#[inline(always)]
+	pub fn as_mut_ptr(&self) -> *mut T {
+		self.get_ptr().cast()
+	}
+
+	/// Sets the value, without reading the old one.
+	///
+	/// # Example
+	///
+	/// ```
+	/// # #![feature(asm)]
+	/// use core::mem::MaybeUninit;
+	///
+	/// wintls::static_thread_local!{
+	///     static SCRATCH: MaybeUninit<[u8; 4096]> = MaybeUninit::uninit();
+	/// }
+	///
+	/// # fn main() {
+	/// SCRATCH.write([0; 4096]);
+	/// let first = unsafe { SCRATCH.assume_init_with(|scratch| scratch[0]) };
+	/// # }
+	/// ```
+	#[inline(always)]
+	pub fn write(&self, value: T) {
+		borrow::check_write(K::borrow());
+		unsafe { self.as_mut_ptr().write(value) }
+	}
+
+	/// Reads the value.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread. See
+	/// [`MaybeUninit::assume_init_read`].
+	#[inline(always)]
+	pub unsafe fn assume_init_read(&self) -> T {
+		borrow::check_read(K::borrow());
+		self.as_mut_ptr().read()
+	}
+
+	/// Calls `f` with a reference to the value.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread. See
+	/// [`MaybeUninit::assume_init_ref`].
+	#[inline(always)]
+	pub unsafe fn assume_init_with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		let _guard = borrow::BorrowGuard::shared(K::borrow());
+		f(&*self.as_mut_ptr())
+	}
+
+	/// Calls `f` with a mutable reference to the value.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread. See
+	/// [`MaybeUninit::assume_init_mut`].
+	#[inline(always)]
+	pub unsafe fn assume_init_with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+		let _guard = borrow::BorrowGuard::exclusive(K::borrow());
+		f(&mut *self.as_mut_ptr())
+	}
+}
+
+/// Keeping track of whether the value has been initialized is up to the user.
+///
+/// # Example
+///
+/// A value that's created the first time it's used on each thread and dropped
+/// when the thread exits.
+///
+/// ```
+/// # #![feature(asm)]
+/// use core::mem::MaybeUninit;
+/// use wintls::dtor::register_dtor;
+///
+/// wintls::static_thread_local!{
+///     static INITIALIZED: bool = false;
+/// }
+/// wintls::unsafe_local!{
+///     static NAMES: MaybeUninit<Vec<String>> = MaybeUninit::uninit();
+/// }
+///
+/// fn names() -> &'static mut Vec<String> {
+///     unsafe {
+///         if !INITIALIZED.get() {
+///             INITIALIZED.set(true);
+///             register_dtor(|| {
+///                 INITIALIZED.set(false);
+///                 NAMES.assume_init_drop();
+///             });
+///             return NAMES.init(Vec::new());
+///         }
+///         NAMES.assume_init_mut()
+///     }
+/// }
+///
+/// # fn main() {
+/// names().push(String::from("main"));
+/// assert_eq!(names().len(), 1);
+/// # }
+/// ```
+impl<T> UnsafeLocal<MaybeUninit<T>> {
+	/// Returns a pointer to the current thread's value.
+	#[inline(always)]
+	pub fn as_uninit_ptr(&self) -> *mut T {
+		self.as_ptr().cast()
+	}
+
+	/// Initializes the value, returning a reference to it.
+	///
+	/// The old value isn't dropped.
+	///
+	/// # Safety
+	///
+	/// There must not be any references to the value. The returned reference
+	/// has the same rules as [`as_ref_mut`](Self::as_ref_mut): it must be the
+	/// only reference to the value for as long as it's used.
+	#[inline(always)]
+	#[allow(clippy::mut_from_ref)]
+	pub unsafe fn init(&self, value: T) -> &mut T {
+		self.check_write();
+		(*self.as_ptr()).write(value)
+	}
+
+	/// Returns a reference to the value.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread. The same
+	/// rules apply as for [`as_ref`](Self::as_ref).
+	#[inline(always)]
+	pub unsafe fn assume_init_ref(&self) -> &T {
+		self.check_read();
+		&*self.as_uninit_ptr()
+	}
+
+	/// Returns a mutable reference to the value.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread. The same
+	/// rules apply as for [`as_ref_mut`](Self::as_ref_mut): there must not be
+	/// any other references to the value for as long as the returned reference
+	/// is used.
+	#[inline(always)]
+	#[allow(clippy::mut_from_ref)]
+	pub unsafe fn assume_init_mut(&self) -> &mut T {
+		self.check_write();
+		&mut *self.as_uninit_ptr()
+	}
+
+	/// Drops the value, leaving it uninitialized.
+	///
+	/// # Safety
+	///
+	/// The value must have been initialized on the current thread and there
+	/// must not be any references to it.
+	#[inline(always)]
+	pub unsafe fn assume_init_drop(&self) {
+		self.check_write();
+		self.as_uninit_ptr().drop_in_place()
+	}
+}
@@ -0,0 +1,112 @@
+#![feature(asm)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+// Counts the current thread's live allocations that could be arena chunks.
+struct Counting;
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		if layout.size() >= CHUNK_SIZE {
+			LIVE_CHUNKS.set(LIVE_CHUNKS.get() + 1);
+		}
+		System.alloc(layout)
+	}
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		if layout.size() >= CHUNK_SIZE {
+			LIVE_CHUNKS.set(LIVE_CHUNKS.get() - 1);
+		}
+		System.dealloc(ptr, layout)
+	}
+}
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const CHUNK_SIZE: usize = 4096;
+
+wintls::static_thread_local! {
+	static LIVE_CHUNKS: isize = 0;
+}
+
+wintls::local_arena! {
+	static BOUNDARIES;
+	static RESET;
+	static ALIGNED;
+	static CLEANED_UP;
+}
+
+#[test]
+fn across_chunk_boundaries() {
+	BOUNDARIES.with(|arena| {
+		let values: Vec<&[u8; 1000]> = (0..20u8).map(|i| arena.alloc([i; 1000])).collect();
+		assert!(arena.capacity() >= 20 * 1000);
+		for (i, value) in values.into_iter().enumerate() {
+			assert!(value.iter().all(|&b| b == i as u8));
+		}
+	});
+	// Values larger than a chunk get a chunk of their own.
+	BOUNDARIES.with(|arena| {
+		let big = arena.alloc([7u8; 100_000]);
+		assert!(big.iter().all(|&b| b == 7));
+	});
+}
+
+#[test]
+fn reset_and_reuse() {
+	RESET.with(|arena| {
+		for i in 0..10_000u32 {
+			arena.alloc(i);
+		}
+		let capacity = arena.capacity();
+		arena.reset();
+		// Only the largest chunk is kept.
+		assert!(arena.capacity() < capacity);
+		let largest = arena.capacity();
+
+		let first = arena.alloc(1u64) as *const u64;
+		arena.reset();
+		assert_eq!(arena.alloc(2u64) as *const u64, first);
+		assert_eq!(arena.capacity(), largest);
+	});
+}
+
+#[test]
+fn aligned() {
+	#[derive(Clone, Copy)]
+	#[repr(align(64))]
+	struct Aligned(u8);
+
+	ALIGNED.with(|arena| {
+		for i in 0..100u8 {
+			let byte = arena.alloc(i);
+			assert_eq!(*byte, i);
+			let aligned = arena.alloc(Aligned(i));
+			assert_eq!(aligned as *const Aligned as usize % 64, 0);
+			assert_eq!(aligned.0, i);
+			let int = arena.alloc(u64::from(i));
+			assert_eq!(int as *const u64 as usize % 8, 0);
+			assert_eq!(*int, u64::from(i));
+		}
+		let () = *arena.alloc(());
+	});
+}
+
+#[test]
+fn cleaned_up_at_thread_exit() {
+	static LEAKED: AtomicIsize = AtomicIsize::new(-1);
+	std::thread::spawn(|| {
+		// Destructors run last to first, so this runs after the arena is
+		// freed.
+		wintls::dtor::register_dtor(|| LEAKED.store(LIVE_CHUNKS.get(), Ordering::SeqCst));
+		assert_eq!(LIVE_CHUNKS.get(), 0);
+		CLEANED_UP.with(|arena| {
+			for i in 0..100_000u32 {
+				arena.alloc(i);
+			}
+		});
+		assert!(LIVE_CHUNKS.get() > 1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(LEAKED.load(Ordering::SeqCst), 0);
+}
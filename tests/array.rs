@@ -0,0 +1,73 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static SLOTS: [u32; 16] = [0xfeedface; 16];
+}
+
+#[test]
+fn get_set_at() {
+	assert_eq!(SLOTS.get_at(0), 0xfeedface);
+	assert_eq!(SLOTS.get_at(15), 0xfeedface);
+
+	SLOTS.set_at(0, 1);
+	SLOTS.set_at(15, 2);
+	assert_eq!(SLOTS.get_at(0), 1);
+	assert_eq!(SLOTS.get_at(15), 2);
+
+	let mut expected = [0xfeedface; 16];
+	expected[0] = 1;
+	expected[15] = 2;
+	assert_eq!(SLOTS.get(), expected);
+
+	std::thread::spawn(|| {
+		assert_eq!(SLOTS.get_at(0), 0xfeedface);
+		assert_eq!(SLOTS.get_at(15), 0xfeedface);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn get_at_out_of_bounds() {
+	SLOTS.get_at(16);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn set_at_out_of_bounds() {
+	SLOTS.set_at(16, 0);
+}
+
+wintls::static_thread_local! {
+	static SCRATCH: [u8; 8] = [0; 8];
+}
+
+#[test]
+fn with_slice_sort() {
+	SCRATCH.with_slice(|s| s.copy_from_slice(&[5, 3, 8, 1, 7, 2, 6, 4]));
+	SCRATCH.with_slice(|s| s.sort_unstable());
+	assert_eq!(SCRATCH.get(), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+	std::thread::spawn(|| SCRATCH.with_slice(|s| assert!(s.iter().all(|&b| b == 0))))
+		.join()
+		.unwrap();
+}
+
+#[test]
+fn with_slice_in_dtor() {
+	use std::sync::atomic::{AtomicBool, Ordering};
+	static SAW_DATA: AtomicBool = AtomicBool::new(false);
+
+	std::thread::spawn(|| {
+		SCRATCH.with_slice(|s| s.fill(9));
+		wintls::dtor::register_dtor(|| {
+			let saw_data = SCRATCH.with_slice(|s| s.iter().all(|&b| b == 9));
+			SAW_DATA.store(saw_data, Ordering::SeqCst);
+		});
+	})
+	.join()
+	.unwrap();
+
+	assert!(SAW_DATA.load(Ordering::SeqCst));
+}
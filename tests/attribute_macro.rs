@@ -0,0 +1,66 @@
+#![feature(asm)]
+
+#[wintls::thread_local]
+static COUNT: u32 = 1;
+
+/// Documented.
+#[wintls::thread_local(zeroed, align = 8, section = "B")]
+pub static BUFFER: [u8; 64];
+
+#[wintls::thread_local(zeroed)]
+pub(crate) static PAIR: (u64, u32);
+
+// Both styles can be used in the same crate.
+wintls::static_thread_local! {
+	static OTHER: u32 = 2;
+}
+
+#[test]
+fn values() {
+	assert_eq!(COUNT.get(), 1);
+	assert_eq!(BUFFER.get(), [0; 64]);
+	assert_eq!(PAIR.get(), (0, 0));
+	assert_eq!(OTHER.get(), 2);
+	COUNT.set(3);
+	BUFFER.set_at(3, 4);
+	std::thread::spawn(|| {
+		assert_eq!(COUNT.get(), 1);
+		assert_eq!(BUFFER.get_at(3), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNT.get(), 3);
+	assert_eq!(BUFFER.get_at(3), 4);
+}
+
+#[test]
+fn alignment() {
+	assert_eq!(BUFFER.get_ptr() as usize % 8, 0);
+}
+
+#[wintls::thread_local]
+#[export_name = "test_attribute_exported"]
+static EXPORTED: u16 = 9;
+
+extern "C" {
+	#[link_name = "test_attribute_exported"]
+	static ATTRIBUTE_EXPORTED: u16;
+}
+
+#[test]
+fn export_name() {
+	let key = unsafe { wintls::static_key!(ATTRIBUTE_EXPORTED) };
+	assert_eq!(key, <EXPORTED as wintls::StaticKey>::key());
+	assert_eq!(EXPORTED.get(), 9);
+}
+
+extern crate wintls as renamed;
+
+// The path to the crate can be given if it's been renamed.
+#[wintls::thread_local(crate = "renamed")]
+static RENAMED: u32 = 5;
+
+#[test]
+fn renamed_crate() {
+	assert_eq!(RENAMED.get(), 5);
+}
@@ -0,0 +1,55 @@
+#![feature(asm)]
+// Doc comments must be forwarded to the public statics.
+#![deny(missing_docs)]
+//! Attributes and visibility on declared locals.
+
+/// Declared locals.
+pub mod locals {
+	wintls::static_thread_local! {
+		/// A documented local.
+		pub static DOCUMENTED: u32 = 1;
+		/// Only visible within the crate.
+		pub(crate) static CRATE_VISIBLE: u32 = 2;
+		/// Declared depending on a feature.
+		#[cfg(not(feature = "raw"))]
+		pub static FEATURE_GATED: u32 = 3;
+		/// Declared depending on a feature.
+		#[cfg(feature = "raw")]
+		pub static FEATURE_GATED: u64 = 4;
+	}
+
+	wintls::static_thread_local! {
+		// `any()` is always false so this is removed entirely.
+		#[cfg(any())]
+		pub static MISSING: String = String::new();
+	}
+
+	wintls::unsafe_local! {
+		/// An unsafe local.
+		pub static UNSAFE: u32 = 5;
+		#[cfg(any())]
+		pub static UNSAFE_MISSING: String = String::new();
+	}
+
+	wintls::static_thread_local_struct! {
+		#[cfg(any())]
+		pub static MISSING_STRUCT: Missing {
+			pub spans: String = String::new(),
+		}
+	}
+}
+
+#[test]
+fn visibility() {
+	assert_eq!(locals::DOCUMENTED.get(), 1);
+	assert_eq!(locals::CRATE_VISIBLE.get(), 2);
+	assert_eq!(unsafe { *locals::UNSAFE.as_ptr() }, 5);
+}
+
+#[test]
+fn cfg() {
+	#[cfg(not(feature = "raw"))]
+	assert_eq!(locals::FEATURE_GATED.get(), 3_u32);
+	#[cfg(feature = "raw")]
+	assert_eq!(locals::FEATURE_GATED.get(), 4_u64);
+}
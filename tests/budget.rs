@@ -0,0 +1,27 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static BUFFER: [u8; 1024] = [0; 1024];
+	static COUNT: u32 = 0;
+	lazy static SEED: u64 = 1;
+}
+
+wintls::static_thread_local_struct! {
+	static CTX: Context {
+		ticks: u64 = 0,
+		flags: u8 = 0,
+	}
+}
+
+// Exactly at the budget is fine.
+wintls::assert_tls_budget!(1028; BUFFER, COUNT);
+wintls::assert_tls_budget!(4096; BUFFER, COUNT, SEED, CTX);
+
+#[test]
+fn within_budget() {
+	assert_eq!(COUNT.get(), 0);
+	assert_eq!(BUFFER.get_at(0), 0);
+	assert_eq!(SEED.get(), 1);
+	assert_eq!(CTX.ticks.get(), 0);
+	assert_eq!(CTX.flags.get(), 0);
+}
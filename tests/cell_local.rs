@@ -0,0 +1,45 @@
+#![feature(asm)]
+
+wintls::cell_local! {
+	static COUNT: u32 = 1;
+	/// An optional id.
+	pub static ID: Option<u32> = Some(1);
+	static PAIR: (u8, u8) = (1, 2);
+}
+
+#[test]
+fn get_set() {
+	assert_eq!(COUNT.get(), 1);
+	COUNT.set(2);
+	std::thread::spawn(|| {
+		assert_eq!(COUNT.get(), 1);
+		COUNT.set(3);
+		assert_eq!(COUNT.get(), 3);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNT.get(), 2);
+}
+
+#[test]
+fn take_and_replace() {
+	std::thread::spawn(|| {
+		assert_eq!(ID.take(), Some(1));
+		assert_eq!(ID.take(), None);
+		assert_eq!(ID.replace(Some(2)), None);
+		assert_eq!(ID.get(), Some(2));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(ID.get(), Some(1));
+}
+
+#[test]
+fn update() {
+	assert_eq!(PAIR.update(|(a, b)| (b, a)), (2, 1));
+	assert_eq!(PAIR.get(), (2, 1));
+	std::thread::spawn(|| assert_eq!(PAIR.update(|(a, b)| (a + b, 0)), (3, 0)))
+		.join()
+		.unwrap();
+	assert_eq!(PAIR.get(), (2, 1));
+}
@@ -0,0 +1,39 @@
+//! Builds the helper crates in `tests/` that some tests need as separate
+//! binaries.
+// Not every test uses every function.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Returns a `cargo <subcommand>` command for the crate in `tests/<name>`.
+///
+/// Each `target` is a separate target directory, so that builds with
+/// different flags don't invalidate each other.
+pub fn cargo(subcommand: &str, name: &str, target: &str) -> Command {
+	let manifest = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join(name)
+		.join("Cargo.toml");
+	let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+	let mut command = Command::new(cargo);
+	command
+		.arg(subcommand)
+		.arg("--manifest-path")
+		.arg(manifest)
+		.arg("--target-dir")
+		.arg(target_dir(target));
+	command
+}
+
+/// Builds the crate in `tests/<name>`, returning the directory its binaries
+/// are in.
+pub fn build(name: &str) -> PathBuf {
+	let status = cargo("build", name, name).status().unwrap();
+	assert!(status.success(), "failed to build `{name}`: {status}");
+	target_dir(name).join("debug")
+}
+
+fn target_dir(target: &str) -> PathBuf {
+	Path::new(env!("CARGO_TARGET_TMPDIR")).join(target)
+}
@@ -0,0 +1,48 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+wintls::thread_local_counter! {
+	static HAMMERED;
+	static SATURATED;
+}
+
+#[test]
+fn exact_total() {
+	const THREADS: u64 = 8;
+	const INCREMENTS: u64 = 10_000;
+	let done = Arc::new(AtomicBool::new(false));
+	let watcher = {
+		let done = done.clone();
+		std::thread::spawn(move || {
+			while !done.load(Ordering::Relaxed) {
+				assert!(HAMMERED.sum() <= THREADS * (INCREMENTS + 2));
+			}
+		})
+	};
+	let threads: Vec<_> = (0..THREADS)
+		.map(|_| {
+			std::thread::spawn(|| {
+				for _ in 0..INCREMENTS {
+					HAMMERED.increment();
+				}
+				HAMMERED.add(2);
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	done.store(true, Ordering::Relaxed);
+	watcher.join().unwrap();
+	assert_eq!(HAMMERED.sum(), THREADS * (INCREMENTS + 2));
+}
+
+#[test]
+fn saturates() {
+	SATURATED.add(u64::MAX - 1);
+	SATURATED.add(5);
+	std::thread::spawn(|| SATURATED.add(5)).join().unwrap();
+	assert_eq!(SATURATED.sum(), u64::MAX);
+}
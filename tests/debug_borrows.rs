@@ -0,0 +1,36 @@
+#![feature(asm)]
+
+wintls::unsafe_local! {
+	static LIST: Vec<u32> = Vec::new();
+	static OTHER: Vec<u32> = Vec::new();
+}
+
+#[test]
+fn nesting_is_allowed() {
+	unsafe {
+		LIST.with_mut(|list| list.push(1));
+		LIST.with(|a| LIST.with(|b| assert_eq!(a, b)));
+		LIST.with(|list| OTHER.with_mut(|other| other.extend(list)));
+		OTHER.with(|other| assert_eq!(other, &[1]));
+		// The borrows have all ended.
+		LIST.with_mut(|list| list.clear());
+	}
+}
+
+#[test]
+#[should_panic(expected = "thread local `LIST` is already mutably borrowed")]
+fn shared_while_exclusive() {
+	unsafe { LIST.with_mut(|_| LIST.with(|_| ())) }
+}
+
+#[test]
+#[should_panic(expected = "thread local `LIST` is already borrowed")]
+fn exclusive_while_shared() {
+	unsafe { LIST.with(|_| LIST.take()) };
+}
+
+#[test]
+#[should_panic(expected = "thread local `LIST` is already mutably borrowed")]
+fn format_while_exclusive() {
+	unsafe { LIST.with_mut(|_| format!("{:?}", LIST)) };
+}
@@ -0,0 +1,24 @@
+#![feature(asm)]
+
+wintls::dll_safe_thread_local! {
+	/// A counter.
+	pub static COUNTER: u32 = 1;
+	static FLAG: bool = false;
+}
+
+#[test]
+fn get_set() {
+	assert_eq!(COUNTER.get(), 1);
+	COUNTER.set(2);
+	assert_eq!(COUNTER.replace(3), 2);
+	assert!(!FLAG.get());
+	std::thread::spawn(|| {
+		assert_eq!(COUNTER.get(), 1);
+		FLAG.set(true);
+		assert!(FLAG.get());
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNTER.get(), 3);
+	assert!(!FLAG.get());
+}
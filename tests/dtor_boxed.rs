@@ -0,0 +1,55 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+#[test]
+fn closure_drops_its_capture() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	static DROPPED: AtomicU32 = AtomicU32::new(0);
+	struct Capture(String);
+	impl Drop for Capture {
+		fn drop(&mut self) {
+			DROPPED.fetch_add(1, Ordering::SeqCst);
+		}
+	}
+
+	std::thread::spawn(|| {
+		let capture = Capture(String::from("context"));
+		wintls::dtor::register_dtor_boxed(Box::new(move || {
+			assert_eq!(capture.0, "context");
+			RAN.fetch_add(1, Ordering::SeqCst);
+		}));
+		assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 1);
+	assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn mixed_lifo_order() {
+	static ORDER: Mutex<Vec<String>> = Mutex::new(Vec::new());
+	fn record(name: &str) {
+		ORDER.lock().unwrap().push(name.to_string());
+	}
+	std::thread::spawn(|| {
+		wintls::dtor::register_dtor(|| record("fn 1"));
+		let name = String::from("closure 2");
+		wintls::dtor::register_dtor_boxed(Box::new(move || {
+			record(&name);
+			// Registered while running, so it runs after the rest.
+			wintls::dtor::register_dtor_boxed(Box::new(|| record("nested")));
+		}));
+		wintls::dtor::register_dtor(|| record("fn 3"));
+		let name = String::from("closure 4");
+		wintls::dtor::register_dtor_boxed(Box::new(move || record(&name)));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(
+		*ORDER.lock().unwrap(),
+		["closure 4", "fn 3", "closure 2", "fn 1", "nested"]
+	);
+}
@@ -0,0 +1,146 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use wintls::dtor::{register_dtor, register_dtor_boxed, DtorHandle};
+
+#[test]
+fn cancelled_never_runs() {
+	static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor(|| ORDER.lock().unwrap().push(1));
+		let cancelled = register_dtor(|| ORDER.lock().unwrap().push(2));
+		register_dtor(|| ORDER.lock().unwrap().push(3));
+		let last = register_dtor(|| ORDER.lock().unwrap().push(4));
+		cancelled.cancel();
+		last.cancel();
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [3, 1]);
+}
+
+#[test]
+fn cancelled_closure_is_dropped() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	static DROPPED: AtomicU32 = AtomicU32::new(0);
+	struct Capture;
+	impl Drop for Capture {
+		fn drop(&mut self) {
+			DROPPED.fetch_add(1, Ordering::SeqCst);
+		}
+	}
+	std::thread::spawn(|| {
+		let capture = Capture;
+		let handle = register_dtor_boxed(Box::new(move || {
+			let _capture = &capture;
+			RAN.fetch_add(1, Ordering::SeqCst);
+		}));
+		handle.cancel();
+		assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn cancel_from_another_destructor() {
+	static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	static HANDLE: Mutex<Option<DtorHandle>> = Mutex::new(None);
+	std::thread::spawn(|| {
+		let handle = register_dtor(|| ORDER.lock().unwrap().push(1));
+		*HANDLE.lock().unwrap() = Some(handle);
+		register_dtor(|| {
+			ORDER.lock().unwrap().push(2);
+			// The other destructor hasn't been run yet.
+			HANDLE.lock().unwrap().take().unwrap().cancel();
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [2]);
+}
+
+#[test]
+fn cancel_after_run() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	std::thread::spawn(|| {
+		let handle = register_dtor(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		});
+		unsafe { wintls::dtor::drop_locals() };
+		assert_eq!(RAN.load(Ordering::SeqCst), 1);
+		handle.cancel();
+		// Later registrations are unaffected.
+		register_dtor(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn many_cancelled() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	std::thread::spawn(|| {
+		register_dtor(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		});
+		let handles: Vec<_> = (0..10_000)
+			.map(|_| register_dtor(|| unreachable!()))
+			.collect();
+		register_dtor(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		});
+		for handle in handles {
+			handle.cancel();
+		}
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+#[should_panic(expected = "a destructor can only be cancelled by the thread that registered it")]
+fn cancel_on_other_thread() {
+	let handle = std::thread::spawn(|| register_dtor(|| {})).join().unwrap();
+	handle.cancel();
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn GetCurrentThreadId() -> u32;
+}
+
+#[test]
+fn cancel_on_thread_with_reused_id() {
+	let (id, handle) =
+		std::thread::spawn(|| (unsafe { GetCurrentThreadId() }, register_dtor(|| {})))
+			.join()
+			.unwrap();
+	let mut handle = Some(handle);
+	// Windows reuses the ids of exited threads quickly.
+	for _ in 0..1000 {
+		handle = std::thread::spawn(move || {
+			if unsafe { GetCurrentThreadId() } != id {
+				return handle;
+			}
+			let cancelled = std::panic::catch_unwind(|| handle.unwrap().cancel());
+			assert!(
+				cancelled.is_err(),
+				"cancelled on a thread that reused the id"
+			);
+			None
+		})
+		.join()
+		.unwrap();
+		if handle.is_none() {
+			return;
+		}
+	}
+	panic!("no thread reused the id");
+}
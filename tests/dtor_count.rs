@@ -0,0 +1,75 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use wintls::dtor;
+
+#[test]
+fn passive() {
+	std::thread::spawn(|| {
+		assert_eq!(dtor::registered_count(), 0);
+		assert!(dtor::is_empty());
+		dtor::register_dtor(|| {});
+		let cancelled = dtor::register_dtor(|| {});
+		dtor::register_dtor_with_priority(3, || {});
+		assert_eq!(dtor::registered_count(), 3);
+		cancelled.cancel();
+		assert_eq!(dtor::registered_count(), 2);
+		assert!(!dtor::is_empty());
+		#[cfg(debug_assertions)]
+		dtor::dump();
+		unsafe { dtor::drop_locals() };
+		assert!(dtor::is_empty());
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn while_running() {
+	static COUNTS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+	fn record() {
+		COUNTS.lock().unwrap().push(dtor::registered_count());
+	}
+	std::thread::spawn(|| {
+		dtor::register_dtor(record);
+		dtor::register_dtor(record);
+		dtor::register_dtor(|| {
+			record();
+			dtor::register_dtor(record);
+			record();
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*COUNTS.lock().unwrap(), [2, 3, 2, 1, 0]);
+}
+
+wintls::static_thread_local! {
+	static WATCHED: bool = false;
+}
+static COUNT_AFTER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// This runs after the crate's own callback.
+#[link_section = ".CRT$XLC"]
+#[used]
+static CALLBACK: unsafe extern "system" fn(*mut i8, u32, *mut i8) = callback;
+extern "system" fn callback(_: *mut i8, reason: u32, _: *mut i8) {
+	const DLL_THREAD_DETACH: u32 = 3;
+	if reason == DLL_THREAD_DETACH && WATCHED.get() && dtor::is_empty() {
+		#[cfg(debug_assertions)]
+		dtor::dump();
+		COUNT_AFTER.store(dtor::registered_count(), Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn after_teardown() {
+	std::thread::spawn(|| {
+		WATCHED.set(true);
+		dtor::register_dtor(|| {});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNT_AFTER.load(Ordering::SeqCst), 0);
+}
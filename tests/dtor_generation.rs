@@ -0,0 +1,44 @@
+#![feature(asm)]
+
+use std::sync::Mutex;
+use wintls::dtor;
+
+#[test]
+fn drop_locals_bumps_generation() {
+	std::thread::spawn(|| {
+		assert_eq!(dtor::generation(), 0);
+		unsafe { dtor::drop_locals() };
+		assert_eq!(dtor::generation(), 1);
+		dtor::register_dtor(|| {});
+		unsafe { dtor::drop_locals() };
+		assert_eq!(dtor::generation(), 2);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn per_thread() {
+	std::thread::spawn(|| {
+		unsafe { dtor::drop_locals() };
+		std::thread::spawn(|| assert_eq!(dtor::generation(), 0))
+			.join()
+			.unwrap();
+		assert_eq!(dtor::generation(), 1);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn same_during_drain() {
+	static SEEN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		dtor::register_dtor(|| SEEN.lock().unwrap().push(dtor::generation()));
+		unsafe { dtor::drop_locals() };
+		dtor::register_dtor(|| SEEN.lock().unwrap().push(dtor::generation()));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*SEEN.lock().unwrap(), [0, 1]);
+}
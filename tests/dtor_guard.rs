@@ -0,0 +1,97 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use wintls::dtor::{self, DtorGuard};
+
+#[test]
+fn dropped_guard_runs_once() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	std::thread::spawn(|| {
+		let guard = DtorGuard::new(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		});
+		assert_eq!(dtor::registered_count(), 1);
+		drop(guard);
+		assert_eq!(RAN.load(Ordering::SeqCst), 1);
+		assert_eq!(dtor::registered_count(), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn leaked_guard_runs_at_thread_exit() {
+	static RAN: AtomicU32 = AtomicU32::new(0);
+	std::thread::spawn(|| {
+		core::mem::forget(DtorGuard::new(|| {
+			RAN.fetch_add(1, Ordering::SeqCst);
+		}));
+		assert_eq!(RAN.load(Ordering::SeqCst), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn guard_dropped_after_its_destructor() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	// Only used by one thread.
+	struct Guard(Option<DtorGuard>);
+	unsafe impl Send for Guard {}
+	wintls::unsafe_local! {
+		static GUARD: Guard = Guard(None);
+	}
+	std::thread::spawn(|| unsafe {
+		// Registered first, so it runs after the guard's destructor.
+		dtor::register_dtor(|| {
+			ORDER.lock().unwrap().push("drop guard");
+			GUARD.with_mut(|guard| guard.0 = None);
+		});
+		let guard = DtorGuard::new(|| ORDER.lock().unwrap().push("guard"));
+		GUARD.with_mut(|slot| slot.0 = Some(guard));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), ["guard", "drop guard"]);
+}
+
+#[test]
+fn guard_dropped_before_its_destructor() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	struct Guard(Option<DtorGuard>);
+	unsafe impl Send for Guard {}
+	wintls::unsafe_local! {
+		static GUARD: Guard = Guard(None);
+	}
+	std::thread::spawn(|| unsafe {
+		let guard = DtorGuard::new(|| ORDER.lock().unwrap().push("guard"));
+		GUARD.with_mut(|slot| slot.0 = Some(guard));
+		// Registered last, so it runs first and cancels the guard's destructor.
+		dtor::register_dtor(|| {
+			ORDER.lock().unwrap().push("drop guard");
+			GUARD.with_mut(|guard| guard.0 = None);
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), ["drop guard", "guard"]);
+}
+
+#[test]
+fn guard_in_destructor() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		dtor::register_dtor(|| {
+			let guard = DtorGuard::new(|| ORDER.lock().unwrap().push("dropped"));
+			core::mem::forget(DtorGuard::new(|| ORDER.lock().unwrap().push("leaked")));
+			drop(guard);
+			ORDER.lock().unwrap().push("destructor");
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), ["dropped", "destructor", "leaked"]);
+}
@@ -0,0 +1,40 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wintls::dtor::{self, INLINE_CAPACITY};
+
+#[test]
+fn full() {
+	static RAN: AtomicUsize = AtomicUsize::new(0);
+	fn count() {
+		RAN.fetch_add(1, Ordering::SeqCst);
+	}
+	std::thread::spawn(|| {
+		for _ in 0..INLINE_CAPACITY {
+			dtor::try_register_dtor(count).unwrap();
+		}
+		let error = dtor::try_register_dtor(count).unwrap_err();
+		assert!(error.is_full());
+		assert_eq!(dtor::registered_count(), INLINE_CAPACITY);
+
+		// Running them makes room.
+		unsafe { dtor::drop_locals() };
+		assert_eq!(RAN.load(Ordering::SeqCst), INLINE_CAPACITY);
+		dtor::try_register_dtor(count).unwrap();
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RAN.load(Ordering::SeqCst), INLINE_CAPACITY + 1);
+}
+
+#[test]
+fn register_panics_when_full() {
+	let result = std::thread::spawn(|| {
+		for _ in 0..INLINE_CAPACITY {
+			dtor::register_dtor(|| {});
+		}
+		dtor::register_dtor(|| {});
+	})
+	.join();
+	assert!(result.is_err());
+}
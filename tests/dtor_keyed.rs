@@ -0,0 +1,46 @@
+#![feature(asm)]
+
+use std::sync::Mutex;
+use wintls::dtor::{register_dtor, register_dtor_keyed, remove_dtors_for_key, run_dtors_for_key};
+
+const HOST: usize = 1;
+const PLUGIN: usize = 2;
+
+#[test]
+fn run_one_key_early() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor_keyed(HOST, || ORDER.lock().unwrap().push("host 1"));
+		register_dtor_keyed(PLUGIN, || ORDER.lock().unwrap().push("plugin 1"));
+		register_dtor(|| ORDER.lock().unwrap().push("unkeyed"));
+		register_dtor_keyed(HOST, || ORDER.lock().unwrap().push("host 2"));
+		register_dtor_keyed(PLUGIN, || ORDER.lock().unwrap().push("plugin 2"));
+
+		run_dtors_for_key(PLUGIN);
+		assert_eq!(*ORDER.lock().unwrap(), ["plugin 2", "plugin 1"]);
+		// They've been removed, so running them again does nothing.
+		run_dtors_for_key(PLUGIN);
+		assert_eq!(ORDER.lock().unwrap().len(), 2);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(
+		*ORDER.lock().unwrap(),
+		["plugin 2", "plugin 1", "host 2", "unkeyed", "host 1"]
+	);
+}
+
+#[test]
+fn remove_one_key() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor_keyed(PLUGIN, || ORDER.lock().unwrap().push("plugin"));
+		register_dtor_keyed(HOST, || ORDER.lock().unwrap().push("host"));
+		remove_dtors_for_key(PLUGIN);
+		// Registering again after a reload.
+		register_dtor_keyed(PLUGIN, || ORDER.lock().unwrap().push("reloaded"));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), ["reloaded", "host"]);
+}
@@ -0,0 +1,104 @@
+#![feature(asm)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use wintls::dtor::{self, DtorNode};
+
+wintls::static_thread_local! {
+	static REGISTERED: bool = false;
+	static IN_ALLOC: bool = false;
+	static WATCHED: bool = false;
+}
+wintls::unsafe_local! {
+	static NODE: DtorNode = DtorNode::new(on_exit, core::ptr::null_mut());
+}
+static RECURSED: AtomicBool = AtomicBool::new(false);
+static EXITED: AtomicU32 = AtomicU32::new(0);
+
+fn on_exit(_: *mut u8) {
+	if WATCHED.get() {
+		EXITED.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+// Registers a node the first time each thread allocates.
+struct Alloc;
+unsafe impl GlobalAlloc for Alloc {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		if IN_ALLOC.get() {
+			RECURSED.store(true, Ordering::SeqCst);
+		} else if !REGISTERED.get() {
+			IN_ALLOC.set(true);
+			REGISTERED.set(dtor::register_dtor_node(&mut *NODE.as_ptr()).is_ok());
+			IN_ALLOC.set(false);
+		}
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static ALLOC: Alloc = Alloc;
+
+#[test]
+fn register_from_allocator() {
+	std::thread::spawn(|| {
+		WATCHED.set(true);
+		drop(Box::new(1));
+		assert!(REGISTERED.get());
+		assert!(unsafe { (*NODE.as_ptr()).is_registered() });
+	})
+	.join()
+	.unwrap();
+	assert_eq!(EXITED.load(Ordering::SeqCst), 1);
+	assert!(!RECURSED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn nodes_run_first() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	fn first(_: *mut u8) {
+		ORDER.lock().unwrap().push("first node");
+	}
+	fn second(_: *mut u8) {
+		ORDER.lock().unwrap().push("second node");
+		// Run in the next pass.
+		dtor::register_dtor_node(unsafe { &mut *THIRD.as_ptr() }).unwrap();
+	}
+	fn third(_: *mut u8) {
+		ORDER.lock().unwrap().push("third node");
+	}
+	wintls::unsafe_local! {
+		static FIRST: DtorNode = DtorNode::new(first, core::ptr::null_mut());
+		static SECOND: DtorNode = DtorNode::new(second, core::ptr::null_mut());
+		static THIRD: DtorNode = DtorNode::new(third, core::ptr::null_mut());
+	}
+	std::thread::spawn(|| unsafe {
+		dtor::register_dtor(|| {
+			ORDER.lock().unwrap().push("destructor");
+			// Run after the destructors.
+			dtor::register_dtor_node(&mut *FIRST.as_ptr()).unwrap();
+		});
+		dtor::register_dtor_node(&mut *FIRST.as_ptr()).unwrap();
+		dtor::register_dtor_node(&mut *SECOND.as_ptr()).unwrap();
+		// Registering twice does nothing.
+		dtor::register_dtor_node(&mut *SECOND.as_ptr()).unwrap();
+		assert_eq!(dtor::registered_count(), 3);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(
+		*ORDER.lock().unwrap(),
+		[
+			"second node",
+			"first node",
+			"third node",
+			"destructor",
+			"first node"
+		]
+	);
+}
@@ -0,0 +1,61 @@
+#![feature(asm)]
+
+use std::sync::Mutex;
+use wintls::dtor::{register_dtor, register_dtor_with_priority, set_order, Order};
+
+#[test]
+fn fifo() {
+	static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		set_order(Order::Fifo);
+		register_dtor(|| ORDER.lock().unwrap().push(1));
+		register_dtor(|| ORDER.lock().unwrap().push(2));
+		register_dtor_with_priority(1, || ORDER.lock().unwrap().push(3));
+		register_dtor(|| ORDER.lock().unwrap().push(4));
+		register_dtor_with_priority(1, || ORDER.lock().unwrap().push(5));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [3, 5, 1, 2, 4]);
+}
+
+fn register_while_running(order: Order) -> Vec<&'static str> {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	std::thread::spawn(move || {
+		set_order(order);
+		register_dtor(|| {
+			ORDER.lock().unwrap().push("a");
+			register_dtor_with_priority(1, || ORDER.lock().unwrap().push("higher"));
+			register_dtor(|| ORDER.lock().unwrap().push("same 1"));
+			register_dtor(|| ORDER.lock().unwrap().push("same 2"));
+		});
+		register_dtor(|| ORDER.lock().unwrap().push("b"));
+	})
+	.join()
+	.unwrap();
+	core::mem::take(&mut *ORDER.lock().unwrap())
+}
+
+#[test]
+fn registered_while_running() {
+	assert_eq!(
+		register_while_running(Order::Fifo),
+		["a", "higher", "b", "same 1", "same 2"]
+	);
+	assert_eq!(
+		register_while_running(Order::Lifo),
+		["b", "a", "higher", "same 2", "same 1"]
+	);
+}
+
+#[test]
+fn change_after_registering() {
+	let result = std::thread::spawn(|| {
+		register_dtor(|| {});
+		// Setting the same order is fine.
+		set_order(Order::Lifo);
+		set_order(Order::Fifo);
+	})
+	.join();
+	assert!(result.is_err());
+}
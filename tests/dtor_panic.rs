@@ -0,0 +1,52 @@
+#![feature(asm)]
+
+use std::panic;
+use std::sync::Mutex;
+use wintls::dtor::register_dtor;
+
+static RAN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static PANICS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn register() {
+	register_dtor(|| RAN.lock().unwrap().push(1));
+	register_dtor(|| panic!("destructor 2 panicked"));
+	register_dtor(|| RAN.lock().unwrap().push(3));
+}
+
+// Both cases are in one test as the panic hook is shared by every thread.
+#[test]
+fn panicking_destructor() {
+	panic::set_hook(Box::new(|info| {
+		let message = info.payload().downcast_ref::<&str>().copied();
+		PANICS
+			.lock()
+			.unwrap()
+			.push(message.unwrap_or_default().into());
+	}));
+
+	// Thread exit reports the panic and carries on.
+	std::thread::spawn(register).join().unwrap();
+	assert_eq!(*RAN.lock().unwrap(), [3, 1]);
+	assert_eq!(*PANICS.lock().unwrap(), ["destructor 2 panicked"]);
+
+	// `drop_locals` resumes it.
+	let result = std::thread::spawn(|| {
+		register();
+		let result = panic::catch_unwind(|| unsafe { wintls::dtor::drop_locals() });
+		assert!(matches!(
+			wintls::dtor::state(),
+			wintls::dtor::DtorState::Passive
+		));
+		result
+	})
+	.join()
+	.unwrap();
+	let _ = panic::take_hook();
+	let payload = result.unwrap_err();
+	assert_eq!(
+		payload.downcast_ref::<&str>(),
+		Some(&"destructor 2 panicked")
+	);
+	assert_eq!(*RAN.lock().unwrap(), [3, 1, 3, 1]);
+	assert_eq!(PANICS.lock().unwrap().len(), 2);
+}
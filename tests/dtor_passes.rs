@@ -0,0 +1,45 @@
+#![feature(asm)]
+
+use std::panic;
+use std::sync::atomic::{AtomicU32, Ordering};
+use wintls::dtor;
+
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+fn again() {
+	RUNS.fetch_add(1, Ordering::SeqCst);
+	dtor::register_dtor(again);
+}
+
+// One test, as the limit is shared by every thread.
+#[test]
+fn reregistering_destructor() {
+	// Thread exit stops too.
+	std::thread::spawn(|| {
+		dtor::register_dtor(again);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RUNS.swap(0, Ordering::SeqCst), 8);
+
+	std::thread::spawn(|| {
+		dtor::set_max_passes(3);
+		for _ in 0..2 {
+			// The passes are counted for each call.
+			dtor::register_dtor(again);
+			let result = panic::catch_unwind(|| unsafe { dtor::drop_locals() });
+			assert_eq!(RUNS.swap(0, Ordering::SeqCst), 3);
+			// Only debug builds report it.
+			assert_eq!(result.is_err(), cfg!(debug_assertions));
+			if let Err(payload) = result {
+				assert_eq!(
+					payload.downcast_ref::<String>().unwrap(),
+					"1 thread local destructors were discarded after 3 passes"
+				);
+			}
+		}
+		dtor::set_max_passes(8);
+	})
+	.join()
+	.unwrap();
+}
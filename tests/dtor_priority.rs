@@ -0,0 +1,61 @@
+#![feature(asm)]
+
+use std::sync::Mutex;
+use wintls::dtor::{register_dtor, register_dtor_with_priority};
+
+#[test]
+fn higher_priorities_run_first() {
+	static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor_with_priority(1, || ORDER.lock().unwrap().push(1));
+		register_dtor_with_priority(-1, || ORDER.lock().unwrap().push(2));
+		register_dtor(|| ORDER.lock().unwrap().push(3));
+		register_dtor_with_priority(1, || ORDER.lock().unwrap().push(4));
+		register_dtor_with_priority(i8::MIN, || ORDER.lock().unwrap().push(5));
+		register_dtor(|| ORDER.lock().unwrap().push(6));
+		register_dtor_with_priority(i8::MAX, || ORDER.lock().unwrap().push(7));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [7, 4, 1, 6, 3, 2, 5]);
+}
+
+#[test]
+fn registered_while_running() {
+	static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor_with_priority(-1, || ORDER.lock().unwrap().push("low"));
+		register_dtor(|| ORDER.lock().unwrap().push("first"));
+		register_dtor(|| {
+			ORDER.lock().unwrap().push("running");
+			register_dtor_with_priority(1, || ORDER.lock().unwrap().push("higher"));
+			register_dtor(|| ORDER.lock().unwrap().push("same"));
+			register_dtor_with_priority(-1, || ORDER.lock().unwrap().push("lower"));
+			register_dtor_with_priority(-2, || ORDER.lock().unwrap().push("lowest"));
+		});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(
+		*ORDER.lock().unwrap(),
+		["running", "higher", "first", "same", "low", "lower", "lowest"]
+	);
+}
+
+#[test]
+fn drop_locals_runs_by_priority() {
+	static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		register_dtor_with_priority(-5, || ORDER.lock().unwrap().push(1));
+		register_dtor_with_priority(5, || ORDER.lock().unwrap().push(2));
+		unsafe { wintls::dtor::drop_locals() };
+		assert_eq!(*ORDER.lock().unwrap(), [2, 1]);
+
+		// New destructors start from a clean batch.
+		register_dtor(|| ORDER.lock().unwrap().push(3));
+		register_dtor(|| ORDER.lock().unwrap().push(4));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [2, 1, 4, 3]);
+}
@@ -0,0 +1,90 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use wintls::dtor::{self, DtorState};
+
+#[test]
+fn in_destructor() {
+	static STATES: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+	std::thread::spawn(|| {
+		dtor::register_dtor(|| STATES.lock().unwrap().push(dtor::state() as u8));
+		unsafe { dtor::drop_locals() };
+		STATES.lock().unwrap().push(dtor::state() as u8);
+		dtor::register_dtor(|| STATES.lock().unwrap().push(dtor::state() as u8));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(
+		*STATES.lock().unwrap(),
+		[
+			DtorState::Dropping as u8,
+			DtorState::Passive as u8,
+			DtorState::Dropping as u8
+		]
+	);
+}
+
+wintls::static_thread_local! {
+	static WATCHED: bool = false;
+}
+static STATE_AFTER: AtomicU8 = AtomicU8::new(u8::MAX);
+static REGISTERED_AFTER: AtomicBool = AtomicBool::new(true);
+static RAN_AFTER: AtomicBool = AtomicBool::new(false);
+
+// This runs after the crate's own callback.
+#[link_section = ".CRT$XLC"]
+#[used]
+static CALLBACK: unsafe extern "system" fn(*mut i8, u32, *mut i8) = callback;
+extern "system" fn callback(_: *mut i8, reason: u32, _: *mut i8) {
+	const DLL_THREAD_DETACH: u32 = 3;
+	if reason == DLL_THREAD_DETACH && WATCHED.get() {
+		STATE_AFTER.store(dtor::state() as u8, Ordering::SeqCst);
+		REGISTERED_AFTER.store(
+			dtor::try_register_dtor(|| RAN_AFTER.store(true, Ordering::SeqCst)).is_ok(),
+			Ordering::SeqCst,
+		);
+		unsafe { dtor::drop_locals() };
+	}
+}
+
+#[test]
+fn after_thread_destructors() {
+	std::thread::spawn(|| {
+		WATCHED.set(true);
+		dtor::register_dtor(|| {});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(STATE_AFTER.load(Ordering::SeqCst), DtorState::Done as u8);
+	assert!(!REGISTERED_AFTER.load(Ordering::SeqCst));
+	assert!(!RAN_AFTER.load(Ordering::SeqCst));
+}
+
+#[test]
+fn try_register() {
+	static RAN: AtomicBool = AtomicBool::new(false);
+	std::thread::spawn(|| {
+		dtor::try_register_dtor(|| RAN.store(true, Ordering::SeqCst)).unwrap();
+	})
+	.join()
+	.unwrap();
+	assert!(RAN.load(Ordering::SeqCst));
+}
+
+extern "C" {
+	fn atexit(f: extern "C" fn()) -> i32;
+}
+
+#[test]
+fn in_atexit() {
+	extern "C" fn at_exit() {
+		// The main thread's destructors haven't been run yet. Failing the
+		// test means exiting with an error.
+		if !matches!(dtor::state(), DtorState::Passive) {
+			std::process::abort();
+		}
+		dtor::register_dtor(|| {});
+	}
+	assert_eq!(unsafe { atexit(at_exit) }, 0);
+}
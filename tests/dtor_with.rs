@@ -0,0 +1,47 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+struct Slot(u32);
+impl Drop for Slot {
+	fn drop(&mut self) {
+		DROPPED.fetch_add(self.0, Ordering::SeqCst);
+	}
+}
+
+// One shim for every slot.
+unsafe extern "C" fn drop_slot(data: *mut u8) {
+	drop(Box::from_raw(data.cast::<Slot>()));
+}
+
+#[test]
+fn one_shim_for_three_slots() {
+	std::thread::spawn(|| {
+		for value in [1, 10, 100] {
+			let slot = Box::into_raw(Box::new(Slot(value)));
+			unsafe { wintls::dtor::register_dtor_with(slot.cast(), drop_slot) };
+		}
+		assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DROPPED.load(Ordering::SeqCst), 111);
+}
+
+#[test]
+fn mixed_with_plain_dtors() {
+	static ORDER: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+	unsafe extern "C" fn record(data: *mut u8) {
+		ORDER.lock().unwrap().push(data.addr() as u32);
+	}
+	std::thread::spawn(|| unsafe {
+		wintls::dtor::register_dtor_with(std::ptr::without_provenance_mut(1), record);
+		wintls::dtor::register_dtor(|| ORDER.lock().unwrap().push(2));
+		wintls::dtor::register_dtor_with(std::ptr::without_provenance_mut(3), record);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*ORDER.lock().unwrap(), [3, 2, 1]);
+}
@@ -0,0 +1,125 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use wintls::DynamicThreadLocal;
+
+// Each test allocates TLS indexes, which would make `index_reused` flaky if the
+// tests ran in parallel.
+static LOCK: Mutex<()> = Mutex::new(());
+
+struct Tracked(&'static AtomicU32);
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn many_instances() {
+	let _lock = LOCK.lock().unwrap();
+	let locals: Vec<_> = (0..100)
+		.map(|i| DynamicThreadLocal::new(move || i).unwrap())
+		.collect();
+	for (i, local) in locals.iter().enumerate() {
+		local.with_mut(|value| *value *= 2);
+		assert_eq!(local.with(|value| *value), i * 2);
+	}
+	let locals = Arc::new(locals);
+	let shared = locals.clone();
+	std::thread::spawn(move || {
+		for (i, local) in shared.iter().enumerate() {
+			assert_eq!(local.with(|value| *value), i);
+		}
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn dropped_on_thread_exit() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let _lock = LOCK.lock().unwrap();
+	let local = Arc::new(DynamicThreadLocal::new(|| Tracked(&DROPS)).unwrap());
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			let local = local.clone();
+			std::thread::spawn(move || local.with(|_| {}))
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+
+	// Dropping the local drops the current thread's value.
+	local.with(|_| {});
+	drop(local);
+	assert_eq!(DROPS.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn index_reused() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let _lock = LOCK.lock().unwrap();
+	std::thread::spawn(|| {
+		let first = DynamicThreadLocal::new(|| Tracked(&DROPS)).unwrap();
+		let index = first.index();
+		first.with(|_| {});
+		drop(first);
+		assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+		// The thread's exit must only drop the new value.
+		let second = DynamicThreadLocal::new(|| 5u32).unwrap();
+		assert_eq!(second.index(), index);
+		assert_eq!(second.with(|value| *value), 5);
+		std::mem::forget(second);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn access_after_destroy() {
+	let _lock = LOCK.lock().unwrap();
+	let local = Arc::new(DynamicThreadLocal::new(String::new).unwrap());
+	let shared = local.clone();
+	std::thread::spawn(move || {
+		shared.with_mut(|value| value.push_str("test"));
+		unsafe { wintls::dtor::drop_locals() };
+		assert!(shared.try_with(|_| {}).is_err());
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn index_reused_with_other_threads_values() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let _lock = LOCK.lock().unwrap();
+	let first = Arc::new(DynamicThreadLocal::new(|| Tracked(&DROPS)).unwrap());
+	let (to_main, from_thread) = std::sync::mpsc::channel();
+	let (to_thread, from_main) = std::sync::mpsc::channel::<Arc<DynamicThreadLocal<u32>>>();
+	let shared = first.clone();
+	let thread = std::thread::spawn(move || {
+		shared.with(|_| {});
+		drop(shared);
+		to_main.send(()).unwrap();
+
+		// The first local's value is still in the slot, but the second local
+		// doesn't use it.
+		let second = from_main.recv().unwrap();
+		assert_eq!(second.with(|value| *value), 7);
+	});
+	from_thread.recv().unwrap();
+	let index = first.index();
+	drop(Arc::into_inner(first).unwrap());
+
+	let second = Arc::new(DynamicThreadLocal::new(|| 7u32).unwrap());
+	assert_eq!(second.index(), index);
+	to_thread.send(second.clone()).unwrap();
+	thread.join().unwrap();
+	// The other thread's value of the first local is leaked.
+	assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,82 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static COUNTER: u32 = 1;
+	static DEPTH: i32 = -1;
+}
+
+wintls::export_thread_locals! {
+	COUNTER,
+	DEPTH(get = "test_get_depth", set = "test_set_depth"),
+}
+
+// These would normally be declared in C.
+extern "C" {
+	fn wintls_get_COUNTER() -> u32;
+	fn wintls_set_COUNTER(value: u32);
+	fn test_get_depth() -> i32;
+	fn test_set_depth(value: i32);
+}
+
+#[test]
+fn exported_functions() {
+	unsafe {
+		assert_eq!(wintls_get_COUNTER(), 1);
+		wintls_set_COUNTER(2);
+		assert_eq!(COUNTER.get(), 2);
+		assert_eq!(test_get_depth(), -1);
+		test_set_depth(5);
+		assert_eq!(DEPTH.get(), 5);
+
+		std::thread::spawn(|| {
+			assert_eq!(wintls_get_COUNTER(), 1);
+			assert_eq!(test_get_depth(), -1);
+		})
+		.join()
+		.unwrap();
+	}
+}
+
+wintls::static_thread_local! {
+	#[export_name = "test_exported_level"]
+	static LEVEL: u32 = 3;
+}
+
+// C code would declare this as `extern __declspec(thread) uint32_t
+// test_exported_level;`.
+extern "C" {
+	#[link_name = "test_exported_level"]
+	static EXPORTED_LEVEL: u32;
+}
+
+#[test]
+fn exported_symbol() {
+	// The extern symbol refers to the same thread local.
+	let key = unsafe { wintls::static_key!(EXPORTED_LEVEL) };
+	assert_eq!(key, <LEVEL as wintls::StaticKey>::key());
+	LEVEL.set(4);
+	let ptr = unsafe { wintls::raw_internal::static_ptr::<u32>(key) };
+	assert_eq!(unsafe { *ptr }, 4);
+}
+
+// A thread local defined in C would work the same way.
+wintls::extern_thread_local! {
+	/// The same thread local as `LEVEL`.
+	#[link_name = "test_exported_level"]
+	unsafe extern static EXTERN_LEVEL: u32;
+}
+
+#[test]
+fn extern_thread_local() {
+	assert_eq!(
+		<EXTERN_LEVEL as wintls::StaticKey>::key(),
+		<LEVEL as wintls::StaticKey>::key()
+	);
+	std::thread::spawn(|| {
+		assert_eq!(EXTERN_LEVEL.get(), 3);
+		EXTERN_LEVEL.set(7);
+		assert_eq!(LEVEL.get(), 7);
+	})
+	.join()
+	.unwrap();
+}
@@ -0,0 +1,57 @@
+#![feature(asm)]
+
+use wintls_ffi::{c_add_to_counter, c_get_depth, c_negate_level, c_set_depth};
+
+// Defined in `ffi/src/tls.c`.
+wintls::extern_thread_local! {
+	#[link_name = "c_depth"]
+	unsafe extern static DEPTH: i32;
+}
+
+#[test]
+fn defined_in_c() {
+	assert_eq!(DEPTH.get(), 10);
+	DEPTH.set(11);
+	assert_eq!(unsafe { c_get_depth() }, 11);
+	unsafe { c_set_depth(12) };
+	assert_eq!(DEPTH.get(), 12);
+	DEPTH.with_mut(|depth| *depth += 1);
+	assert_eq!(unsafe { c_get_depth() }, 13);
+
+	std::thread::spawn(|| {
+		assert_eq!(DEPTH.get(), 10);
+		unsafe { c_set_depth(20) };
+		assert_eq!(DEPTH.get(), 20);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DEPTH.get(), 13);
+}
+
+wintls::static_thread_local! {
+	static COUNTER: u32 = 1;
+	static LEVEL: i32 = 3;
+}
+
+// Called from `ffi/src/exported.c`.
+wintls::export_thread_locals! {
+	COUNTER,
+	LEVEL(get = "ffi_get_level", set = "ffi_set_level"),
+}
+
+#[test]
+fn called_from_c() {
+	assert_eq!(unsafe { c_add_to_counter(2) }, 3);
+	assert_eq!(COUNTER.get(), 3);
+	LEVEL.set(5);
+	assert_eq!(unsafe { c_negate_level() }, -5);
+	assert_eq!(LEVEL.get(), -5);
+
+	std::thread::spawn(|| {
+		assert_eq!(unsafe { c_add_to_counter(1) }, 2);
+		assert_eq!(unsafe { c_negate_level() }, -3);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNTER.get(), 3);
+}
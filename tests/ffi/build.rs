@@ -0,0 +1,10 @@
+//! Compiles the C side of the `ffi` test.
+
+fn main() {
+	println!("cargo:rerun-if-changed=src/tls.c");
+	println!("cargo:rerun-if-changed=src/exported.c");
+	cc::Build::new()
+		.file("src/tls.c")
+		.file("src/exported.c")
+		.compile("wintls_ffi");
+}
@@ -0,0 +1,17 @@
+#include <stdint.h>
+
+// Exported by the Rust side with `export_thread_locals!`.
+uint32_t wintls_get_COUNTER(void);
+void wintls_set_COUNTER(uint32_t value);
+int32_t ffi_get_level(void);
+void ffi_set_level(int32_t value);
+
+uint32_t c_add_to_counter(uint32_t n) {
+	wintls_set_COUNTER(wintls_get_COUNTER() + n);
+	return wintls_get_COUNTER();
+}
+
+int32_t c_negate_level(void) {
+	ffi_set_level(-ffi_get_level());
+	return ffi_get_level();
+}
@@ -0,0 +1,10 @@
+//! C code for the `ffi` test, compiled by `build.rs`.
+
+extern "C" {
+	pub fn c_get_depth() -> i32;
+	pub fn c_set_depth(value: i32);
+
+	// These call functions exported by the test.
+	pub fn c_add_to_counter(n: u32) -> u32;
+	pub fn c_negate_level() -> i32;
+}
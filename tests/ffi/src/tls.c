@@ -0,0 +1,12 @@
+#include <stdint.h>
+
+// Used by the Rust side with `extern_thread_local!`.
+__declspec(thread) int32_t c_depth = 10;
+
+int32_t c_get_depth(void) {
+	return c_depth;
+}
+
+void c_set_depth(int32_t value) {
+	c_depth = value;
+}
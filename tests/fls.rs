@@ -0,0 +1,122 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use wintls::FlsLocal;
+
+struct Tracked(&'static AtomicU32);
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn with() {
+	let local = FlsLocal::new(|| vec![1]).unwrap();
+	local.with_mut(|list| list.push(2));
+	assert_eq!(local.with(|list| list.clone()), [1, 2]);
+	assert_eq!(local.try_with(|list| list.len()), Ok(2));
+}
+
+#[test]
+fn dropped_on_thread_exit() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let local = Arc::new(FlsLocal::new(|| Tracked(&DROPS)).unwrap());
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			let local = local.clone();
+			std::thread::spawn(move || local.with(|_| {}))
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn thread_spawned_first() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let (send, receive) = mpsc::channel::<Arc<FlsLocal<Tracked>>>();
+	let thread = std::thread::spawn(move || {
+		let local = receive.recv().unwrap();
+		local.with(|_| {});
+	});
+	let local = Arc::new(FlsLocal::new(|| Tracked(&DROPS)).unwrap());
+	send.send(local.clone()).unwrap();
+	thread.join().unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+	drop(local);
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn dropped_with_local() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let local = FlsLocal::new(|| Tracked(&DROPS)).unwrap();
+	local.with(|_| {});
+	drop(local);
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
+
+mod fibers {
+	use core::ffi::c_void;
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		pub fn ConvertThreadToFiber(parameter: *mut c_void) -> *mut c_void;
+		pub fn ConvertFiberToThread() -> i32;
+		pub fn CreateFiber(
+			stack_size: usize,
+			start: extern "system" fn(*mut c_void),
+			parameter: *mut c_void,
+		) -> *mut c_void;
+		pub fn SwitchToFiber(fiber: *mut c_void);
+		pub fn DeleteFiber(fiber: *mut c_void);
+	}
+
+	pub struct Shared {
+		pub local: wintls::FiberLocal<u32>,
+		pub main: *mut c_void,
+		pub seen: Vec<u32>,
+	}
+
+	pub extern "system" fn fiber(parameter: *mut c_void) {
+		let shared = unsafe { &mut *parameter.cast::<Shared>() };
+		shared.seen.push(shared.local.with(|value| *value));
+		shared.local.set(2);
+		unsafe { SwitchToFiber(shared.main) };
+		shared.seen.push(shared.local.with(|value| *value));
+		unsafe { SwitchToFiber(shared.main) };
+		unreachable!();
+	}
+}
+
+#[test]
+fn fibers_have_their_own_values() {
+	use fibers::*;
+
+	std::thread::spawn(|| unsafe {
+		let mut shared = Shared {
+			local: wintls::FiberLocal::new(|| 0).unwrap(),
+			main: ConvertThreadToFiber(core::ptr::null_mut()),
+			seen: Vec::new(),
+		};
+		assert!(!shared.main.is_null());
+		shared.local.set(1);
+
+		let other = CreateFiber(0, fiber, (&mut shared as *mut Shared).cast());
+		assert!(!other.is_null());
+		SwitchToFiber(other);
+		assert_eq!(shared.local.with(|value| *value), 1);
+		SwitchToFiber(other);
+		assert_eq!(shared.local.with(|value| *value), 1);
+		assert_eq!(shared.seen, [0, 2]);
+
+		DeleteFiber(other);
+		ConvertFiberToThread();
+	})
+	.join()
+	.unwrap();
+}
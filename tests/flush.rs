@@ -0,0 +1,79 @@
+#![feature(asm)]
+
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+macro_rules! sink {
+	($local:ident: $ty:ty = $capacity:expr, $flushed:ident) => {
+		static $flushed: Mutex<Vec<Vec<$ty>>> = Mutex::new(Vec::new());
+		wintls::flush_local! {
+			static $local: $ty = $capacity;
+		}
+		$local.set_sink(|values| $flushed.lock().unwrap().push(std::mem::take(values)));
+	};
+}
+
+#[test]
+fn exited_threads_reach_sink() {
+	sink!(EXITED: u32 = 100, FLUSHED);
+	let threads: Vec<_> = (0..4)
+		.map(|i| {
+			std::thread::spawn(move || {
+				EXITED.push(i);
+				EXITED.push(i + 10);
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	let mut flushed = FLUSHED.lock().unwrap().clone();
+	flushed.sort();
+	assert_eq!(flushed, [[0, 10], [1, 11], [2, 12], [3, 13]]);
+}
+
+#[test]
+fn flushed_when_full() {
+	sink!(FULL: u32 = 4, FLUSHED);
+	std::thread::spawn(|| {
+		for i in 0..3 {
+			FULL.push(i);
+		}
+		assert!(FLUSHED.lock().unwrap().is_empty());
+		FULL.push(3);
+		assert_eq!(*FLUSHED.lock().unwrap(), [[0, 1, 2, 3]]);
+		for i in 4..10 {
+			FULL.push(i);
+		}
+		assert_eq!(*FLUSHED.lock().unwrap(), [[0, 1, 2, 3], [4, 5, 6, 7]]);
+		FULL.flush();
+		assert_eq!(FLUSHED.lock().unwrap().len(), 3);
+	})
+	.join()
+	.unwrap();
+	// There was nothing left to flush at exit.
+	assert_eq!(
+		*FLUSHED.lock().unwrap(),
+		[vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]
+	);
+}
+
+#[test]
+fn flush_all_running_thread() {
+	sink!(RUNNING: &'static str = 100, FLUSHED);
+	let (pushed, wait_pushed) = mpsc::channel();
+	let (done, wait_done) = mpsc::channel::<()>();
+	let thread = std::thread::spawn(move || {
+		RUNNING.push("a");
+		RUNNING.push("b");
+		pushed.send(()).unwrap();
+		wait_done.recv().unwrap();
+	});
+	wait_pushed.recv().unwrap();
+	RUNNING.flush_all();
+	assert_eq!(*FLUSHED.lock().unwrap(), [["a", "b"]]);
+	done.send(()).unwrap();
+	thread.join().unwrap();
+	// The events aren't flushed twice.
+	assert_eq!(FLUSHED.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,64 @@
+#![feature(asm)]
+
+// Both functions use the same name for their local.
+fn next_id() -> u32 {
+	wintls::static_thread_local! {
+		static COUNTER: u32 = 0;
+	}
+	COUNTER.update(|n| n + 1) - 1
+}
+
+fn next_even() -> u32 {
+	wintls::static_thread_local! {
+		static COUNTER: u32 = 0;
+	}
+	COUNTER.update(|n| n + 2) - 2
+}
+
+// Returns the deepest level of recursion reached.
+fn recurse(n: u32) -> u32 {
+	wintls::static_thread_local! {
+		static DEPTH: u32 = 0;
+	}
+	let depth = DEPTH.update(|d| d + 1);
+	let deepest = if n == 0 { depth } else { recurse(n - 1) };
+	assert_eq!(DEPTH.get(), depth);
+	DEPTH.set(depth - 1);
+	deepest
+}
+
+#[test]
+fn separate_functions() {
+	assert_eq!(next_id(), 0);
+	assert_eq!(next_id(), 1);
+	assert_eq!(next_even(), 0);
+	assert_eq!(next_even(), 2);
+	assert_eq!(next_id(), 2);
+}
+
+#[test]
+fn multiple_threads() {
+	assert_eq!(next_id(), 0);
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			std::thread::spawn(|| {
+				for i in 0..10 {
+					assert_eq!(next_id(), i);
+				}
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(next_id(), 1);
+}
+
+#[test]
+fn recursion() {
+	assert_eq!(recurse(9), 10);
+	std::thread::spawn(|| assert_eq!(recurse(4), 5))
+		.join()
+		.unwrap();
+	assert_eq!(recurse(0), 1);
+}
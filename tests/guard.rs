@@ -0,0 +1,29 @@
+#![feature(asm)]
+
+use wintls::TlsGuard;
+
+wintls::unsafe_local! {
+	static COUNT: u32 = 0;
+}
+
+#[test]
+fn unchanged() {
+	let guard = COUNT.guard();
+	let ptr = COUNT.as_ptr();
+	unsafe { *ptr += 1 };
+	assert!(guard.is_current());
+	guard.check();
+	assert_eq!(COUNT.get_copy(), 1);
+}
+
+#[test]
+fn per_thread() {
+	let guard = TlsGuard::new();
+	std::thread::spawn(|| {
+		let guard = TlsGuard::new();
+		guard.check();
+	})
+	.join()
+	.unwrap();
+	guard.check();
+}
@@ -0,0 +1,48 @@
+#![feature(asm)]
+
+//! Checks that a `TlsGuard` notices when loading a DLL with static thread
+//! locals reallocates the TLS array. This is separate from the other guard
+//! tests because loading the DLL affects every thread. The DLL is built from
+//! `unload/`.
+
+mod common;
+
+use std::ffi::c_void;
+use std::os::windows::ffi::OsStrExt;
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn LoadLibraryW(name: *const u16) -> *mut c_void;
+	fn FreeLibrary(module: *mut c_void) -> i32;
+}
+
+wintls::unsafe_local! {
+	static COUNT: u32 = 0;
+}
+
+#[test]
+fn dll_loaded() {
+	let path = common::build("unload").join("plugin.dll");
+	let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+
+	let guard = COUNT.guard();
+	let ptr = COUNT.as_ptr();
+	unsafe { *ptr += 1 };
+	assert!(guard.is_current());
+
+	let dll = unsafe { LoadLibraryW(path.as_ptr()) };
+	assert!(!dll.is_null(), "plugin.dll wasn't found");
+	assert!(!guard.is_current());
+	let error = std::panic::catch_unwind(|| guard.check()).unwrap_err();
+	assert_eq!(
+		error.downcast_ref::<&str>(),
+		Some(&"TLS array was reallocated while a pointer was held")
+	);
+	// Dropping the guard would check it again in debug builds.
+	std::mem::forget(guard);
+
+	// A new guard is fine.
+	COUNT.guard().check();
+	assert_eq!(COUNT.get_copy(), 1);
+	unsafe { FreeLibrary(dll) };
+}
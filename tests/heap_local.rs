@@ -0,0 +1,83 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static ALLOCS: AtomicU32 = AtomicU32::new(0);
+static DROPS: AtomicU32 = AtomicU32::new(0);
+
+struct Tracked(Vec<u32>);
+impl Tracked {
+	fn new() -> Self {
+		ALLOCS.fetch_add(1, Ordering::SeqCst);
+		Self(Vec::new())
+	}
+}
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		DROPS.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+wintls::heap_local! {
+	static TRACKED: Tracked = Tracked::new;
+	static LIST: Vec<u32> = || vec![1, 2, 3];
+	static DESTROYED: String = || String::from("destroyed");
+	static BORROWED: u32 = || 0;
+}
+
+#[test]
+fn no_leaks() {
+	let threads: Vec<_> = (0..4)
+		.map(|i| {
+			std::thread::spawn(move || {
+				assert!(!TRACKED.is_initialized());
+				TRACKED.with_mut(|tracked| tracked.0.push(i));
+				TRACKED.with(|tracked| assert_eq!(tracked.0, [i]));
+				assert!(TRACKED.is_initialized());
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(ALLOCS.load(Ordering::SeqCst), 4);
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+
+	// A thread that doesn't use the local doesn't allocate it.
+	std::thread::spawn(|| assert!(!TRACKED.is_initialized()))
+		.join()
+		.unwrap();
+	assert_eq!(ALLOCS.load(Ordering::SeqCst), 4);
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn independent_threads() {
+	LIST.with_mut(|list| list.push(4));
+	std::thread::spawn(|| {
+		assert_eq!(LIST.with(|list| list.clone()), [1, 2, 3]);
+		LIST.with_mut(|list| list.clear());
+	})
+	.join()
+	.unwrap();
+	assert_eq!(LIST.with(|list| list.clone()), [1, 2, 3, 4]);
+}
+
+#[test]
+fn access_after_destroy() {
+	std::thread::spawn(|| {
+		assert_eq!(DESTROYED.try_with(|value| value.len()), Ok(9));
+		unsafe { wintls::dtor::drop_locals() };
+		assert!(!DESTROYED.is_initialized());
+		assert!(DESTROYED.try_with(|_| {}).is_err());
+		assert!(std::panic::catch_unwind(|| DESTROYED.with(|_| {})).is_err());
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+#[should_panic(expected = "thread local `BORROWED` is already borrowed")]
+fn borrows_are_checked() {
+	BORROWED.with(|_| BORROWED.with_mut(|_| {}));
+}
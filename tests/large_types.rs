@@ -0,0 +1,123 @@
+#![feature(asm)]
+
+// Types that are larger than a register or have a large alignment.
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, align(16))]
+struct Aligned16([u32; 4]);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Large {
+	id: u64,
+	name: [u8; 32],
+	samples: [f64; 64],
+}
+
+wintls::static_thread_local! {
+	static BYTES: [u8; 64] = [0xab; 64];
+	static ZEROS: [u8; 65536] = [0; 65536];
+	static LARGE: Large = Large { id: 1, name: [b'a'; 32], samples: [0.5; 64] };
+}
+
+#[cfg(target_arch = "x86_64")]
+wintls::static_thread_local! {
+	static WIDE: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+	static ALIGNED: Aligned16 = Aligned16([1, 2, 3, 4]);
+}
+
+// On x86 the heap only guarantees an alignment of 8, so 16 byte aligned types
+// are rejected at compile time. The bytes can still be stored.
+#[cfg(target_arch = "x86")]
+wintls::static_thread_local! {
+	static WIDE_BYTES: [u8; 16] = 7_u128.to_ne_bytes();
+}
+
+#[cfg(target_arch = "x86_64")]
+fn is_aligned<T>(ptr: *mut T) -> bool {
+	ptr as usize & (core::mem::align_of::<T>() - 1) == 0
+}
+
+#[test]
+fn bytes_64() {
+	assert_eq!(BYTES.get(), [0xab; 64]);
+	BYTES.set([1; 64]);
+	std::thread::spawn(|| {
+		assert_eq!(BYTES.get(), [0xab; 64]);
+		BYTES.set([2; 64]);
+		assert_eq!(BYTES.get(), [2; 64]);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(BYTES.get(), [1; 64]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn u128() {
+	let initial = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+	assert!(is_aligned(WIDE.get_ptr()));
+	assert_eq!(WIDE.get(), initial);
+	WIDE.set(u128::MAX);
+	std::thread::spawn(move || {
+		assert!(is_aligned(WIDE.get_ptr()));
+		assert_eq!(WIDE.get(), initial);
+		WIDE.set(1 << 100);
+		assert_eq!(WIDE.get(), 1 << 100);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(WIDE.get(), u128::MAX);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn align_16() {
+	assert!(is_aligned(ALIGNED.get_ptr()));
+	assert_eq!(ALIGNED.get(), Aligned16([1, 2, 3, 4]));
+	ALIGNED.set(Aligned16([5; 4]));
+	std::thread::spawn(|| {
+		assert!(is_aligned(ALIGNED.get_ptr()));
+		assert_eq!(ALIGNED.get(), Aligned16([1, 2, 3, 4]));
+		ALIGNED.with_mut(|a| a.0[3] = 9);
+		assert_eq!(ALIGNED.get(), Aligned16([1, 2, 3, 9]));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(ALIGNED.get(), Aligned16([5; 4]));
+}
+
+#[cfg(target_arch = "x86")]
+#[test]
+fn u128_as_bytes() {
+	assert_eq!(u128::from_ne_bytes(WIDE_BYTES.get()), 7);
+	WIDE_BYTES.set(u128::MAX.to_ne_bytes());
+	assert_eq!(u128::from_ne_bytes(WIDE_BYTES.get()), u128::MAX);
+}
+
+#[test]
+fn zeroed_64k() {
+	ZEROS.with_mut(|zeros| zeros.fill(1));
+	std::thread::spawn(|| {
+		ZEROS.with(|zeros| assert!(zeros.iter().all(|&b| b == 0)));
+	})
+	.join()
+	.unwrap();
+	ZEROS.with(|zeros| assert!(zeros.iter().all(|&b| b == 1)));
+}
+
+#[test]
+fn large_copy_struct() {
+	let initial = Large {
+		id: 1,
+		name: [b'a'; 32],
+		samples: [0.5; 64],
+	};
+	assert_eq!(LARGE.get(), initial);
+	LARGE.set(Large { id: 2, ..initial });
+	std::thread::spawn(move || assert_eq!(LARGE.get(), initial))
+		.join()
+		.unwrap();
+	assert_eq!(LARGE.get().id, 2);
+	assert_eq!(LARGE.get().samples, [0.5; 64]);
+}
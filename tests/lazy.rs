@@ -0,0 +1,79 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static INIT_COUNT: u32 = 0;
+	lazy static SEED: u64 = {
+		// The initializer can use other locals.
+		INIT_COUNT.set(INIT_COUNT.get() + 1);
+		seed_from_os()
+	};
+	lazy static SET_FIRST: u32 = {
+		INIT_COUNT.set(INIT_COUNT.get() + 1);
+		1
+	};
+	lazy static RECURSIVE: u32 = RECURSIVE.get() + 1;
+	lazy static FALLIBLE: u32 = if SHOULD_PANIC.get() { panic!("failed") } else { 7 };
+	static SHOULD_PANIC: bool = true;
+	pub lazy static ARRAY: [u8; 4] = [INIT_COUNT.get() as u8; 4];
+}
+
+fn seed_from_os() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.unwrap()
+		.as_nanos() as u64
+		| 1
+}
+
+#[test]
+fn runs_once_per_thread() {
+	assert!(!SEED.is_initialized());
+	assert_eq!(INIT_COUNT.get(), 0);
+	let seed = SEED.get();
+	assert!(SEED.is_initialized());
+	assert_eq!(INIT_COUNT.get(), 1);
+	assert_eq!(SEED.get(), seed);
+	SEED.set(5);
+	assert_eq!(SEED.get(), 5);
+	assert_eq!(INIT_COUNT.get(), 1);
+
+	std::thread::spawn(|| {
+		assert!(!SEED.is_initialized());
+		assert_ne!(SEED.get(), 5);
+		SEED.get();
+		assert_eq!(INIT_COUNT.get(), 1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(INIT_COUNT.get(), 1);
+}
+
+#[test]
+fn set_initializes() {
+	SET_FIRST.set(2);
+	assert_eq!(INIT_COUNT.get(), 1);
+	assert_eq!(SET_FIRST.get(), 2);
+	SET_FIRST.with_mut(|value| *value += 1);
+	assert_eq!(SET_FIRST.with(|value| *value), 3);
+	assert_eq!(INIT_COUNT.get(), 1);
+}
+
+#[test]
+fn initializer_reads_other_locals() {
+	INIT_COUNT.set(9);
+	assert_eq!(ARRAY.get(), [9; 4]);
+}
+
+#[test]
+#[should_panic(expected = "thread local `RECURSIVE` was accessed while it was being initialized")]
+fn reentrant_initialization() {
+	RECURSIVE.get();
+}
+
+#[test]
+fn panicking_initializer() {
+	assert!(std::panic::catch_unwind(|| FALLIBLE.get()).is_err());
+	assert!(!FALLIBLE.is_initialized());
+	SHOULD_PANIC.set(false);
+	assert_eq!(FALLIBLE.get(), 7);
+}
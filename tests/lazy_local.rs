@@ -0,0 +1,80 @@
+#![feature(asm)]
+
+use std::panic;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+wintls::static_thread_local! {
+	static INIT_COUNT: u32 = 0;
+	static SHOULD_PANIC: bool = true;
+}
+
+wintls::lazy_local! {
+	static NAME: String = || {
+		INIT_COUNT.set(INIT_COUNT.get() + 1);
+		String::from("lazy")
+	};
+	static FALLIBLE: u32 = || if SHOULD_PANIC.get() { panic!("failed") } else { 7 };
+	static RECURSIVE: u32 = || RECURSIVE.with(|value| *value + 1);
+	static TRACKED: Tracked = || Tracked;
+}
+
+static DROPS: AtomicU32 = AtomicU32::new(0);
+
+struct Tracked;
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		DROPS.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn runs_once_per_thread() {
+	assert!(!NAME.is_initialized());
+	assert_eq!(NAME.with(|name| name.clone()), "lazy");
+	assert!(NAME.is_initialized());
+	assert_eq!(NAME.with(|name| name.len()), 4);
+	assert_eq!(INIT_COUNT.get(), 1);
+
+	std::thread::spawn(|| {
+		assert!(!NAME.is_initialized());
+		NAME.with(|_| {});
+		NAME.with(|_| {});
+		assert_eq!(INIT_COUNT.get(), 1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(INIT_COUNT.get(), 1);
+}
+
+#[test]
+fn retry_after_panic() {
+	let result = panic::catch_unwind(|| FALLIBLE.with(|value| *value));
+	assert!(result.is_err());
+	assert!(!FALLIBLE.is_initialized());
+
+	SHOULD_PANIC.set(false);
+	assert_eq!(FALLIBLE.with(|value| *value), 7);
+}
+
+#[test]
+#[should_panic(expected = "thread local `RECURSIVE` was accessed while it was being initialized")]
+fn recursive_init_panics() {
+	RECURSIVE.with(|_| {});
+}
+
+#[test]
+fn register_drop() {
+	std::thread::spawn(|| {
+		TRACKED.register_drop();
+		TRACKED.with(|_| {});
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+	// Nothing is dropped if the value was never initialized.
+	std::thread::spawn(|| TRACKED.register_drop())
+		.join()
+		.unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,141 @@
+#![feature(asm)]
+
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static DROPS: AtomicU32 = AtomicU32::new(0);
+
+struct Tracked(u32);
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		DROPS.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+wintls::local! {
+	static TRACKED: Tracked = Tracked(1);
+	static BUF: RefCell<String> = RefCell::new(String::new());
+	static COUNT: Cell<u32> = Cell::new(0);
+	static DESTROYED: String = String::from("destroyed");
+	static RECURSIVE: u32 = RECURSIVE.with(|value| *value);
+	static CELL: Cell<u32> = Cell::new(1);
+	static REF_CELL: RefCell<Vec<u32>> = RefCell::new(vec![1]);
+	static OBSERVER: Observer = Observer;
+	static OBSERVED: u32 = 5;
+}
+
+// Records whether `OBSERVED` could be used when this is dropped.
+static OBSERVED_RESULT: AtomicU32 = AtomicU32::new(0);
+struct Observer;
+impl Drop for Observer {
+	fn drop(&mut self) {
+		let result = match OBSERVED.try_with(|value| *value) {
+			Ok(_) => 1,
+			Err(error) => {
+				assert_eq!(
+					error.to_string(),
+					"thread local `OBSERVED` was accessed during or after its destruction"
+				);
+				2
+			}
+		};
+		OBSERVED_RESULT.store(result, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn drops_on_thread_exit() {
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			std::thread::spawn(|| {
+				for _ in 0..3 {
+					TRACKED.with(|tracked| assert_eq!(tracked.0, 1));
+				}
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+
+	// Nothing is dropped if the value was never used.
+	std::thread::spawn(|| {}).join().unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn independent_threads() {
+	BUF.with(|buf| buf.borrow_mut().push_str("test"));
+	COUNT.with(|count| count.set(1));
+	std::thread::spawn(|| {
+		assert!(BUF.with(|buf| buf.borrow().is_empty()));
+		BUF.with(|buf| buf.borrow_mut().push_str("spawned"));
+		assert_eq!(COUNT.with(Cell::get), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(BUF.with(|buf| buf.borrow().clone()), "test");
+	assert_eq!(COUNT.with(Cell::get), 1);
+}
+
+#[test]
+fn access_after_destroy() {
+	std::thread::spawn(|| {
+		DESTROYED.with(|_| {});
+		unsafe { wintls::dtor::drop_locals() };
+		assert!(DESTROYED.try_with(|_| {}).is_err());
+		let result = std::panic::catch_unwind(|| DESTROYED.with(|_| {}));
+		let message = result.unwrap_err().downcast::<String>().unwrap();
+		assert_eq!(
+			*message,
+			"thread local `DESTROYED` was accessed during or after its destruction"
+		);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+#[should_panic(expected = "thread local `RECURSIVE` was accessed while it was being initialized")]
+fn recursive_init_panics() {
+	RECURSIVE.with(|_| {});
+}
+
+#[test]
+fn cell_methods() {
+	assert_eq!(CELL.get(), 1);
+	CELL.set(2);
+	assert_eq!(CELL.replace(3), 2);
+	assert_eq!(CELL.take(), 3);
+	assert_eq!(CELL.get(), 0);
+	assert_eq!(generic_over_key(&CELL), 1);
+}
+
+#[test]
+fn ref_cell_methods() {
+	REF_CELL.with_borrow_mut(|list| list.push(2));
+	assert_eq!(REF_CELL.with_borrow(|list| list.clone()), [1, 2]);
+	REF_CELL.set(vec![3]);
+	assert_eq!(REF_CELL.replace(vec![4]), [3]);
+	assert_eq!(REF_CELL.take(), [4]);
+	assert!(REF_CELL.with_borrow(Vec::is_empty));
+}
+
+#[test]
+fn access_from_another_destructor() {
+	std::thread::spawn(|| {
+		// Destructors run last to first, so `OBSERVED` is dropped before
+		// `OBSERVER`.
+		OBSERVER.with(|_| {});
+		assert_eq!(OBSERVED.try_with(|value| *value), Ok(5));
+	})
+	.join()
+	.unwrap();
+	assert_eq!(OBSERVED_RESULT.load(Ordering::SeqCst), 2);
+}
+
+// Code written for `std::thread::LocalKey` works unchanged.
+fn generic_over_key(key: &'static wintls::LocalKey<Cell<u32>>) -> u32 {
+	key.with(|cell| cell.get() + 1)
+}
@@ -0,0 +1,67 @@
+#![feature(asm)]
+
+//! Checks that the main thread's destructors are run once, however the process
+//! exits. Each way of exiting is run in a child process.
+
+use std::io::Write;
+use std::process::Command;
+
+const MODE: &str = "WINTLS_EXIT_MODE";
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn ExitProcess(code: u32) -> !;
+}
+
+extern "C" {
+	fn atexit(f: extern "C" fn()) -> i32;
+}
+
+fn print(message: &str) {
+	let mut stdout = std::io::stdout();
+	let _ = writeln!(stdout, "{message}");
+	let _ = stdout.flush();
+}
+
+fn main() {
+	match std::env::var(MODE) {
+		Ok(mode) => child(&mode),
+		Err(_) => parent(),
+	}
+}
+
+fn child(mode: &str) {
+	extern "C" fn at_exit() {
+		print("atexit");
+	}
+	wintls::dtor::register_dtor(|| {
+		print("destructor");
+		// Still run, by the `DLL_PROCESS_DETACH` callback if need be.
+		wintls::dtor::register_dtor(|| print("nested destructor"));
+	});
+	unsafe { atexit(at_exit) };
+	match mode {
+		"return" => {}
+		"exit" => std::process::exit(0),
+		"ExitProcess" => unsafe { ExitProcess(0) },
+		_ => unreachable!("unknown mode {mode}"),
+	}
+}
+
+fn parent() {
+	let exe = std::env::current_exe().unwrap();
+	let tests: [(&str, &[&str]); 3] = [
+		// Handlers registered by `main` run before the destructors.
+		("return", &["atexit", "destructor", "nested destructor"]),
+		("exit", &["destructor", "nested destructor"]),
+		("ExitProcess", &["destructor", "nested destructor"]),
+	];
+	for (mode, expected) in tests {
+		let output = Command::new(&exe).env(MODE, mode).output().unwrap();
+		assert!(output.status.success(), "{mode}: {}", output.status);
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		let lines: Vec<&str> = stdout.lines().collect();
+		assert_eq!(lines, expected, "{mode}");
+		println!("test {mode} ... ok");
+	}
+}
@@ -0,0 +1,31 @@
+#![feature(asm)]
+
+wintls::module_local! {
+	static COUNTER: u32 = 0;
+	pub static PAIR: (u8, u16) = (1, 2);
+}
+
+#[test]
+fn get_set() {
+	assert_eq!(COUNTER.get(), 0);
+	COUNTER.set(5);
+	assert_eq!(COUNTER.get(), 5);
+	assert_eq!(COUNTER.with(|value| *value), 5);
+	assert_eq!(unsafe { *COUNTER.get_ptr() }, 5);
+	std::thread::spawn(|| assert_eq!(COUNTER.get(), 0))
+		.join()
+		.unwrap();
+}
+
+#[test]
+fn handle_is_copy_send_sync() {
+	fn assert_traits<T: Copy + Send + Sync>(_: T) {}
+	assert_traits(PAIR);
+
+	let pair = PAIR;
+	pair.set((3, 4));
+	assert_eq!(PAIR.get(), (3, 4));
+	std::thread::spawn(move || assert_eq!(pair.get(), (1, 2)))
+		.join()
+		.unwrap();
+}
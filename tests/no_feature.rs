@@ -0,0 +1,12 @@
+// The macros must work without `#![feature(asm)]` in the user's crate.
+
+wintls::static_thread_local! {
+	static VALUE: u32 = 1;
+}
+
+#[test]
+fn without_asm_feature() {
+	assert_eq!(VALUE.get(), 1);
+	VALUE.set(2);
+	assert_eq!(VALUE.get(), 2);
+}
@@ -0,0 +1,5 @@
+fn main() {
+	for arg in ["/NODEFAULTLIB", "/ENTRY:start", "/SUBSYSTEM:CONSOLE"] {
+		println!("cargo:rustc-link-arg-bins={arg}");
+	}
+}
@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+use wintls::ops::TlsInt;
+
+fn double<T: TlsInt>(value: T) -> T {
+	value + value
+}
+
+#[no_mangle]
+extern "system" fn start() -> u32 {
+	double(21_u32) - 42
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+	loop {}
+}
@@ -0,0 +1,14 @@
+//! Checks that this crate builds in a `no_std` crate with its default features
+//! turned off.
+
+mod common;
+
+#[test]
+fn builds() {
+	let output = common::cargo("check", "nostd", "nostd").output().unwrap();
+	assert!(
+		output.status.success(),
+		"{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+}
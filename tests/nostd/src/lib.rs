@@ -0,0 +1,34 @@
+#![no_std]
+#![feature(asm)]
+
+use core::cell::Cell;
+use wintls::dtor;
+
+wintls::static_thread_local! {
+	static COUNT: u32 = 0;
+}
+
+wintls::local! {
+	static DROPS: Cell<u32> = Cell::new(0);
+}
+
+pub fn count() -> u32 {
+	let count = COUNT.get() + 1;
+	COUNT.set(count);
+	count
+}
+
+pub fn drops() -> u32 {
+	DROPS.get()
+}
+
+fn on_exit() {
+	let _ = DROPS.try_with(|drops| drops.set(drops.get() + 1));
+}
+
+/// Returns `false` if the destructor couldn't be registered.
+pub fn register() -> bool {
+	dtor::try_register_dtor(on_exit).is_ok()
+}
+
+pub const CAPACITY: usize = dtor::INLINE_CAPACITY;
@@ -0,0 +1,24 @@
+//! Checks the error given when the compiler doesn't support the unstable
+//! features this crate uses.
+
+mod common;
+
+#[test]
+fn compile_error() {
+	// This is the `cfg` the build script sets if the compiler isn't nightly.
+	let output = common::cargo("check", "no_tls", "no_tls-not-nightly")
+		.env("RUSTFLAGS", "--cfg wintls_not_nightly")
+		.env_remove("CARGO_ENCODED_RUSTFLAGS")
+		.output()
+		.unwrap();
+	assert!(!output.status.success());
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert!(
+		stderr.contains(
+			"error: wintls requires a nightly compiler because it uses inline assembly (the \
+			 unstable `asm` feature). Build with `cargo +nightly` or add a \
+			 `rust-toolchain.toml` with `channel = \"nightly\"`."
+		),
+		"{stderr}"
+	);
+}
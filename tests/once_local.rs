@@ -0,0 +1,87 @@
+#![feature(asm)]
+
+use std::panic;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+wintls::once_local! {
+	static STARTED;
+	static OUTER;
+	static INNER;
+	static RECURSIVE;
+	static FALLIBLE;
+	static IN_DTOR;
+}
+
+#[test]
+fn once_per_thread() {
+	static CALLS: AtomicU32 = AtomicU32::new(0);
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			std::thread::spawn(|| {
+				assert!(!STARTED.is_completed());
+				for _ in 0..3 {
+					STARTED.call_once(|| {
+						CALLS.fetch_add(1, Ordering::SeqCst);
+					});
+				}
+				assert!(STARTED.is_completed());
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(CALLS.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn nested() {
+	let mut calls = 0;
+	OUTER.call_once(|| {
+		INNER.call_once(|| calls += 1);
+		assert!(!OUTER.is_completed());
+	});
+	INNER.call_once(|| calls += 1);
+	assert_eq!(calls, 1);
+	assert!(OUTER.is_completed());
+}
+
+#[test]
+#[should_panic(expected = "`RECURSIVE.call_once` was called from its own closure")]
+fn recursive_panics() {
+	RECURSIVE.call_once(|| RECURSIVE.call_once(|| {}));
+}
+
+#[test]
+fn poisoned() {
+	assert!(panic::catch_unwind(|| FALLIBLE.call_once(|| panic!("failed"))).is_err());
+	assert!(!FALLIBLE.is_completed());
+	let result = panic::catch_unwind(|| FALLIBLE.call_once(|| {}));
+	let message = result.unwrap_err().downcast::<String>().unwrap();
+	assert_eq!(
+		*message,
+		"`FALLIBLE` was poisoned by a panic in `call_once`"
+	);
+
+	// Other threads aren't affected.
+	std::thread::spawn(|| FALLIBLE.call_once(|| {}))
+		.join()
+		.unwrap();
+}
+
+#[test]
+fn in_destructor() {
+	static CALLS: AtomicU32 = AtomicU32::new(0);
+	std::thread::spawn(|| {
+		for _ in 0..2 {
+			wintls::dtor::register_dtor(|| {
+				IN_DTOR.call_once(|| {
+					CALLS.fetch_add(1, Ordering::SeqCst);
+				})
+			});
+		}
+	})
+	.join()
+	.unwrap();
+	assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,98 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static COUNT: u32 = 0;
+	static DEPTH: i8 = 0;
+}
+
+#[test]
+fn add_sub_increment() {
+	assert_eq!(COUNT.increment(), 1);
+	assert_eq!(COUNT.add(10), 11);
+	assert_eq!(COUNT.sub(1), 10);
+	assert_eq!(COUNT.get(), 10);
+
+	assert_eq!(DEPTH.sub(3), -3);
+	assert_eq!(DEPTH.increment(), -2);
+
+	std::thread::spawn(|| {
+		assert_eq!(COUNT.increment(), 1);
+		assert_eq!(DEPTH.get(), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(COUNT.get(), 10);
+}
+
+#[test]
+fn wrapping() {
+	std::thread::spawn(|| {
+		COUNT.set(u32::MAX);
+		assert_eq!(COUNT.wrapping_add(1), 0);
+		assert_eq!(COUNT.wrapping_sub(1), u32::MAX);
+
+		DEPTH.set(i8::MIN);
+		assert_eq!(DEPTH.wrapping_sub(1), i8::MAX);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn saturating() {
+	std::thread::spawn(|| {
+		assert_eq!(COUNT.saturating_sub(1), 0);
+		COUNT.set(u32::MAX - 1);
+		assert_eq!(COUNT.saturating_add(5), u32::MAX);
+
+		DEPTH.set(i8::MAX);
+		assert_eq!(DEPTH.saturating_add(1), i8::MAX);
+	})
+	.join()
+	.unwrap();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn add_overflow() {
+	COUNT.set(u32::MAX);
+	COUNT.add(1);
+}
+
+wintls::static_thread_local! {
+	static IN_ALLOCATOR: bool = false;
+	static FLAGS: u8 = 0;
+}
+
+#[test]
+fn toggle() {
+	assert!(IN_ALLOCATOR.toggle());
+	std::thread::spawn(|| {
+		assert!(!IN_ALLOCATOR.get());
+		assert!(IN_ALLOCATOR.toggle());
+		assert!(!IN_ALLOCATOR.toggle());
+	})
+	.join()
+	.unwrap();
+	assert!(IN_ALLOCATOR.get());
+}
+
+#[test]
+fn bits() {
+	const LOGGING_SUPPRESSED: u8 = 0b01;
+	const TRACING: u8 = 0b10;
+
+	assert_eq!(FLAGS.set_bits(LOGGING_SUPPRESSED), 0b01);
+	assert_eq!(FLAGS.set_bits(TRACING), 0b11);
+	assert!(FLAGS.test_bits(LOGGING_SUPPRESSED | TRACING));
+
+	assert_eq!(FLAGS.clear_bits(LOGGING_SUPPRESSED), 0b10);
+	assert!(!FLAGS.test_bits(LOGGING_SUPPRESSED));
+	assert!(!FLAGS.test_bits(LOGGING_SUPPRESSED | TRACING));
+	assert!(FLAGS.test_bits(TRACING));
+
+	std::thread::spawn(|| assert!(!FLAGS.test_bits(TRACING)))
+		.join()
+		.unwrap();
+}
@@ -0,0 +1,38 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+wintls::static_thread_local! {
+	static HANDLE: Option<u32> = None;
+	static SEED: u32 = 100;
+}
+
+#[test]
+fn get_or_set_with() {
+	static CALLS: AtomicUsize = AtomicUsize::new(0);
+	let init = || {
+		CALLS.fetch_add(1, Ordering::SeqCst);
+		// Initializing from another local is fine.
+		SEED.get() + 1
+	};
+
+	std::thread::spawn(move || {
+		assert!(!HANDLE.is_set());
+		SEED.set(1);
+		assert_eq!(HANDLE.get_or_set_with(init), 2);
+		assert_eq!(HANDLE.get_or_set_with(init), 2);
+		assert!(HANDLE.is_set());
+		assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+		HANDLE.clear();
+		assert!(!HANDLE.is_set());
+		assert_eq!(HANDLE.get_or_set_with(init), 2);
+		assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+	})
+	.join()
+	.unwrap();
+
+	// The main thread has its own value.
+	assert!(!HANDLE.is_set());
+	assert_eq!(HANDLE.get_or_set_with(init), 101);
+}
@@ -0,0 +1,56 @@
+#![feature(asm)]
+
+//! Checks that a DLL that registers a destructor with
+//! `wintls::dtor::register_dtor_pinned` stays loaded until it's run. The DLL
+//! is built from `unload/`.
+
+mod common;
+
+use std::ffi::{c_char, c_void, CStr};
+use std::os::windows::ffi::OsStrExt;
+use std::sync::mpsc;
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn LoadLibraryW(name: *const u16) -> *mut c_void;
+	fn GetModuleHandleW(name: *const u16) -> *mut c_void;
+	fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+	fn FreeLibrary(module: *mut c_void) -> i32;
+}
+
+unsafe fn get(dll: *mut c_void, name: &CStr) -> *mut c_void {
+	let f = GetProcAddress(dll, name.as_ptr());
+	assert!(!f.is_null());
+	f
+}
+
+#[test]
+fn pinned() {
+	let path = common::build("unload").join("plugin.dll");
+	let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+	unsafe {
+		let dll = LoadLibraryW(path.as_ptr());
+		assert!(!dll.is_null(), "plugin.dll wasn't found");
+		let register: extern "C" fn() = std::mem::transmute(get(dll, c"plugin_register_pinned"));
+		let drops: extern "C" fn() -> u32 = std::mem::transmute(get(dll, c"plugin_pinned_drops"));
+
+		let (registered, wait_registered) = mpsc::channel();
+		let (unloaded, wait_unloaded) = mpsc::channel();
+		let worker = std::thread::spawn(move || {
+			register();
+			registered.send(()).unwrap();
+			wait_unloaded.recv().unwrap();
+		});
+		wait_registered.recv().unwrap();
+
+		// `FreeLibrary` succeeds but the DLL stays loaded.
+		assert_ne!(FreeLibrary(dll), 0);
+		assert_eq!(GetModuleHandleW(path.as_ptr()), dll, "the DLL wasn't pinned");
+		unloaded.send(()).unwrap();
+		worker.join().unwrap();
+
+		// The destructor was run when the worker exited.
+		assert_eq!(drops(), 1);
+		assert_eq!(GetModuleHandleW(path.as_ptr()), dll);
+	}
+}
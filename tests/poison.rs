@@ -0,0 +1,81 @@
+#![feature(asm)]
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+// What `OBSERVER`'s destructor saw.
+static SEEN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// Where the panic hook says the last panic happened.
+static PANIC_LOCATION: Mutex<Option<(String, u32)>> = Mutex::new(None);
+
+struct Observer;
+impl Drop for Observer {
+	fn drop(&mut self) {
+		let mut seen = vec![
+			LOCAL.try_with(|_| ()).unwrap_err().to_string(),
+			LAZY.try_with(|_| ()).unwrap_err().to_string(),
+			HEAP.try_with(|_| ()).unwrap_err().to_string(),
+			REGISTERED.try_with(|_| ()).unwrap_err().to_string(),
+		];
+
+		let line = line!() + 1;
+		let result = panic::catch_unwind(AssertUnwindSafe(|| LAZY.with(|_| ())));
+		seen.push(*result.unwrap_err().downcast::<String>().unwrap());
+		// Asserting here would abort, so the locations are checked later.
+		let (file, panic_line) = PANIC_LOCATION.lock().unwrap().take().unwrap();
+		seen.push(format!("{file}:{panic_line}"));
+		seen.push(format!("{}:{line}", file!()));
+		*SEEN.lock().unwrap() = seen;
+	}
+}
+
+wintls::local! {
+	static OBSERVER: Observer = Observer;
+	static LOCAL: String = String::from("local");
+}
+wintls::lazy_local! {
+	static LAZY: String = || String::from("lazy");
+}
+wintls::heap_local! {
+	static HEAP: String = || String::from("heap");
+}
+wintls::registered_local! {
+	static REGISTERED: String = String::from("registered");
+}
+
+#[test]
+fn destroyed_before_later_destructor() {
+	panic::set_hook(Box::new(|info| {
+		let location = info.location().unwrap();
+		*PANIC_LOCATION.lock().unwrap() = Some((location.file().to_string(), location.line()));
+	}));
+	std::thread::spawn(|| {
+		// Destructors run last to first. `OBSERVER` is registered first, so its
+		// destructor runs after the others have been dropped.
+		OBSERVER.with(|_| ());
+		LOCAL.with(|_| ());
+		LAZY.register_drop();
+		LAZY.with(|_| ());
+		HEAP.with(|_| ());
+		REGISTERED.with(|_| ());
+	})
+	.join()
+	.unwrap();
+	let _ = panic::take_hook();
+
+	let message =
+		|name| format!("thread local `{name}` was accessed during or after its destruction");
+	let seen = SEEN.lock().unwrap();
+	assert_eq!(
+		seen[..5],
+		[
+			message("LOCAL"),
+			message("LAZY"),
+			message("HEAP"),
+			message("REGISTERED"),
+			message("LAZY"),
+		]
+	);
+	// The panic points at the call to `with`.
+	assert_eq!(seen[5], seen[6]);
+}
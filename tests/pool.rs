@@ -0,0 +1,114 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use wintls::{OnThreadExit, PoolConfig};
+
+struct Tracked {
+	id: u32,
+	drops: &'static AtomicU32,
+}
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		self.drops.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+macro_rules! tracked {
+	($created:ident, $dropped:ident) => {
+		static $created: AtomicU32 = AtomicU32::new(0);
+		static $dropped: AtomicU32 = AtomicU32::new(0);
+	};
+}
+tracked!(REUSED_NEW, REUSED_DROPS);
+tracked!(DROPPED_NEW, DROPPED_DROPS);
+tracked!(RESERVOIR_NEW, RESERVOIR_DROPS);
+
+wintls::local_pool! {
+	static REUSED: Tracked = PoolConfig::new(|| Tracked {
+		id: REUSED_NEW.fetch_add(1, Ordering::SeqCst),
+		drops: &REUSED_DROPS,
+	})
+	.max_free(2);
+	static DROPPED: Tracked = PoolConfig::new(|| Tracked {
+		id: DROPPED_NEW.fetch_add(1, Ordering::SeqCst),
+		drops: &DROPPED_DROPS,
+	});
+	static RESERVOIR: Tracked = PoolConfig::new(|| Tracked {
+		id: RESERVOIR_NEW.fetch_add(1, Ordering::SeqCst),
+		drops: &RESERVOIR_DROPS,
+	})
+	.on_thread_exit(OnThreadExit::Reservoir);
+}
+
+#[test]
+fn reuse_within_thread() {
+	std::thread::spawn(|| {
+		let first = REUSED.acquire();
+		let id = first.id;
+		REUSED.release(first);
+		let second = REUSED.acquire();
+		assert_eq!(second.id, id);
+		assert_eq!(REUSED_NEW.load(Ordering::SeqCst), 1);
+
+		// Only two are kept, so the third is dropped.
+		let objects = [second, REUSED.acquire(), REUSED.acquire()];
+		assert_eq!(REUSED_NEW.load(Ordering::SeqCst), 3);
+		for object in objects {
+			REUSED.release(object);
+		}
+		assert_eq!(REUSED_DROPS.load(Ordering::SeqCst), 1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(REUSED_NEW.load(Ordering::SeqCst), 3);
+	assert_eq!(REUSED_DROPS.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn dropped_on_thread_exit() {
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			std::thread::spawn(|| {
+				let objects = [DROPPED.acquire(), DROPPED.acquire()];
+				for object in objects {
+					DROPPED.release(object);
+				}
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(DROPPED_NEW.load(Ordering::SeqCst), 8);
+	assert_eq!(DROPPED_DROPS.load(Ordering::SeqCst), 8);
+	assert_eq!(DROPPED.reservoir_len(), 0);
+}
+
+#[test]
+fn reservoir_handoff() {
+	std::thread::spawn(|| {
+		let objects = [
+			RESERVOIR.acquire(),
+			RESERVOIR.acquire(),
+			RESERVOIR.acquire(),
+		];
+		for object in objects {
+			RESERVOIR.release(object);
+		}
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RESERVOIR.reservoir_len(), 3);
+	assert_eq!(RESERVOIR_DROPS.load(Ordering::SeqCst), 0);
+
+	std::thread::spawn(|| {
+		let mut ids: Vec<u32> = (0..3).map(|_| RESERVOIR.acquire()).map(|o| o.id).collect();
+		ids.sort_unstable();
+		assert_eq!(ids, [0, 1, 2]);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RESERVOIR_NEW.load(Ordering::SeqCst), 3);
+	assert_eq!(RESERVOIR_DROPS.load(Ordering::SeqCst), 3);
+	assert_eq!(RESERVOIR.reservoir_len(), 0);
+}
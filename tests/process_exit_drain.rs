@@ -0,0 +1,83 @@
+#![feature(asm)]
+
+//! Checks that the destructors of threads that are still running when the
+//! process exits are run, if that's enabled. Each test runs in a child
+//! process.
+
+use std::io::Write;
+use std::process::Command;
+use std::sync::mpsc;
+
+const MODE: &str = "WINTLS_EXIT_MODE";
+const WORKERS: u32 = 3;
+
+fn print(message: &str) {
+	let mut stdout = std::io::stdout();
+	let _ = writeln!(stdout, "{message}");
+	let _ = stdout.flush();
+}
+
+fn main() {
+	match std::env::var(MODE) {
+		Ok(mode) => child(&mode),
+		Err(_) => parent(),
+	}
+}
+
+fn child(mode: &str) {
+	match mode {
+		// Safety: the workers are parked before the process exits, and their
+		// destructors only write to stdout.
+		"enabled" => unsafe { wintls::dtor::enable_process_exit_drain() },
+		"disabled" => {}
+		_ => unreachable!("unknown mode {mode}"),
+	}
+	let (registered, wait) = mpsc::channel();
+	for worker in 0..WORKERS {
+		let registered = registered.clone();
+		std::thread::spawn(move || {
+			wintls::dtor::register_dtor_boxed(Box::new(move || {
+				print(&format!("flushed worker {worker}"));
+			}));
+			registered.send(()).unwrap();
+			loop {
+				std::thread::park();
+			}
+		});
+	}
+	// A thread that exits normally removes itself from the table.
+	std::thread::spawn(|| wintls::dtor::register_dtor(|| print("exited")))
+		.join()
+		.unwrap();
+	for _ in 0..WORKERS {
+		wait.recv().unwrap();
+	}
+	wintls::dtor::register_dtor(|| print("main"));
+}
+
+fn parent() {
+	let exe = std::env::current_exe().unwrap();
+	let tests: [(&str, &[&str]); 2] = [
+		(
+			"enabled",
+			&[
+				"exited",
+				"flushed worker 0",
+				"flushed worker 1",
+				"flushed worker 2",
+				"main",
+			],
+		),
+		("disabled", &["exited", "main"]),
+	];
+	for (mode, expected) in tests {
+		let output = Command::new(&exe).env(MODE, mode).output().unwrap();
+		assert!(output.status.success(), "{mode}: {}", output.status);
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		let mut lines: Vec<&str> = stdout.lines().collect();
+		// The workers' destructors are run in whatever order they registered.
+		lines.sort_unstable();
+		assert_eq!(lines, expected, "{mode}");
+		println!("test {mode} ... ok");
+	}
+}
@@ -66,3 +66,107 @@ fn raw_ptr() {
 		assert_eq!(*value, 5);
 	}
 }
+
+wintls::static_thread_local! {
+	static LOCAL: u32 = 0xfeedface;
+}
+
+#[test]
+fn static_thread_local_key() {
+	unsafe {
+		let key = LOCAL.key();
+		assert_eq!(get_static::<u32>(key), LOCAL.get());
+
+		LOCAL.set(5);
+		assert_eq!(get_static::<u32>(key), 5);
+
+		set_static::<u32>(key, 6);
+		assert_eq!(LOCAL.get(), 6);
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Inner {
+	flag: bool,
+	level: u16,
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ctx {
+	id: u64,
+	counter: u32,
+	inner: Inner,
+	padding: [u8; 64],
+}
+
+wintls::static_thread_local! {
+	static CTX: Ctx = Ctx {
+		id: 1,
+		counter: 2,
+		inner: Inner { flag: false, level: 3 },
+		padding: [0; 64],
+	};
+}
+
+#[test]
+fn static_field_projection() {
+	use wintls::raw::static_field;
+	unsafe {
+		let counter = static_field!(CTX.counter);
+		let flag = static_field!(CTX.inner.flag);
+		let level = static_field!(CTX.inner.level);
+
+		// Reads through the projection match the full struct.
+		assert_eq!(get_static::<u64>(static_field!(CTX.id)), CTX.get().id);
+		assert_eq!(get_static::<u32>(counter), CTX.get().counter);
+		assert_eq!(get_static::<bool>(flag), CTX.get().inner.flag);
+		assert_eq!(get_static::<u16>(level), CTX.get().inner.level);
+
+		// Writes through the projection are visible in the struct.
+		set_static::<u32>(counter, 20);
+		set_static::<bool>(flag, true);
+		*static_ptr::<u16>(level) = 30;
+		let ctx = CTX.get();
+		assert_eq!(ctx.counter, 20);
+		assert_eq!(
+			ctx.inner,
+			Inner {
+				flag: true,
+				level: 30
+			}
+		);
+		assert_eq!(ctx.id, 1);
+	}
+}
+
+mod tls {
+	pub mod counters {
+		wintls::raw::init_static!(
+			pub static COUNTER: u32 = 1;
+		);
+	}
+}
+mod reexport {
+	pub use super::tls::counters::COUNTER;
+	pub use wintls_shared_a::RAW;
+}
+
+#[test]
+fn paths() {
+	unsafe {
+		assert_eq!(
+			static_key!(tls::counters::COUNTER),
+			static_key!(reexport::COUNTER)
+		);
+		set_static!(crate::tls::counters::COUNTER, 2_u32);
+		let value: u32 = get_static!(reexport::COUNTER);
+		assert_eq!(value, 2);
+		let ptr: *mut u32 = static_ptr!(self::tls::counters::COUNTER);
+		assert_eq!(*ptr, 2);
+
+		let value: u32 = get_static!(wintls_shared_a::RAW);
+		assert_eq!(value, 5);
+		set_static!(reexport::RAW, 6_u32);
+		let value: u32 = get_static!(wintls_shared_a::RAW);
+		assert_eq!(value, 6);
+	}
+}
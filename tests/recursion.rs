@@ -0,0 +1,55 @@
+#![feature(asm)]
+
+wintls::recursion_guard!(static NESTED);
+wintls::recursion_guard!(static PANICKED);
+wintls::recursion_guard!(static THREADS);
+wintls::recursion_guard!(static BOUNDED);
+
+#[test]
+fn nested_enter() {
+	let outer = NESTED.enter();
+	assert!(outer.is_some());
+	assert!(NESTED.is_entered());
+	assert!(NESTED.enter().is_none());
+	drop(outer);
+	assert!(!NESTED.is_entered());
+	assert!(NESTED.enter().is_some());
+}
+
+#[test]
+fn cleared_after_panic() {
+	let result = std::panic::catch_unwind(|| {
+		let _guard = PANICKED.enter().unwrap();
+		panic!("inside");
+	});
+	assert!(result.is_err());
+	assert_eq!(PANICKED.depth(), 0);
+	assert!(PANICKED.enter().is_some());
+}
+
+#[test]
+fn other_threads_unaffected() {
+	let _guard = THREADS.enter().unwrap();
+	std::thread::spawn(|| {
+		assert!(!THREADS.is_entered());
+		assert!(THREADS.enter().is_some());
+	})
+	.join()
+	.unwrap();
+	assert!(THREADS.enter().is_none());
+}
+
+#[test]
+fn bounded_depth() {
+	fn recurse(calls: &mut u32) {
+		if let Some(_guard) = BOUNDED.enter_max(3) {
+			*calls += 1;
+			assert_eq!(BOUNDED.depth(), *calls);
+			recurse(calls);
+		}
+	}
+	let mut calls = 0;
+	recurse(&mut calls);
+	assert_eq!(calls, 3);
+	assert_eq!(BOUNDED.depth(), 0);
+}
@@ -0,0 +1,51 @@
+#![feature(asm)]
+
+wintls::ref_cell_local! {
+	static STACK: Vec<u32> = Vec::new();
+	/// A counter.
+	pub static COUNT: u64 = 0;
+}
+
+#[test]
+fn nested_shared_borrows() {
+	STACK.borrow_mut().extend([1, 2, 3]);
+	let a = STACK.borrow();
+	let b = STACK.borrow();
+	assert_eq!(*a, *b);
+	assert!(STACK.try_borrow_mut().is_err());
+	drop((a, b));
+	STACK.borrow_mut().clear();
+}
+
+#[test]
+#[should_panic(expected = "thread local `COUNT` is already borrowed")]
+fn double_mut() {
+	let _a = COUNT.borrow_mut();
+	let _b = COUNT.borrow_mut();
+}
+
+#[test]
+fn error_message() {
+	let _a = COUNT.borrow_mut();
+	let error = COUNT.try_borrow().unwrap_err();
+	assert_eq!(
+		error.to_string(),
+		"thread local `COUNT` is already mutably borrowed"
+	);
+}
+
+#[test]
+fn per_thread() {
+	*COUNT.borrow_mut() = 5;
+	let _held = COUNT.borrow_mut();
+	std::thread::spawn(|| {
+		// Each thread has its own borrow flag.
+		*COUNT.borrow_mut() += 1;
+		assert_eq!(*COUNT.borrow(), 1);
+		wintls::dtor::register_dtor(|| drop(STACK.take()));
+		STACK.borrow_mut().push(1);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(*_held, 5);
+}
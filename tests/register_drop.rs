@@ -0,0 +1,42 @@
+#![feature(asm)]
+
+use core::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static DROPS: AtomicU32 = AtomicU32::new(0);
+
+struct Counted(u32);
+impl Drop for Counted {
+	fn drop(&mut self) {
+		DROPS.fetch_add(self.0, Ordering::SeqCst);
+	}
+}
+
+wintls::unsafe_local! {
+	static FIRST: MaybeUninit<Counted> = MaybeUninit::uninit();
+	static SECOND: MaybeUninit<Counted> = MaybeUninit::uninit();
+}
+
+#[test]
+fn drops_in_place_at_thread_exit() {
+	std::thread::spawn(|| unsafe {
+		// The values are only written on this thread, so only it drops them.
+		let first = FIRST.as_ptr().cast::<Counted>();
+		first.write(Counted(1));
+		wintls::dtor::register_drop(first);
+		let second = SECOND.as_ptr().cast::<Counted>();
+		second.write(Counted(10));
+		wintls::dtor::register_drop(second);
+
+		// A leaked allocation works too.
+		wintls::dtor::register_drop(Box::into_raw(Box::new(Counted(100))));
+		assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 111);
+
+	// Other threads never wrote a value, so they have nothing to drop.
+	std::thread::spawn(|| {}).join().unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 111);
+}
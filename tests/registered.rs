@@ -0,0 +1,44 @@
+#![feature(asm)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Barrier};
+
+wintls::registered_local! {
+	static COUNTER: AtomicU32 = AtomicU32::new(0);
+}
+
+fn live() -> (usize, u32) {
+	let (mut count, mut sum) = (0, 0);
+	COUNTER.for_each(|value| {
+		count += 1;
+		sum += value.load(Ordering::Relaxed);
+	});
+	(count, sum)
+}
+
+#[test]
+fn visits_live_threads() {
+	const THREADS: usize = 4;
+	// Wait until every thread has bumped its value, then until the values have
+	// been visited.
+	let bumped = Arc::new(Barrier::new(THREADS + 1));
+	let visited = Arc::new(Barrier::new(THREADS + 1));
+	let threads: Vec<_> = (0..THREADS)
+		.map(|i| {
+			let (bumped, visited) = (bumped.clone(), visited.clone());
+			std::thread::spawn(move || {
+				COUNTER.with(|value| value.fetch_add(i as u32 + 1, Ordering::Relaxed));
+				bumped.wait();
+				visited.wait();
+			})
+		})
+		.collect();
+
+	bumped.wait();
+	assert_eq!(live(), (THREADS, 1 + 2 + 3 + 4));
+	visited.wait();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(live(), (0, 0));
+}
@@ -0,0 +1,91 @@
+#![feature(asm)]
+
+use std::sync::mpsc;
+
+wintls::remote_local! {
+	static VISIBLE: u32 = 0;
+	static ISOLATED: u32 = 0;
+	static EXITED: String = String::new();
+}
+
+#[test]
+fn visible_on_next_access() {
+	let (id_sender, ids) = mpsc::channel();
+	let (go, wait) = mpsc::channel();
+	let worker = std::thread::spawn(move || {
+		assert_eq!(VISIBLE.get(), 0);
+		id_sender.send(VISIBLE.thread_id()).unwrap();
+		wait.recv().unwrap();
+		let seen = VISIBLE.get();
+		// The value stays once it's been taken.
+		assert_eq!(VISIBLE.get(), seen);
+		seen
+	});
+	let id = ids.recv().unwrap();
+	VISIBLE.send_to(id, 1).unwrap();
+	VISIBLE.send_to(id, 2).unwrap();
+	go.send(()).unwrap();
+	// Only the newest value is kept.
+	assert_eq!(worker.join().unwrap(), 2);
+}
+
+#[test]
+fn no_cross_talk() {
+	let workers: Vec<_> = (0..4)
+		.map(|_| {
+			let (id_sender, ids) = mpsc::channel();
+			let (go, wait) = mpsc::channel::<()>();
+			let thread = std::thread::spawn(move || {
+				ISOLATED.with(|_| ());
+				id_sender.send(ISOLATED.thread_id()).unwrap();
+				wait.recv().unwrap();
+				(ISOLATED.thread_id(), ISOLATED.get())
+			});
+			(ids.recv().unwrap(), go, thread)
+		})
+		.collect();
+	for (i, (id, _, _)) in workers.iter().enumerate() {
+		ISOLATED.send_to(*id, i as u32 + 10).unwrap();
+	}
+	for (i, (id, go, thread)) in workers.into_iter().enumerate() {
+		go.send(()).unwrap();
+		assert_eq!(thread.join().unwrap(), (id, i as u32 + 10));
+	}
+	// This thread never got a value.
+	assert_eq!(ISOLATED.get(), 0);
+}
+
+#[test]
+fn nested_access_sees_one_value() {
+	let id = VISIBLE.thread_id();
+	VISIBLE.with(|outer| {
+		VISIBLE.send_to(id, 5).unwrap();
+		// The value is borrowed so the new one isn't taken yet.
+		VISIBLE.with(|inner| assert_eq!(inner, outer));
+	});
+	assert_eq!(VISIBLE.get(), 5);
+}
+
+#[test]
+fn send_to_exited_thread() {
+	let id = std::thread::spawn(|| {
+		EXITED.with(|_| ());
+		EXITED.thread_id()
+	})
+	.join()
+	.unwrap();
+	let error = EXITED.send_to(id, String::from("late")).unwrap_err();
+	assert_eq!(error.thread_id(), id);
+	assert_eq!(error.into_inner(), "late");
+
+	// A thread that hasn't used the local has no mailbox either.
+	let (id_sender, ids) = mpsc::channel();
+	let (go, wait) = mpsc::channel::<()>();
+	let idle = std::thread::spawn(move || {
+		id_sender.send(EXITED.thread_id()).unwrap();
+		wait.recv().unwrap();
+	});
+	assert!(EXITED.send_to(ids.recv().unwrap(), String::new()).is_err());
+	go.send(()).unwrap();
+	idle.join().unwrap();
+}
@@ -0,0 +1,54 @@
+#![feature(asm)]
+
+use std::panic;
+
+wintls::static_thread_local! {
+	static LOG_LEVEL: u8 = 2;
+	static DEPTH: u32 = 0;
+	static UNWIND: u8 = 1;
+}
+
+#[test]
+fn nested() {
+	LOG_LEVEL.set_scoped(3, || {
+		assert_eq!(LOG_LEVEL.get(), 3);
+		LOG_LEVEL.set_scoped(4, || assert_eq!(LOG_LEVEL.get(), 4));
+		assert_eq!(LOG_LEVEL.get(), 3);
+	});
+	assert_eq!(LOG_LEVEL.get(), 2);
+}
+
+#[test]
+fn push() {
+	let outer = DEPTH.push(1);
+	let inner = DEPTH.push(2);
+	assert_eq!(inner.previous(), 1);
+	assert_eq!(DEPTH.get(), 2);
+	drop(inner);
+	assert_eq!(DEPTH.get(), 1);
+	drop(outer);
+	assert_eq!(DEPTH.get(), 0);
+}
+
+#[test]
+fn restore_on_panic() {
+	UNWIND.set_scoped(2, || {
+		let result = panic::catch_unwind(|| UNWIND.set_scoped(3, || panic!("failed")));
+		assert!(result.is_err());
+		assert_eq!(UNWIND.get(), 2);
+	});
+	assert_eq!(UNWIND.get(), 1);
+}
+
+#[test]
+fn other_threads_unaffected() {
+	LOG_LEVEL.set_scoped(5, || {
+		std::thread::spawn(|| {
+			assert_eq!(LOG_LEVEL.get(), 2);
+			LOG_LEVEL.set_scoped(6, || assert_eq!(LOG_LEVEL.get(), 6));
+		})
+		.join()
+		.unwrap();
+		assert_eq!(LOG_LEVEL.get(), 5);
+	});
+}
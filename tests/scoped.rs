@@ -0,0 +1,60 @@
+#![feature(asm)]
+
+use std::panic;
+
+struct Context {
+	depth: u32,
+}
+
+wintls::scoped_thread_local!(static CTX: Context);
+
+fn depth() -> u32 {
+	CTX.with(|ctx| ctx.depth)
+}
+
+#[test]
+fn nesting() {
+	assert!(!CTX.is_set());
+	CTX.set(&Context { depth: 1 }, || {
+		assert_eq!(depth(), 1);
+		CTX.set(&Context { depth: 2 }, || assert_eq!(depth(), 2));
+		assert_eq!(depth(), 1);
+	});
+	assert!(!CTX.is_set());
+}
+
+#[test]
+fn not_set() {
+	assert!(CTX.try_with(|_| {}).is_err());
+	let result = panic::catch_unwind(depth);
+	let message = result.unwrap_err().downcast::<String>().unwrap();
+	assert_eq!(
+		*message,
+		"scoped thread local `CTX` was used outside of `set`"
+	);
+}
+
+#[test]
+fn restored_on_unwind() {
+	CTX.set(&Context { depth: 1 }, || {
+		let result = panic::catch_unwind(|| {
+			CTX.set(&Context { depth: 2 }, || panic!("failed"));
+		});
+		assert!(result.is_err());
+		assert_eq!(depth(), 1);
+	});
+	assert!(!CTX.is_set());
+}
+
+#[test]
+fn independent_threads() {
+	CTX.set(&Context { depth: 1 }, || {
+		std::thread::spawn(|| {
+			assert!(!CTX.is_set());
+			CTX.set(&Context { depth: 5 }, || assert_eq!(depth(), 5));
+		})
+		.join()
+		.unwrap();
+		assert_eq!(depth(), 1);
+	});
+}
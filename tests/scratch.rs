@@ -0,0 +1,104 @@
+#![feature(asm)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU32, Ordering};
+use wintls::scratch::with_scratch;
+
+// Counts the current thread's allocations.
+struct Counting;
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.set(ALLOCATIONS.get() + 1);
+		System.alloc(layout)
+	}
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		if layout.size() == FREED_SIZE {
+			FREED.fetch_add(1, Ordering::SeqCst);
+		}
+		System.dealloc(ptr, layout)
+	}
+}
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+wintls::static_thread_local! {
+	static ALLOCATIONS: u32 = 0;
+}
+
+// An unusual size so that only the scratch buffer is counted.
+const FREED_SIZE: usize = 123_457;
+static FREED: AtomicU32 = AtomicU32::new(0);
+
+#[test]
+fn capacity_persists() {
+	std::thread::spawn(|| {
+		let first = with_scratch(1000, |buffer| {
+			assert!(buffer.capacity() >= 1000);
+			buffer.as_ptr()
+		});
+		// A smaller request reuses the buffer.
+		let second = with_scratch(10, |buffer| {
+			assert!(buffer.capacity() >= 1000);
+			buffer.as_ptr()
+		});
+		assert_eq!(first, second);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn cleared_between_calls() {
+	with_scratch(16, |buffer| buffer.extend_from_slice(b"secret"));
+	with_scratch(16, |buffer| assert!(buffer.is_empty()));
+
+	// Even if the closure panics.
+	let result = std::panic::catch_unwind(|| {
+		with_scratch(16, |buffer| {
+			buffer.extend_from_slice(b"secret");
+			panic!();
+		})
+	});
+	assert!(result.is_err());
+	with_scratch(16, |buffer| assert!(buffer.is_empty()));
+}
+
+#[test]
+fn no_steady_state_allocation() {
+	std::thread::spawn(|| {
+		with_scratch(4096, |buffer| buffer.extend_from_slice(&[1; 4096]));
+		let before = ALLOCATIONS.get();
+		for _ in 0..100 {
+			with_scratch(4096, |buffer| buffer.extend_from_slice(&[1; 4096]));
+		}
+		assert_eq!(ALLOCATIONS.get(), before);
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn nested_calls_get_a_new_buffer() {
+	with_scratch(16, |outer| {
+		outer.push(1);
+		with_scratch(16, |inner| {
+			assert!(inner.is_empty());
+			assert_ne!(inner.as_ptr(), outer.as_ptr());
+			inner.push(2);
+		});
+		assert_eq!(outer, &[1]);
+	});
+}
+
+#[test]
+fn freed_at_thread_exit() {
+	std::thread::spawn(|| {
+		with_scratch(FREED_SIZE, |buffer| {
+			assert_eq!(buffer.capacity(), FREED_SIZE)
+		});
+		assert_eq!(FREED.load(Ordering::SeqCst), 0);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(FREED.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,36 @@
+#![feature(asm)]
+
+use wintls::StaticKey;
+
+wintls::static_thread_local! {
+	static DEFAULT: u32 = 1;
+	#[tls_section = "BBB"]
+	static SECOND: u32 = 3;
+	/// Attributes can come before the section.
+	#[tls_section = "AAA"]
+	#[allow(dead_code)]
+	static FIRST: u32 = 2;
+	#[tls_section = "YYY"]
+	lazy static LAST: u32 = 4;
+}
+
+#[test]
+fn ordered_by_suffix() {
+	assert!(DEFAULT.key() < FIRST.key());
+	assert!(FIRST.key() < SECOND.key());
+	assert!(SECOND.key() < <LAST as StaticKey>::key());
+}
+
+#[test]
+fn values() {
+	assert_eq!(DEFAULT.get(), 1);
+	assert_eq!(FIRST.get(), 2);
+	assert_eq!(SECOND.get(), 3);
+	assert_eq!(LAST.get(), 4);
+	std::thread::spawn(|| {
+		assert_eq!(FIRST.get(), 2);
+		assert_eq!(SECOND.get(), 3);
+	})
+	.join()
+	.unwrap();
+}
@@ -0,0 +1,21 @@
+#![feature(asm)]
+
+use wintls::StaticKey;
+use wintls_shared_a::CONTEXT as A;
+use wintls_shared_b::CONTEXT as B;
+
+#[test]
+fn same_slot() {
+	assert_eq!(<A as StaticKey>::key(), <B as StaticKey>::key());
+	assert_eq!(A.get(), 1);
+	A.set(2);
+	assert_eq!(B.get(), 2);
+	std::thread::spawn(|| {
+		assert_eq!(B.get(), 1);
+		B.set(3);
+		assert_eq!(A.get(), 3);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(A.get(), 2);
+}
@@ -0,0 +1,17 @@
+//! Thread locals defined in another crate, for the tests.
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	#[shared("wintls_test_shared_context")]
+	pub static CONTEXT: u32 = 1;
+}
+
+// Used by the `raw` test to access a static in another crate.
+wintls::raw_internal::init_static!(
+	pub static RAW: u32 = 5;
+);
+
+// Used by the `unsafe_local` test.
+wintls::unsafe_local! {
+	pub static NAMES: Vec<&'static str> = Vec::new();
+}
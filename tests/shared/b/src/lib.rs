@@ -0,0 +1,8 @@
+//! Defines the same shared thread local as `wintls-shared-a`, without
+//! depending on it.
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	#[shared("wintls_test_shared_context")]
+	pub static CONTEXT: u32 = 1;
+}
@@ -0,0 +1,297 @@
+#![feature(asm)]
+
+wintls::static_thread_local! {
+	static BUFFER: [u8; 4096] = [0xfe; 4096];
+}
+
+#[test]
+fn with_large_array() {
+	// The reference points to the same data `get` reads.
+	BUFFER.with(|buffer| {
+		assert!(buffer.iter().all(|&b| b == 0xfe));
+		assert_eq!(buffer[..], BUFFER.get()[..]);
+	});
+
+	BUFFER.set([1; 4096]);
+
+	// Each thread sees its own copy...
+	let threads: Vec<_> = (2..6_u8)
+		.map(|n| {
+			std::thread::spawn(move || {
+				assert!(BUFFER.with(|buffer| buffer.iter().all(|&b| b == 0xfe)));
+				BUFFER.set([n; 4096]);
+				assert!(BUFFER.with(|buffer| buffer.iter().all(|&b| b == n)));
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+
+	// ...and none of them changed this thread's copy.
+	assert!(BUFFER.with(|buffer| buffer.iter().all(|&b| b == 1)));
+}
+
+wintls::static_thread_local! {
+	static SLOTS: [u64; 64] = [0; 64];
+}
+
+#[test]
+fn with_mut_array() {
+	SLOTS.with_mut(|slots| slots[0] = 1);
+
+	std::thread::spawn(|| {
+		SLOTS.with_mut(|slots| {
+			assert_eq!(slots[0], 0);
+			slots[0] = 2;
+			slots[63] = 2;
+		});
+		assert_eq!(SLOTS.get()[0], 2);
+		assert_eq!(SLOTS.get()[63], 2);
+	})
+	.join()
+	.unwrap();
+
+	// The main thread's copy is untouched.
+	let slots = SLOTS.get();
+	assert_eq!(slots[0], 1);
+	assert_eq!(slots[63], 0);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn with_mut_reentrant() {
+	SLOTS.with_mut(|_| SLOTS.with_mut(|_| {}));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn with_mut_unwind_releases_borrow() {
+	let result = std::panic::catch_unwind(|| SLOTS.with_mut(|_| panic!()));
+	assert!(result.is_err());
+	// The flag was reset so the local can be borrowed again.
+	SLOTS.with_mut(|slots| slots[1] = 1);
+}
+
+wintls::static_thread_local! {
+	static COUNTER: u64 = 0;
+}
+
+#[test]
+fn update_counter() {
+	assert_eq!(COUNTER.update(|n| n + 1), 1);
+	assert_eq!(COUNTER.update(|n| n * 10), 10);
+
+	let threads: Vec<_> = (1..=4_u64)
+		.map(|n| {
+			std::thread::spawn(move || {
+				for _ in 0..n * 100 {
+					COUNTER.update(|c| c + 1);
+				}
+				COUNTER.get()
+			})
+		})
+		.collect();
+	for (n, thread) in (1..=4_u64).zip(threads) {
+		assert_eq!(thread.join().unwrap(), n * 100);
+	}
+
+	assert_eq!(COUNTER.get(), 10);
+}
+
+wintls::static_thread_local! {
+	static KEY: [u8; 32] = [0xaa; 32];
+}
+
+#[test]
+fn replace_multi_word() {
+	assert_eq!(KEY.replace([1; 32]), [0xaa; 32]);
+	assert_eq!(KEY.replace([2; 32]), [1; 32]);
+	assert_eq!(KEY.get(), [2; 32]);
+
+	std::thread::spawn(|| {
+		assert_eq!(KEY.replace([3; 32]), [0xaa; 32]);
+		assert_eq!(KEY.get(), [3; 32]);
+	})
+	.join()
+	.unwrap();
+
+	assert_eq!(KEY.get(), [2; 32]);
+}
+
+wintls::static_thread_local! {
+	static ID: Option<core::num::NonZeroU32> = core::num::NonZeroU32::new(7);
+}
+
+#[test]
+fn take_resets_to_default() {
+	let seven = core::num::NonZeroU32::new(7);
+	assert_eq!(ID.take(), seven);
+	assert_eq!(ID.get(), None);
+	assert_eq!(ID.take(), None);
+
+	// Another thread still has the initial value.
+	std::thread::spawn(move || assert_eq!(ID.get(), seven))
+		.join()
+		.unwrap();
+}
+
+wintls::static_thread_local! {
+	static RESULT: i32 = -1;
+}
+
+#[test]
+fn get_ptr_write() {
+	// Pretend this is a C callback writing the result directly into TLS.
+	extern "C" fn callback(out: *mut i32) {
+		unsafe { out.write(42) };
+	}
+	callback(RESULT.get_ptr());
+	assert_eq!(RESULT.get(), 42);
+
+	// Other threads have their own slot.
+	let main_ptr = RESULT.get_ptr() as usize;
+	std::thread::spawn(move || {
+		assert_eq!(RESULT.get(), -1);
+		assert_ne!(RESULT.get_ptr() as usize, main_ptr);
+	})
+	.join()
+	.unwrap();
+	assert_eq!(RESULT.get(), 42);
+}
+
+wintls::static_thread_local! {
+	static SHARED: u32 = 0xfeedface;
+}
+
+#[test]
+fn unsafe_local_view() {
+	let local = SHARED.as_unsafe_local();
+	assert_eq!(local.as_ptr(), SHARED.get_ptr());
+
+	unsafe { local.as_ptr().write(5) };
+	assert_eq!(SHARED.get(), 5);
+
+	SHARED.set(6);
+	assert_eq!(unsafe { *local.as_ref() }, 6);
+
+	std::thread::spawn(|| {
+		let local = SHARED.as_unsafe_local();
+		assert_eq!(unsafe { *local.as_ref() }, 0xfeedface);
+	})
+	.join()
+	.unwrap();
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CloneOnly([u16; 8]);
+
+wintls::static_thread_local! {
+	static CLONE_ONLY: CloneOnly = CloneOnly([1; 8]);
+}
+
+#[test]
+fn clone_only_type() {
+	assert_eq!(CLONE_ONLY.get_cloned(), CloneOnly([1; 8]));
+
+	CLONE_ONLY.set(CloneOnly([2; 8]));
+	assert_eq!(CLONE_ONLY.get_cloned(), CloneOnly([2; 8]));
+	assert_eq!(CLONE_ONLY.replace(CloneOnly([3; 8])), CloneOnly([2; 8]));
+
+	std::thread::spawn(|| assert_eq!(CLONE_ONLY.get_cloned(), CloneOnly([1; 8])))
+		.join()
+		.unwrap();
+	assert_eq!(CLONE_ONLY.get_cloned(), CloneOnly([3; 8]));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Worker {
+	id: u32,
+	jobs: [u16; 4],
+}
+
+wintls::static_thread_local! {
+	static WORKER: Worker = Worker { id: 0, jobs: [0xffff; 4] };
+}
+
+#[test]
+fn reset_to_initializer() {
+	let initial = Worker { id: 0, jobs: [0xffff; 4] };
+
+	WORKER.set(Worker { id: 1, jobs: [1; 4] });
+	let (tx, rx) = std::sync::mpsc::channel();
+	let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+	let other = std::thread::spawn(move || {
+		WORKER.set(Worker { id: 2, jobs: [2; 4] });
+		tx.send(()).unwrap();
+		// Stay alive until the main thread has reset its own copy.
+		done_rx.recv().unwrap();
+		WORKER.get()
+	});
+
+	rx.recv().unwrap();
+	WORKER.reset();
+	assert_eq!(WORKER.get(), initial);
+	done_tx.send(()).unwrap();
+
+	assert_eq!(other.join().unwrap(), Worker { id: 2, jobs: [2; 4] });
+}
+
+wintls::static_thread_local! {
+	static CURRENT: [u32; 256] = [1; 256];
+	static PREVIOUS: [u32; 256] = [0; 256];
+}
+
+#[test]
+fn swap_buffers() {
+	fn flip(frames: u32) {
+		for frame in 0..frames {
+			CURRENT.with_mut(|current| current[0] = frame);
+			wintls::swap(&CURRENT, &PREVIOUS);
+		}
+	}
+
+	let other = std::thread::spawn(|| {
+		flip(3);
+		(CURRENT.get_at(0), PREVIOUS.get_at(0))
+	});
+	flip(2);
+	assert_eq!((CURRENT.get_at(0), PREVIOUS.get_at(0)), (0, 1));
+	assert_eq!(CURRENT.get_at(1), 1);
+	assert_eq!(other.join().unwrap(), (1, 2));
+
+	// Swapping a local with itself does nothing.
+	wintls::swap(&CURRENT, &CURRENT);
+	assert_eq!(CURRENT.get_at(0), 0);
+}
+
+wintls::static_thread_local! {
+	static MANY_A: u8 = 1;
+	static MANY_B: u64 = 2;
+	static MANY_C: [u16; 3] = [3; 3];
+	static MANY_D: Option<u32> = None;
+}
+
+#[test]
+fn get_many() {
+	assert_eq!(wintls::get_many!(MANY_A), (1,));
+	assert_eq!(wintls::get_many!(MANY_A, MANY_B, MANY_C, MANY_D), (1, 2, [3; 3], None));
+
+	MANY_B.set(20);
+	MANY_D.set(Some(40));
+	assert_eq!(wintls::get_many!(MANY_D, MANY_B,), (Some(40), 20));
+
+	std::thread::spawn(|| {
+		assert_eq!(wintls::get_many!(MANY_A, MANY_B, MANY_C, MANY_D), (1, 2, [3; 3], None));
+	})
+	.join()
+	.unwrap();
+}
+
+#[test]
+fn handle_is_zero_sized() {
+	// There are no function pointers to call through.
+	assert_eq!(core::mem::size_of_val(&COUNTER), 0);
+	assert_eq!(core::mem::size_of::<wintls::StaticThreadLocal<u64, COUNTER>>(), 0);
+}
@@ -0,0 +1,49 @@
+#![feature(asm)]
+
+wintls::string_local! {
+	static STRING;
+	static TAKEN;
+	static DESTROYED;
+}
+
+#[test]
+fn per_thread() {
+	STRING.push_str("Hello!").unwrap();
+	let other = std::thread::spawn(|| {
+		STRING.push_str(" World!").unwrap();
+		STRING.to_string()
+	})
+	.join()
+	.unwrap();
+	assert_eq!(other, " World!");
+	assert_eq!(STRING.to_string(), "Hello!");
+	assert_eq!(STRING.with_str(str::len), Ok(6));
+	STRING.clear().unwrap();
+	assert_eq!(STRING.with_str(str::is_empty), Ok(true));
+}
+
+#[test]
+fn take() {
+	TAKEN.push_str("abc").unwrap();
+	assert_eq!(TAKEN.take().unwrap(), "abc");
+	assert_eq!(TAKEN.to_string(), "");
+	assert_eq!(format!("{:?}", TAKEN), r#"StringLocal("")"#);
+}
+
+#[test]
+fn destroyed() {
+	std::thread::spawn(|| {
+		DESTROYED.push_str("gone").unwrap();
+		unsafe { wintls::dtor::drop_locals() };
+		assert!(DESTROYED.push_str("again").is_err());
+		assert!(DESTROYED.with_str(|_| ()).is_err());
+		assert!(DESTROYED.clear().is_err());
+		assert!(DESTROYED.take().is_err());
+		let error = DESTROYED.take().unwrap_err();
+		assert!(error.to_string().contains("`DESTROYED`"));
+		use std::fmt::Write;
+		assert!(write!(String::new(), "{}", DESTROYED).is_err());
+	})
+	.join()
+	.unwrap();
+}
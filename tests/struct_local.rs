@@ -0,0 +1,73 @@
+#![feature(asm)]
+
+wintls::static_thread_local_struct! {
+	/// Hot per-thread context.
+	pub static CTX: Context {
+		pub scheduler: usize = 0,
+		/// Number of ticks on this thread.
+		pub ticks: u64 = 1,
+		flags: u8 = 0b10,
+		#[allow(dead_code)]
+		pub(crate) spare: [u16; 3] = [7; 3],
+	}
+}
+
+wintls::static_thread_local_struct! {
+	static CFG_CTX: CfgContext {
+		a: u32 = 1,
+		#[cfg(any())]
+		b: u32 = 2,
+		#[cfg(all())]
+		c: u32 = 3,
+	}
+}
+
+#[test]
+fn field_accessors() {
+	assert_eq!(CTX.scheduler.get(), 0);
+	assert_eq!(CTX.ticks.get(), 1);
+	assert_eq!(CTX.flags.get(), 0b10);
+	assert_eq!(CTX.spare.get(), [7; 3]);
+
+	CTX.scheduler.set(0x1000);
+	CTX.ticks.set(2);
+	CTX.flags.set(0b11);
+	CTX.spare.set([8; 3]);
+
+	std::thread::spawn(|| {
+		assert_eq!(CTX.scheduler.get(), 0);
+		assert_eq!(CTX.ticks.get(), 1);
+		assert_eq!(CTX.flags.get(), 0b10);
+		assert_eq!(CTX.spare.get(), [7; 3]);
+		CTX.ticks.set(100);
+		assert_eq!(CTX.get().ticks, 100);
+	})
+	.join()
+	.unwrap();
+
+	let context = CTX.get();
+	assert_eq!(context.scheduler, 0x1000);
+	assert_eq!(context.ticks, 2);
+	assert_eq!(context.flags, 0b11);
+	assert_eq!(context.spare, [8; 3]);
+
+	// The fields point into the struct.
+	assert_eq!(
+		CTX.scheduler.get_ptr() as usize % core::mem::align_of::<usize>(),
+		0
+	);
+	CTX.set(Context {
+		ticks: 5,
+		..context
+	});
+	assert_eq!(CTX.ticks.get(), 5);
+}
+
+#[test]
+fn cfg_fields() {
+	assert_eq!(CFG_CTX.a.get(), 1);
+	assert_eq!(CFG_CTX.c.get(), 3);
+	CFG_CTX.c.set(4);
+	let context = CFG_CTX.get();
+	assert_eq!((context.a, context.c), (1, 4));
+}
@@ -0,0 +1,99 @@
+#![feature(asm)]
+
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use wintls::{ThreadBound, WrongThreadDrop};
+
+struct Tracked(&'static AtomicU32);
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn owner_thread() {
+	let mut value = ThreadBound::new(Rc::new(1));
+	assert!(value.is_owner());
+	assert_eq!(value.get().map(|rc| **rc), Some(1));
+	*Rc::get_mut(value.get_mut().unwrap()).unwrap() = 2;
+	assert_eq!(unsafe { **value.get_unchecked() }, 2);
+	assert_eq!(*value.into_inner().ok().unwrap(), 2);
+}
+
+#[test]
+fn other_thread() {
+	let value = Arc::new(ThreadBound::new(Rc::new(1)));
+	let shared = value.clone();
+	std::thread::spawn(move || {
+		assert!(!shared.is_owner());
+		assert!(shared.get().is_none());
+	})
+	.join()
+	.unwrap();
+	assert!(value.get().is_some());
+}
+
+#[test]
+fn dropped_on_owner_thread() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	drop(ThreadBound::new(Tracked(&DROPS)));
+	assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn wrong_thread_leak() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let value = ThreadBound::with_drop(Tracked(&DROPS), WrongThreadDrop::Leak);
+	std::thread::spawn(move || drop(value)).join().unwrap();
+	assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn wrong_thread_panic() {
+	static DROPS: AtomicU32 = AtomicU32::new(0);
+	let value = ThreadBound::new(Tracked(&DROPS));
+	let result = std::thread::spawn(move || drop(value)).join();
+	assert!(result.is_err());
+	assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn into_inner_wrong_thread() {
+	let value = ThreadBound::with_drop(5, WrongThreadDrop::Leak);
+	std::thread::spawn(move || assert!(value.into_inner().is_err()))
+		.join()
+		.unwrap();
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn GetCurrentThreadId() -> u32;
+}
+
+#[test]
+fn thread_with_reused_id() {
+	let value = std::thread::spawn(|| ThreadBound::with_drop(Rc::new(1), WrongThreadDrop::Leak))
+		.join()
+		.unwrap();
+	let mut value = Some(value);
+	// Windows reuses the ids of exited threads quickly.
+	for _ in 0..1000 {
+		value = std::thread::spawn(move || {
+			let value = value.unwrap();
+			if unsafe { GetCurrentThreadId() } != value.thread_id() {
+				return Some(value);
+			}
+			assert!(!value.is_owner());
+			assert!(value.get().is_none());
+			None
+		})
+		.join()
+		.unwrap();
+		if value.is_none() {
+			return;
+		}
+	}
+	panic!("no thread reused the id");
+}
@@ -0,0 +1,66 @@
+#![feature(asm)]
+
+use std::sync::{Arc, Barrier, Mutex};
+
+// The indexes are shared by every test so they're run one at a time.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn unique_among_live_threads() {
+	let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+	const THREADS: usize = 8;
+	let barrier = Arc::new(Barrier::new(THREADS));
+	let threads: Vec<_> = (0..THREADS)
+		.map(|_| {
+			let barrier = barrier.clone();
+			std::thread::spawn(move || {
+				let index = wintls::thread_index();
+				// Keep every thread alive until they all have an index.
+				barrier.wait();
+				assert_eq!(wintls::thread_index(), index);
+				index
+			})
+		})
+		.collect();
+	let mut indexes: Vec<u32> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+	indexes.sort_unstable();
+	indexes.dedup();
+	assert_eq!(indexes.len(), THREADS);
+	assert!(wintls::max_threads_seen() >= THREADS as u32);
+	assert!(indexes
+		.iter()
+		.all(|&index| index < wintls::max_threads_seen()));
+}
+
+#[test]
+fn reused_after_exit() {
+	let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+	let first = std::thread::spawn(wintls::thread_index).join().unwrap();
+	let max = wintls::max_threads_seen();
+	let second = std::thread::spawn(wintls::thread_index).join().unwrap();
+	assert_eq!(first, second);
+	assert_eq!(wintls::max_threads_seen(), max);
+}
+
+// Holding the lock needs the `test-hooks` feature.
+#[cfg(feature = "test-hooks")]
+#[test]
+fn fast_path_does_not_lock() {
+	let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+	let (to_thread, from_test) = std::sync::mpsc::channel();
+	let (to_test, from_thread) = std::sync::mpsc::channel();
+	let thread = std::thread::spawn(move || {
+		let index = wintls::thread_index();
+		to_test.send(index).unwrap();
+		from_test.recv().unwrap();
+		to_test.send(wintls::thread_index()).unwrap();
+	});
+	let index = from_thread.recv().unwrap();
+	let guard = wintls::lock_thread_indexes();
+	to_thread.send(()).unwrap();
+	// If getting the index took the lock this would time out.
+	let again = from_thread.recv_timeout(std::time::Duration::from_secs(10));
+	drop(guard);
+	assert_eq!(again, Ok(index));
+	thread.join().unwrap();
+}
@@ -0,0 +1,71 @@
+#![feature(asm)]
+
+//! Checks which binaries get a TLS directory, by reading their PE headers.
+
+mod common;
+
+use core::ffi::c_void;
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn GetModuleHandleW(name: *const u16) -> *mut c_void;
+}
+
+wintls::static_thread_local! {
+	static COUNT: u32 = 0;
+}
+
+const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+
+fn read_u16(image: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(image[offset..offset + 2].try_into().unwrap())
+}
+fn read_u32(image: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(image[offset..offset + 4].try_into().unwrap())
+}
+
+/// Returns the RVA and size of the TLS directory from an image's headers.
+fn tls_directory(headers: &[u8]) -> (u32, u32) {
+	assert_eq!(&headers[..2], b"MZ");
+	let nt = read_u32(headers, 0x3c) as usize;
+	assert_eq!(&headers[nt..nt + 4], b"PE\0\0");
+	// The optional header follows the signature and the file header.
+	let optional = nt + 4 + 20;
+	let directories = match read_u16(headers, optional) {
+		0x10b => optional + 96,
+		0x20b => optional + 112,
+		magic => panic!("unknown optional header magic {magic:#x}"),
+	};
+	let entry = directories + IMAGE_DIRECTORY_ENTRY_TLS * 8;
+	(read_u32(headers, entry), read_u32(headers, entry + 4))
+}
+
+#[test]
+fn included_with_locals() {
+	COUNT.set(1);
+	unsafe {
+		let base = GetModuleHandleW(core::ptr::null()).cast::<u8>();
+		let headers = core::slice::from_raw_parts(base, 4096);
+		let (rva, size) = tls_directory(headers);
+		assert!(rva != 0 && size != 0);
+
+		// `AddressOfCallBacks` comes after the start and end of the template
+		// and `AddressOfIndex`. It's been relocated, like the callbacks.
+		let directory = base.add(rva as usize).cast::<usize>();
+		let mut callback = *directory.add(3) as *const usize;
+		let mut found = false;
+		while *callback != 0 {
+			found |= *callback == wintls::dtor::TLS_CALLBACK as usize;
+			callback = callback.add(1);
+		}
+		assert!(found, "the destructor callback isn't in the TLS directory");
+	}
+}
+
+#[test]
+fn not_included_without_locals() {
+	let exe = common::build("no_tls").join("wintls-no-tls.exe");
+	let image = std::fs::read(&exe).unwrap();
+	assert_eq!(tls_directory(&image), (0, 0));
+	assert!(std::process::Command::new(exe).status().unwrap().success());
+}
@@ -0,0 +1,7 @@
+//! Checks the errors given by `#[wintls::thread_local]` and where they point.
+
+#[test]
+fn ui() {
+	let tests = trybuild::TestCases::new();
+	tests.compile_fail("tests/ui/*.rs");
+}
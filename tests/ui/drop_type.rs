@@ -0,0 +1,4 @@
+#[wintls::thread_local]
+static NAME: String = String::new();
+
+fn main() {}
@@ -0,0 +1,71 @@
+#![feature(asm)]
+
+use core::mem::MaybeUninit;
+
+wintls::static_thread_local! {
+	static SCRATCH: MaybeUninit<[u8; 4096]> = MaybeUninit::uninit();
+	static PAIR: MaybeUninit<(u32, u64)> = MaybeUninit::uninit();
+}
+
+#[test]
+fn write_then_read() {
+	let threads: Vec<_> = (0..4_u8)
+		.map(|i| {
+			std::thread::spawn(move || {
+				SCRATCH.write([i; 4096]);
+				PAIR.write((i.into(), u64::from(i) << 32));
+				unsafe {
+					SCRATCH.assume_init_with_mut(|scratch| scratch[4095] = 0xff);
+					SCRATCH.assume_init_with(|scratch| {
+						assert!(scratch[..4095].iter().all(|&b| b == i));
+						assert_eq!(scratch[4095], 0xff);
+					});
+					assert_eq!(PAIR.assume_init_read(), (i.into(), u64::from(i) << 32));
+				}
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+}
+
+#[test]
+fn pointer() {
+	unsafe {
+		PAIR.as_mut_ptr().write((1, 2));
+		assert_eq!(PAIR.assume_init_read(), (1, 2));
+	}
+}
+
+static DROPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+struct Tracked(String);
+impl Drop for Tracked {
+	fn drop(&mut self) {
+		DROPS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+	}
+}
+
+wintls::unsafe_local! {
+	static TRACKED: MaybeUninit<Tracked> = MaybeUninit::uninit();
+}
+
+#[test]
+fn unsafe_local_init_then_drop() {
+	std::thread::spawn(|| unsafe {
+		let tracked = TRACKED.init(Tracked(String::from("a")));
+		tracked.0.push('b');
+		assert_eq!(TRACKED.assume_init_ref().0, "ab");
+		TRACKED.assume_init_mut().0.push('c');
+		assert_eq!((*TRACKED.as_uninit_ptr()).0, "abc");
+
+		TRACKED.assume_init_drop();
+		assert_eq!(DROPS.load(std::sync::atomic::Ordering::SeqCst), 1);
+		TRACKED.init(Tracked(String::new()));
+		wintls::dtor::register_dtor(|| TRACKED.assume_init_drop());
+	})
+	.join()
+	.unwrap();
+	assert_eq!(DROPS.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
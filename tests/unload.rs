@@ -0,0 +1,80 @@
+#![feature(asm)]
+
+//! Checks that destructors a DLL registers with the EXE are skipped once the
+//! DLL has been unloaded. The DLL is built from `unload/`.
+
+mod common;
+
+use std::ffi::{c_char, c_void, CStr};
+use std::os::windows::ffi::OsStrExt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+
+type Register = unsafe extern "C" fn(f: unsafe extern "C" fn(*mut u8), data: *mut u8);
+type PluginRegister = unsafe extern "C" fn(register: Register, count: *const AtomicU32);
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn LoadLibraryW(name: *const u16) -> *mut c_void;
+	fn GetModuleHandleW(name: *const u16) -> *mut c_void;
+	fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+	fn FreeLibrary(module: *mut c_void) -> i32;
+}
+
+unsafe extern "C" fn register(f: unsafe extern "C" fn(*mut u8), data: *mut u8) {
+	wintls::dtor::register_dtor_with(data, f);
+}
+
+unsafe fn load(path: &[u16], name: &CStr) -> (*mut c_void, *mut c_void) {
+	let dll = LoadLibraryW(path.as_ptr());
+	assert!(!dll.is_null(), "plugin.dll wasn't found");
+	let f = GetProcAddress(dll, name.as_ptr());
+	assert!(!f.is_null());
+	(dll, f)
+}
+
+#[test]
+fn unloaded() {
+	static PLUGIN_DROPS: AtomicU32 = AtomicU32::new(0);
+	static EXE_DROPS: AtomicU32 = AtomicU32::new(0);
+	let path = common::build("unload").join("plugin.dll");
+	let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+	unsafe {
+		let (dll, plugin_register) = load(&path, c"plugin_register");
+		let plugin_register: PluginRegister = std::mem::transmute(plugin_register);
+
+		// Two threads register a destructor of their own and one in the DLL.
+		let (registered, wait_registered) = mpsc::channel();
+		let mut unloaded = Vec::new();
+		let workers: Vec<_> = (0..2)
+			.map(|_| {
+				let registered = registered.clone();
+				let (to_worker, wait_unloaded) = mpsc::channel::<()>();
+				unloaded.push(to_worker);
+				std::thread::spawn(move || {
+					wintls::dtor::register_dtor(|| {
+						EXE_DROPS.fetch_add(1, Ordering::SeqCst);
+					});
+					plugin_register(register, &PLUGIN_DROPS);
+					registered.send(()).unwrap();
+					wait_unloaded.recv().unwrap();
+				})
+			})
+			.collect();
+		for _ in &workers {
+			wait_registered.recv().unwrap();
+		}
+
+		assert_ne!(FreeLibrary(dll), 0);
+		assert!(GetModuleHandleW(path.as_ptr()).is_null(), "the DLL is still loaded");
+		for to_worker in unloaded {
+			to_worker.send(()).unwrap();
+		}
+		for worker in workers {
+			worker.join().unwrap();
+		}
+	}
+	// Only the EXE's destructors were run.
+	assert_eq!(EXE_DROPS.load(Ordering::SeqCst), 2);
+	assert_eq!(PLUGIN_DROPS.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,36 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+pub type Register = unsafe extern "C" fn(f: unsafe extern "C" fn(*mut u8), data: *mut u8);
+
+unsafe extern "C" fn dtor(count: *mut u8) {
+	(*count.cast::<AtomicU32>()).fetch_add(1, Ordering::SeqCst);
+}
+
+/// Registers a destructor on the current thread with `register`. The
+/// destructor increments `count`.
+///
+/// # Safety
+///
+/// `register` must call the destructor with its data when the thread exits,
+/// and `count` must still be valid then.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_register(register: Register, count: *const AtomicU32) {
+	register(dtor, count as *mut u8);
+}
+
+static PINNED_DROPS: AtomicU32 = AtomicU32::new(0);
+
+/// Registers a destructor on the current thread with this DLL's copy of
+/// `wintls`, which pins the DLL.
+#[no_mangle]
+pub extern "C" fn plugin_register_pinned() {
+	wintls::dtor::register_dtor_pinned(|| {
+		PINNED_DROPS.fetch_add(1, Ordering::SeqCst);
+	});
+}
+
+/// Returns the number of times the pinned destructors have run.
+#[no_mangle]
+pub extern "C" fn plugin_pinned_drops() -> u32 {
+	PINNED_DROPS.load(Ordering::SeqCst)
+}
@@ -0,0 +1,211 @@
+#![feature(asm)]
+
+mod tls {
+	pub mod nested {
+		wintls::unsafe_local! {
+			pub(crate) static DEPTH: u32 = 0;
+		}
+	}
+}
+
+#[test]
+fn pub_from_another_crate() {
+	unsafe {
+		assert!(wintls_shared_a::NAMES.as_ref().is_empty());
+		wintls_shared_a::NAMES.as_ref_mut().push("test");
+		assert_eq!(*wintls_shared_a::NAMES.as_ref(), ["test"]);
+	}
+}
+
+#[test]
+fn pub_crate() {
+	unsafe {
+		*tls::nested::DEPTH.as_ref_mut() += 1;
+		assert_eq!(*tls::nested::DEPTH.as_ref(), 1);
+	}
+}
+
+wintls::unsafe_local! {
+	static NUMBER: u64 = 1;
+	/// A local that needs dropping.
+	static TEXT: String = String::new();
+	pub static PAIR: (u8, bool) = (2, true);
+}
+
+#[test]
+fn batch() {
+	fn check(number: u64, text: &str, pair: (u8, bool)) {
+		unsafe {
+			assert_eq!(*NUMBER.as_ref(), number);
+			assert_eq!(TEXT.as_ref(), text);
+			assert_eq!(*PAIR.as_ref(), pair);
+		}
+	}
+
+	// Each handle refers to its own static.
+	assert_ne!(NUMBER.as_ptr() as usize, TEXT.as_ptr() as usize);
+	assert_ne!(TEXT.as_ptr() as usize, PAIR.as_ptr() as usize);
+
+	check(1, "", (2, true));
+	unsafe {
+		*NUMBER.as_ref_mut() = 10;
+		TEXT.as_ref_mut().push_str("main");
+		PAIR.as_ref_mut().1 = false;
+	}
+	std::thread::spawn(|| {
+		check(1, "", (2, true));
+		unsafe {
+			TEXT.as_ref_mut().push_str("spawned");
+			*PAIR.as_ref_mut() = (3, false);
+		}
+		check(1, "spawned", (3, false));
+		unsafe { TEXT.drop_value() };
+	})
+	.join()
+	.unwrap();
+	check(10, "main", (2, false));
+}
+
+wintls::unsafe_local! {
+	static INPUT: Vec<u32> = Vec::new();
+	static OUTPUT: Vec<u32> = Vec::new();
+}
+
+#[test]
+fn nested_with() {
+	unsafe {
+		INPUT.with_mut(|input| input.extend([1, 2, 3]));
+		INPUT.with(|input| {
+			OUTPUT.with_mut(|output| output.extend(input.iter().map(|n| n * 2)));
+		});
+		let sum = OUTPUT.with(|output| INPUT.with(|input| output.iter().chain(input).sum::<u32>()));
+		assert_eq!(sum, 18);
+		INPUT.drop_value();
+		OUTPUT.drop_value();
+	}
+}
+
+wintls::unsafe_local! {
+	static LIST: Vec<u32> = Vec::new();
+}
+
+#[test]
+fn replace_and_take() {
+	unsafe {
+		LIST.with_mut(|list| list.extend([1, 2]));
+		let old = LIST.replace(vec![3, 4, 5]);
+		assert_eq!(old, [1, 2]);
+		LIST.with(|list| assert_eq!(list, &[3, 4, 5]));
+
+		let old = LIST.take();
+		assert_eq!(old, [3, 4, 5]);
+		LIST.with(|list| assert!(list.is_empty()));
+	}
+}
+
+wintls::unsafe_local! {
+	static GREETING: String = String::new();
+}
+
+#[test]
+fn drop_then_write() {
+	unsafe {
+		GREETING.set(String::from("hello"));
+		GREETING.with(|greeting| assert_eq!(greeting, "hello"));
+
+		GREETING.drop_value();
+		GREETING.write(String::from("again"));
+		GREETING.with_mut(|greeting| greeting.push('!'));
+		GREETING.with(|greeting| assert_eq!(greeting, "again!"));
+		GREETING.drop_value();
+	}
+}
+
+wintls::unsafe_local! {
+	static TICKS: u32 = 0;
+}
+
+#[test]
+fn local_ptr() {
+	let ptr = TICKS.local_ptr();
+	unsafe {
+		ptr.write(ptr.read() + 1);
+		assert_eq!(ptr.read(), 1);
+	}
+	assert_eq!(ptr.as_ptr(), TICKS.as_ptr());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn local_ptr_on_another_thread() {
+	let ptr = TICKS.local_ptr();
+	let error = std::thread::spawn(move || unsafe { ptr.read() })
+		.join()
+		.unwrap_err();
+	let message = error.downcast_ref::<String>().unwrap();
+	assert!(message.starts_with("a pointer to a thread local created on thread"));
+}
+
+static DROPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+struct CountDrops(Vec<u8>);
+impl Drop for CountDrops {
+	fn drop(&mut self) {
+		DROPS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+	}
+}
+
+wintls::unsafe_local! {
+	static COUNTED: CountDrops = CountDrops(Vec::new());
+}
+
+#[test]
+fn register_drop_once_per_thread() {
+	let threads: Vec<_> = (0..4)
+		.map(|i| {
+			std::thread::spawn(move || unsafe {
+				for _ in 0..3 {
+					COUNTED.register_drop_for_current_thread();
+					COUNTED.with_mut(|counted| counted.0.push(i));
+				}
+			})
+		})
+		.collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+	assert_eq!(DROPS.load(std::sync::atomic::Ordering::SeqCst), 4);
+}
+
+wintls::unsafe_local! {
+	static LABEL: String = String::new();
+}
+
+#[test]
+fn formatting() {
+	unsafe { LABEL.with_mut(|label| label.push_str("main")) };
+	assert_eq!(format!("{:?}", LABEL), r#"UnsafeLocal("main")"#);
+	assert_eq!(format!("{}", LABEL), "main");
+	std::thread::spawn(|| assert_eq!(format!("{:?}", LABEL), r#"UnsafeLocal("")"#))
+		.join()
+		.unwrap();
+}
+
+wintls::unsafe_local! {
+	static POSITION: (i32, i32) = (0, 0);
+}
+
+#[test]
+fn get_set_with_pointers() {
+	assert_eq!(POSITION.get_copy(), (0, 0));
+	POSITION.set_copy((1, 2));
+	unsafe { (*POSITION.as_ptr()).0 += 10 };
+	assert_eq!(POSITION.get_copy(), (11, 2));
+	unsafe { POSITION.with_mut(|position| position.1 = 5) };
+	assert_eq!(POSITION.get_copy(), (11, 5));
+	POSITION.set_copy((0, 1));
+	assert_eq!(unsafe { *POSITION.as_ref() }, (0, 1));
+	std::thread::spawn(|| assert_eq!(POSITION.get_copy(), (0, 0)))
+		.join()
+		.unwrap();
+}
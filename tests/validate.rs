@@ -0,0 +1,74 @@
+#![feature(asm)]
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+enum State {
+	Idle,
+	Busy,
+}
+impl State {
+	fn is_valid(bytes: &[u8]) -> bool {
+		bytes[0] <= State::Busy as u8
+	}
+}
+
+wintls::static_thread_local! {
+	static STATE: State = State::Idle;
+	validate = State::is_valid;
+	static OTHER: State = State::Busy;
+	validate = |bytes| bytes[0] <= 1;
+	static STATES: [State; 2] = [State::Idle; 2];
+	validate = |bytes| bytes.iter().all(|&b| b <= 1);
+	// `None` is stored as 2.
+	static MAYBE: Option<State> = None;
+	validate = |bytes| bytes[0] <= 2;
+}
+
+#[test]
+fn valid() {
+	assert_eq!(STATE.get(), State::Idle);
+	STATE.set(State::Busy);
+	assert_eq!(STATE.get(), State::Busy);
+	assert_eq!(OTHER.replace(State::Idle), State::Busy);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "thread local `STATE` has an invalid value")]
+fn invalid_discriminant() {
+	// Scribble over the value.
+	unsafe { STATE.get_ptr().cast::<u8>().write(7) };
+	STATE.get();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "thread local `OTHER` has an invalid value")]
+fn invalid_discriminant_with() {
+	unsafe { OTHER.get_ptr().cast::<u8>().write(2) };
+	OTHER.with(|_| ());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "thread local `STATES` has an invalid value")]
+fn invalid_element() {
+	unsafe { STATES.get_ptr().cast::<u8>().add(1).write(3) };
+	STATES.get_at(0);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "thread local `STATES` has an invalid value")]
+fn invalid_element_with_slice() {
+	unsafe { STATES.get_ptr().cast::<u8>().write(3) };
+	STATES.with_slice(|_| ());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "thread local `MAYBE` has an invalid value")]
+fn invalid_option() {
+	unsafe { MAYBE.get_ptr().cast::<u8>().write(3) };
+	MAYBE.get_or_set_with(|| State::Idle);
+}